@@ -0,0 +1,171 @@
+//! Command line parsing. Options follow GNU ld's spelling so weld can be
+//! dropped into existing build scripts.
+
+pub struct Args {
+    pub inputs: Vec<String>,
+    pub libraries: Vec<String>, // -l names, looked for once all -L are known
+    pub options: weld_core::LinkOptions,
+    pub map_file: Option<String>,
+    pub cref: bool,
+    pub trace: bool,
+    pub threads: usize, // 0 lets rayon decide
+    pub reproducible: bool,
+    pub check_determinism: Option<u64>, // Shuffle seed
+    pub verbose: bool,
+}
+
+pub fn parse(args: &[String]) -> Result<Args, String> {
+    let mut result = Args {
+        inputs: Vec::new(),
+        libraries: Vec::new(),
+        options: weld_core::LinkOptions::default(),
+        map_file: None,
+        cref: false,
+        trace: false,
+        threads: 0,
+        reproducible: false,
+        check_determinism: None,
+        verbose: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if let Some(path) = arg.strip_prefix("-Map=").or(arg.strip_prefix("--Map=")) {
+            result.map_file = Some(path.to_string());
+        } else if arg == "-Map" || arg == "--Map" {
+            let path = args.next().ok_or("-Map requires a file name")?;
+            result.map_file = Some(path.clone());
+        } else if arg == "--cref" {
+            result.cref = true;
+        } else if arg == "-t" || arg == "--trace" {
+            result.trace = true;
+        } else if let Some(name) = arg.strip_prefix("--trace-symbol=") {
+            result.options.trace_symbols.push(name.to_string());
+        } else if let Some(name) = arg.strip_prefix("-y").filter(|name| !name.is_empty()) {
+            result.options.trace_symbols.push(name.to_string());
+        } else if arg == "-y" || arg == "--trace-symbol" {
+            let name = args.next().ok_or(format!("{arg} requires a symbol name"))?;
+            result.options.trace_symbols.push(name.clone());
+        } else if arg == "--build-id" {
+            result.options.build_id = Some(weld_core::build_id::BuildId::Sha1);
+        } else if let Some(style) = arg.strip_prefix("--build-id=") {
+            result.options.build_id = match style {
+                "none" => None,
+                _ => Some(style.parse()?),
+            };
+        } else if let Some(address) = arg.strip_prefix("--image-base=") {
+            result.options.layout.image_base = Some(parse_address(address)?);
+        } else if arg == "--image-base" {
+            let address = args.next().ok_or("--image-base requires an address")?;
+            result.options.layout.image_base = Some(parse_address(address)?);
+        } else if let Some(spec) = arg.strip_prefix("--section-start=") {
+            let (name, address) = spec
+                .split_once('=')
+                .ok_or(format!("invalid argument to --section-start: '{spec}'"))?;
+            result
+                .options
+                .layout
+                .section_starts
+                .insert(name.to_string(), parse_address(address)?);
+        } else if let Some((name, address)) = section_start_shorthand(arg) {
+            let address = match address {
+                Some(address) => address,
+                None => args.next().ok_or(format!("{arg} requires an address"))?,
+            };
+            result
+                .options
+                .layout
+                .section_starts
+                .insert(name.to_string(), parse_address(address)?);
+        } else if let Some(count) = arg.strip_prefix("--threads=") {
+            result.threads = count
+                .parse()
+                .ok()
+                .filter(|&count| count > 0)
+                .ok_or(format!("invalid thread count '{count}'"))?;
+        } else if arg == "--no-threads" {
+            result.threads = 1;
+        } else if arg == "--incremental" {
+            result.options.incremental = true;
+        } else if arg == "--reproducible" {
+            result.reproducible = true;
+        } else if arg == "--check-determinism" {
+            // Each run tries a different shuffle unless given a seed
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+            result.check_determinism = Some(now.map(|t| t.as_nanos() as u64).unwrap_or_default());
+        } else if let Some(seed) = arg.strip_prefix("--check-determinism=") {
+            let seed = seed.parse().map_err(|_| format!("invalid seed '{seed}'"))?;
+            result.check_determinism = Some(seed);
+        } else if let Some(symbol) = arg.strip_prefix("--entry=") {
+            result.options.entry = symbol.to_string();
+        } else if arg == "-e" || arg == "--entry" {
+            let symbol = args.next().ok_or(format!("{arg} requires a symbol name"))?;
+            result.options.entry = symbol.clone();
+        } else if let Some(symbol) = arg.strip_prefix("-e") {
+            result.options.entry = symbol.to_string();
+        } else if let Some(dir) = arg.strip_prefix("--library-path=") {
+            result.options.search_paths.push(dir.into());
+        } else if arg == "-L" || arg == "--library-path" {
+            let dir = args.next().ok_or(format!("{arg} requires a directory"))?;
+            result.options.search_paths.push(dir.into());
+        } else if let Some(dir) = arg.strip_prefix("-L") {
+            result.options.search_paths.push(dir.into());
+        } else if let Some(name) = arg.strip_prefix("--library=") {
+            result.libraries.push(name.to_string());
+        } else if arg == "-l" || arg == "--library" {
+            let name = args
+                .next()
+                .ok_or(format!("{arg} requires a library name"))?;
+            result.libraries.push(name.clone());
+        } else if let Some(name) = arg.strip_prefix("-l") {
+            result.libraries.push(name.to_string());
+        } else if arg == "--gc-sections" {
+            result.options.gc_sections = true;
+        } else if arg == "--no-gc-sections" {
+            result.options.gc_sections = false;
+        } else if let Some(mode) = arg.strip_prefix("--icf=") {
+            result.options.icf = match mode {
+                "all" => true,
+                "none" => false,
+                _ => return Err(format!("invalid --icf mode '{mode}'")),
+            };
+        } else if arg == "--fatal-warnings" {
+            result.options.warnings = weld_core::Warnings::Fatal;
+        } else if arg == "--no-fatal-warnings" {
+            result.options.warnings = weld_core::Warnings::Report;
+        } else if arg == "-w" || arg == "--no-warnings" {
+            result.options.warnings = weld_core::Warnings::Ignore;
+        } else if arg == "--verbose" {
+            result.verbose = true;
+        } else if arg.starts_with('-') {
+            return Err(format!("unrecognized option '{arg}'"));
+        } else {
+            result.inputs.push(arg.clone());
+        }
+    }
+
+    if result.reproducible && result.options.build_id == Some(weld_core::build_id::BuildId::Uuid) {
+        return Err("--build-id=uuid can't be used with --reproducible".to_string());
+    }
+
+    Ok(result)
+}
+
+// -Ttext=<addr> or -Ttext <addr>, and likewise for .data and .bss
+fn section_start_shorthand(arg: &str) -> Option<(&'static str, Option<&str>)> {
+    [("-Ttext", ".text"), ("-Tdata", ".data"), ("-Tbss", ".bss")]
+        .into_iter()
+        .find_map(|(option, section)| match arg.strip_prefix(option)? {
+            "" => Some((section, None)),
+            rest => Some((section, Some(rest.strip_prefix('=')?))),
+        })
+}
+
+// Like GNU ld, addresses are hexadecimal with or without a leading 0x
+fn parse_address(address: &str) -> Result<u64, String> {
+    let digits = address
+        .strip_prefix("0x")
+        .or(address.strip_prefix("0X"))
+        .unwrap_or(address);
+    u64::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{address}'"))
+}
@@ -1,45 +1,323 @@
+use memmap2::Mmap;
+use rayon::prelude::*;
 use std::env;
 use std::fs;
 use std::io::Write;
+use std::process::ExitCode;
+use std::time::Instant;
+use weld_core::incremental::{self, Relink};
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
+mod args;
 
-    if args.len() < 2 {
-        println!("Usage: weld <files>");
-        return;
+const OUTPUT: &str = "./weld.out";
+// Where --incremental keeps what it needs to patch OUTPUT next time
+const STATE: &str = "./weld.out.state";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let args = match args::parse(&args) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("weld: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    init_logging(args.verbose);
+
+    if args.inputs.is_empty() {
+        println!("Usage: weld [options] <files>");
+        println!("  --build-id[=sha1|md5|uuid|fast|0x<hex>]");
+        println!("  -Map=<file>");
+        println!("  --cref");
+        println!("  -t, --trace");
+        println!("  -y <symbol>, --trace-symbol=<symbol>");
+        println!("  --image-base=<address>");
+        println!("  -Ttext=<address>, -Tdata=<address>, -Tbss=<address>");
+        println!("  --section-start=<section>=<address>");
+        println!("  --threads=<count>, --no-threads");
+        println!("  --incremental");
+        println!("  --reproducible");
+        println!("  --check-determinism[=<seed>]");
+        println!("  -e <symbol>, --entry=<symbol>");
+        println!("  -L <dir>, --library-path=<dir>");
+        println!("  -l <name>, --library=<name>");
+        println!("  --gc-sections, --no-gc-sections");
+        println!("  --icf=all|none");
+        println!("  --fatal-warnings, --no-fatal-warnings, -w, --no-warnings");
+        println!("  --verbose");
+        return ExitCode::SUCCESS;
+    }
+
+    if args.threads > 0 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(args.threads)
+            .build_global()
+            .expect("Thread pool already initialized");
     }
 
+    // -l libraries are found on the search path, wherever -L came on the
+    // command line, and searched after the other inputs
+    let mut paths = args.inputs.clone();
+    for name in &args.libraries {
+        match weld_core::archive::find_library(name, &args.options.search_paths) {
+            Ok(path) => paths.push(path.to_string_lossy().into_owned()),
+            Err(err) => {
+                eprintln!("weld: {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // Under --reproducible, inputs are known by paths that don't depend on
+    // where the build tree is
+    let cwd = env::current_dir().unwrap_or_default();
+    let names: Vec<String> = paths
+        .iter()
+        .map(|path| match args.reproducible {
+            true => weld_core::reproducible::strip_path(path, &cwd),
+            false => path.clone(),
+        })
+        .collect();
+
+    let started = Instant::now();
+    // Files are mapped and parsed in parallel, then reported in command line
+    // order. The parsed inputs borrow from the mappings. Archives are
+    // searched once all the objects are in.
+    let mapped: Vec<Result<Mmap, String>> = paths.par_iter().map(|path| map(path)).collect();
+    let parsed: Vec<Result<Option<elf::logical::Relocatable>, String>> = names
+        .par_iter()
+        .zip(&mapped)
+        .map(|(name, bytes)| {
+            let bytes = bytes.as_ref().map_err(Clone::clone)?;
+            if elf_parser::archive::is_archive(bytes) {
+                return Ok(None);
+            }
+            elf_parser::parse(name, bytes)
+                .map(Some)
+                .map_err(|err| err.to_string())
+        })
+        .collect();
+
     let mut relocatables = Vec::new();
-    for i in 1..args.len() {
-        let path = args.get(i).unwrap();
-        match fs::read(path) {
-            Ok(bytes) => {
-                println!("\n=============================================================");
-                let reloc = elf_parser::parse(path, &bytes);
-                println!("{reloc:?}");
+    let mut sources = Vec::new(); // The file each relocatable came from
+    let mut archives = Vec::new();
+    let mut archive_sources = Vec::new();
+    for (file, (path, reloc)) in paths.iter().zip(parsed).enumerate() {
+        if args.trace {
+            println!("{path}");
+        }
+        match reloc {
+            Ok(Some(reloc)) => {
+                log::debug!(
+                    target: "parse",
+                    "{path}: {} sections, {} symbols, {} relocations",
+                    reloc.sections.len(),
+                    reloc.symbols.len(),
+                    reloc.relocations.len()
+                );
+                log::trace!(target: "parse", "{reloc:?}");
                 relocatables.push(reloc);
+                sources.push(file);
+            }
+            Ok(None) => {
+                if let Ok(bytes) = &mapped[file] {
+                    archives.push((names[file].as_str(), &bytes[..]));
+                    archive_sources.push(file);
+                }
+            }
+            Err(err) => {
+                eprintln!("{path} : {err}");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    match weld_core::archive::members(&relocatables, &archives) {
+        Ok(members) => {
+            for (archive, member) in members {
+                if args.trace {
+                    println!("{}", member.path);
+                }
+                relocatables.push(member);
+                sources.push(archive_sources[archive]);
+            }
+        }
+        Err(errs) => {
+            for err in errs {
+                eprintln!("weld: {err}");
+            }
+            return ExitCode::FAILURE;
+        }
+    }
+    log::info!(
+        target: "parse",
+        "{} inputs in {:.2?}",
+        relocatables.len(),
+        started.elapsed()
+    );
+
+    // Archive members count as changed when their archive is
+    let file_hashes: Vec<u64> = mapped
+        .par_iter()
+        .map(|bytes| bytes.as_deref().map(incremental::hash).unwrap_or_default())
+        .collect();
+    let hashes: Vec<u64> = sources.iter().map(|&file| file_hashes[file]).collect();
+
+    if let Some(seed) = args.check_determinism {
+        return match weld_core::reproducible::check(&relocatables, &args.options, seed) {
+            Ok(()) => {
+                println!("weld: output is deterministic");
+                ExitCode::SUCCESS
             }
             Err(err) => {
-                println!("{path} : {err}");
-                return;
+                eprintln!("weld: {err}");
+                ExitCode::FAILURE
             }
+        };
+    }
+
+    // The link map describes a full link, so asking for one forces it
+    if args.options.incremental && args.map_file.is_none() {
+        let relinked = match previous_link() {
+            Ok((state, mut output)) => {
+                match incremental::relink(
+                    &state,
+                    &mut output,
+                    &relocatables,
+                    &hashes,
+                    &args.options,
+                ) {
+                    Ok(Relink::Patched(state)) => Ok((state, output)),
+                    Ok(Relink::FullLink(reason)) => Err(reason),
+                    Err(errs) => {
+                        for err in errs {
+                            eprintln!("weld: {err}");
+                        }
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            Err(reason) => Err(reason),
+        };
+        match relinked {
+            Ok((state, output)) => {
+                if args.cref {
+                    weld_core::cref::CrossReference::new(&relocatables)
+                        .write(&mut std::io::stdout())
+                        .expect("Write to stdout failed");
+                }
+                fs::write(OUTPUT, &output).expect("Write to file failed");
+                log::info!(target: "write", "patched {OUTPUT}, {} bytes", output.len());
+                write_state(&state);
+                return ExitCode::SUCCESS;
+            }
+            Err(reason) => log::info!(target: "layout", "{reason}; linking from scratch"),
         }
     }
 
-    println!("\n======================== WELD ===========================");
-    match weld_core::link(&relocatables) {
-        Ok(exec) => {
+    match weld_core::link(&relocatables, &args.options) {
+        Ok(weld_core::LinkOutput {
+            executable: exec,
+            map: link_map,
+            ..
+        }) => {
+            // Like GNU ld, the cross reference table goes in the map file if there is one
+            let cref = args
+                .cref
+                .then(|| weld_core::cref::CrossReference::new(&relocatables));
+            if let Some(path) = &args.map_file {
+                let written = fs::File::create(path).and_then(|mut file| {
+                    weld_core::map::write(&exec, &link_map, &mut file)?;
+                    match &cref {
+                        Some(cref) => writeln!(file).and_then(|_| cref.write(&mut file)),
+                        None => Ok(()),
+                    }
+                });
+                if let Err(err) = written {
+                    eprintln!("{path} : {err}");
+                    return ExitCode::FAILURE;
+                }
+            } else if let Some(cref) = &cref {
+                cref.write(&mut std::io::stdout())
+                    .expect("Write to stdout failed");
+            }
+            let started = Instant::now();
+            let output = exec.encode();
             let mut file = std::fs::OpenOptions::new()
                 .create(true)
+                .truncate(true)
                 .write(true)
-                .open("./weld.out")
+                .open(OUTPUT)
                 .unwrap();
-            file.write_all(&exec.encode())
-                .expect("Write to file failed");
+            file.write_all(&output).expect("Write to file failed");
+            log::info!(
+                target: "write",
+                "{OUTPUT}, {} bytes in {:.2?}",
+                output.len(),
+                started.elapsed()
+            );
+            if args.options.incremental {
+                write_state(&incremental::State::new(
+                    &relocatables,
+                    &hashes,
+                    &exec,
+                    &output,
+                    &link_map,
+                    &args.options,
+                ));
+            }
+            ExitCode::SUCCESS
         }
         Err(errs) => {
-            println!("{errs:?}")
+            for err in errs {
+                eprintln!("weld: {err}");
+            }
+            ExitCode::FAILURE
         }
     }
 }
+
+// Log records go to stderr, one line each, so they never mix with what weld
+// was asked to print. Nothing below a warning is shown unless asked for with
+// --verbose (everything at info) or WELD_LOG, which takes filters like
+// `relocate=debug` or `layout=trace,resolve=debug` and overrides --verbose.
+// The targets are the phases: parse, resolve, layout, relocate and write.
+fn init_logging(verbose: bool) {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(match verbose {
+            true => log::LevelFilter::Info,
+            false => log::LevelFilter::Warn,
+        })
+        .format(|out, record| {
+            let level = record.level().as_str().to_lowercase();
+            writeln!(out, "weld: {level}: {}: {}", record.target(), record.args())
+        });
+    if let Ok(filters) = env::var("WELD_LOG") {
+        builder.parse_filters(&filters);
+    }
+    builder.init();
+}
+
+// The state and output --incremental left behind, or why there are none
+fn previous_link() -> Result<(incremental::State, Vec<u8>), String> {
+    let state = fs::read_to_string(STATE).map_err(|_| format!("no {STATE} to relink from"))?;
+    let state = incremental::State::read(&state)?;
+    let output = fs::read(OUTPUT).map_err(|err| format!("{OUTPUT} : {err}"))?;
+    Ok((state, output))
+}
+
+fn write_state(state: &incremental::State) {
+    let written = fs::File::create(STATE).and_then(|mut file| state.write(&mut file));
+    if let Err(err) = written {
+        eprintln!("{STATE} : {err}");
+    }
+}
+
+// Inputs are mapped rather than read, so only what the linker touches is paged in
+fn map(path: &str) -> Result<Mmap, String> {
+    let file = fs::File::open(path).map_err(|err| err.to_string())?;
+    // Safety: weld never writes to its inputs. Another process truncating one
+    // mid-link would fault, as it would with any linker that maps its inputs.
+    unsafe { Mmap::map(&file) }.map_err(|err| err.to_string())
+}
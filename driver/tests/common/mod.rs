@@ -0,0 +1,122 @@
+//! What the integration tests share: finding a C compiler and the programs
+//! under testdata/N_*, compiling them and running what they link to.
+//!
+//! Programs without a start.c of their own are compiled with testdata/runtime,
+//! which provides `_start` and printing.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+pub const LEVELS: [&str; 4] = ["-O0", "-O1", "-O2", "-Os"];
+
+// There's no libc, so gcc mustn't turn loops into calls to strlen and
+// memset, nor guard stacks with __stack_chk_fail
+const CFLAGS: [&str; 2] = ["-fno-tree-loop-distribute-patterns", "-fno-stack-protector"];
+
+pub fn testdata() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../testdata")
+}
+
+// Why the host can't run the tests, if it can't: they need a C compiler
+// ($CC, cc or gcc), returned otherwise, and to run x86-64 Linux executables
+pub fn compiler() -> Result<String, &'static str> {
+    if !cfg!(all(target_arch = "x86_64", target_os = "linux")) {
+        return Err("weld's output only runs on x86-64 Linux");
+    }
+    let candidates = std::env::var("CC")
+        .into_iter()
+        .chain(["cc".to_string(), "gcc".to_string()]);
+    candidates
+        .into_iter()
+        .find(|cc| installed(cc))
+        .ok_or("no C compiler ($CC, cc or gcc)")
+}
+
+pub fn installed(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+// N in testdata/N_name
+fn number(dir: &Path) -> Option<u32> {
+    let name = dir.file_name()?.to_str()?;
+    name.split_once('_')?.0.parse().ok()
+}
+
+// In numeric order
+pub fn programs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(testdata())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir() && number(path).is_some())
+        .collect();
+    dirs.sort_by_key(|dir| number(dir));
+    dirs
+}
+
+pub fn name(dir: &Path) -> &str {
+    dir.file_name().unwrap().to_str().unwrap()
+}
+
+fn sources(dir: &Path) -> Vec<PathBuf> {
+    let mut sources: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c"))
+        .collect();
+    sources.sort();
+    sources
+}
+
+// An empty directory for `test` to build the program in `dir` at `level` in
+pub fn work_dir(test: &str, dir: &Path, level: &str) -> PathBuf {
+    let work = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join(test)
+        .join(format!("{}{level}", name(dir)));
+    let _ = fs::remove_dir_all(&work);
+    fs::create_dir_all(&work).unwrap();
+    work
+}
+
+// Compiles the program in `dir` into `work`, returning its objects in link order
+pub fn compile(cc: &str, dir: &Path, level: &str, work: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut sources = sources(dir);
+    if !dir.join("start.c").exists() {
+        sources.extend(self::sources(&testdata().join("runtime")));
+    }
+    let mut objects = Vec::new();
+    for source in &sources {
+        let object = work.join(source.file_stem().unwrap()).with_extension("o");
+        let compiled = Command::new(cc)
+            .args(["-c", level])
+            .args(CFLAGS)
+            .arg(source)
+            .arg("-o")
+            .arg(&object)
+            .output()
+            .unwrap();
+        if !compiled.status.success() {
+            return Err(format!(
+                "{cc} failed on {}:\n{}",
+                source.display(),
+                String::from_utf8_lossy(&compiled.stderr)
+            ));
+        }
+        objects.push(object);
+    }
+    Ok(objects)
+}
+
+// Runs the executable at `path`, returning its exit status and stdout
+pub fn run(path: &Path) -> (Option<i32>, String) {
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    let ran = Command::new(path).output().unwrap();
+    (
+        ran.status.code(),
+        String::from_utf8_lossy(&ran.stdout).into_owned(),
+    )
+}
@@ -0,0 +1,390 @@
+//! Links each program under testdata/N_* with both weld and the host's GNU
+//! ld, and checks they agree. The two executables must exit the same way and
+//! print the same, every symbol must be at the same offset in the same output
+//! section, and every relocation must refer to the same place; the first
+//! symbol or relocation that isn't is reported. Where ld's default script
+//! lays out input sections differently from weld, each of weld's input
+//! sections is compared with where ld would put it (see `ld_layout`).
+//!
+//! Skipped when `ld` isn't installed (or there's no C compiler).
+
+mod common;
+
+use elf::logical::{Relocatable, RelocationType};
+use elf_parser::ElfFile;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use weld_core::map::InputSection;
+use weld_core::{LinkOutput, Linker};
+
+const SHF_MERGE: u64 = 0x10;
+
+// A global by name, or a local by its file's stem (ld's symbol table only
+// has the source file name) and name
+type Key = (Option<String>, String);
+
+// An output section and offset within it
+type Location = (String, u64);
+
+fn stem(path: &str) -> String {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.split('.').next().unwrap_or(name).to_string()
+}
+
+// The section `address` is in; one-past-the-end counts, for symbols like
+// _end, if no section starts there
+fn locate(file: &ElfFile, address: u64) -> Option<Location> {
+    let sections = || {
+        file.section_headers
+            .iter()
+            .zip(&file.section_names)
+            .filter(|(shdr, _)| shdr.virtual_address != 0)
+    };
+    sections()
+        .find(|(shdr, _)| {
+            (shdr.virtual_address..shdr.virtual_address + shdr.size).contains(&address)
+        })
+        .or_else(|| sections().find(|(shdr, _)| shdr.virtual_address + shdr.size == address))
+        .map(|(shdr, name)| (name.clone(), address - shdr.virtual_address))
+}
+
+// Keys defined exactly once, so comparing them means something
+fn unique<T>(entries: impl IntoIterator<Item = (Key, T)>) -> HashMap<Key, T> {
+    let mut seen: HashMap<Key, Option<T>> = HashMap::new();
+    for (key, value) in entries {
+        seen.entry(key)
+            .and_modify(|v| *v = None)
+            .or_insert(Some(value));
+    }
+    seen.into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+}
+
+// ld's symbols' addresses. Locals follow the STT_FILE symbol naming their file.
+fn ld_symbols(file: &ElfFile) -> HashMap<Key, u64> {
+    let mut entries = Vec::new();
+    for (_, symbols) in &file.symbol_tables {
+        let mut source = None;
+        for s in symbols {
+            if s.is_file() {
+                source = Some(stem(&s.name));
+            } else if !s.name.is_empty() && !s.is_section() && s.is_defined() {
+                let file = (!s.is_global()).then(|| source.clone()).flatten();
+                entries.push(((file, s.name.clone()), s.symbol.value));
+            }
+        }
+    }
+    unique(entries)
+}
+
+// The output section ld's default script puts input section `name` in, which
+// weld put in `weld`
+fn ld_output_section(name: &str, weld: &str) -> String {
+    let outputs = [".text", ".rodata", ".data.rel.ro", ".data", ".bss"];
+    outputs
+        .into_iter()
+        .find(|&output| name == output || name.starts_with(&format!("{output}.")))
+        .unwrap_or(weld)
+        .to_string()
+}
+
+// ld puts .text.unlikely, .text.exit, .text.startup and .text.hot sections
+// ahead of the rest of .text, in that order
+fn ld_rank(name: &str) -> usize {
+    let first = [".text.unlikely", ".text.exit", ".text.startup", ".text.hot"];
+    first
+        .iter()
+        .position(|&prefix| name == prefix || name.starts_with(&format!("{prefix}.")))
+        .unwrap_or(first.len())
+}
+
+// One of weld's input sections, and where ld should have put it
+struct Placement {
+    address: u64,
+    size: u64,
+    ld: Option<Location>,
+}
+
+// weld's input sections laid out again in ld's output sections and order.
+// Where ld merges duplicate strings, what follows moves by however much it
+// saved, so a mergeable section and those after it have no expected place.
+fn ld_layout(weld: &LinkOutput, objects: &[Relocatable]) -> Vec<Placement> {
+    let mut inputs: Vec<&InputSection> = weld.map.input_sections.iter().collect();
+    inputs.sort_by_key(|i| (ld_rank(&i.name), i.file_index, i.index));
+    let mut ends: HashMap<String, Option<u64>> = HashMap::new();
+    inputs
+        .into_iter()
+        .map(|i| {
+            let output = ld_output_section(&i.name, &i.output_section);
+            let end = ends.entry(output.clone()).or_insert(Some(0));
+            if objects[i.file_index].sections[i.index].flags & SHF_MERGE != 0 {
+                *end = None;
+            }
+            let offset = end.map(|end| end.next_multiple_of(i.alignment.max(1)));
+            *end = offset.map(|offset| offset + i.size);
+            Placement {
+                address: weld.section(&i.output_section).unwrap().address + i.offset,
+                size: i.size,
+                ld: offset.map(|offset| (output, offset)),
+            }
+        })
+        .collect()
+}
+
+// Where ld should have put what weld put at `address`, if that can be told
+fn expected(layout: &[Placement], weld_file: &ElfFile, address: u64) -> Option<Location> {
+    match layout
+        .iter()
+        .find(|p| (p.address..p.address + p.size).contains(&address))
+    {
+        Some(p) => {
+            let (section, offset) = p.ld.clone()?;
+            Some((section, offset + (address - p.address)))
+        }
+        // Not in any input section, like _end
+        None => locate(weld_file, address),
+    }
+}
+
+fn weld_symbols(output: &LinkOutput) -> HashMap<Key, u64> {
+    let entries = output.map.symbols.iter().map(|s| {
+        let file = (!s.global).then(|| stem(&s.file));
+        ((file, s.name.clone()), s.address)
+    });
+    unique(entries)
+}
+
+// What the linker wrote at `site` for a relocation of `relocation_type`,
+// read back as the address it refers to plus the addend (S + A)
+fn target(file: &ElfFile, relocation_type: RelocationType, site: u64) -> Option<u64> {
+    let (section, offset) = locate(file, site)?;
+    let index = file.section_names.iter().position(|n| *n == section)?;
+    let bytes = file.section_bytes(index).ok()?.get(offset as usize..)?;
+    let read = |n: usize| -> Option<Vec<u8>> { bytes.get(..n).map(<[u8]>::to_vec) };
+    Some(match relocation_type {
+        RelocationType::Abs64 => u64::from_le_bytes(read(8)?.try_into().ok()?),
+        RelocationType::Pc64 => site.wrapping_add(u64::from_le_bytes(read(8)?.try_into().ok()?)),
+        RelocationType::Abs32 => u32::from_le_bytes(read(4)?.try_into().ok()?).into(),
+        RelocationType::Abs32Signed => i32::from_le_bytes(read(4)?.try_into().ok()?) as u64,
+        RelocationType::Pc32 | RelocationType::Plt32 => {
+            site.wrapping_add_signed(i32::from_le_bytes(read(4)?.try_into().ok()?).into())
+        }
+        _ => return None,
+    })
+}
+
+// Where ld put input section `section` of `object`, worked out from a symbol
+// defined in it
+fn ld_placement(object: &Relocatable, section: usize, symbols: &HashMap<Key, u64>) -> Option<u64> {
+    object
+        .symbols
+        .iter()
+        .filter(|s| !s.name.is_empty() && !s.is_section())
+        .filter(|s| s.symbol.relative_to_section as usize == section)
+        .find_map(|s| {
+            let file = (!s.is_global()).then(|| stem(&object.path));
+            let address = symbols.get(&(file, s.name.clone()))?;
+            Some(address.wrapping_sub(s.symbol.value))
+        })
+}
+
+fn compare_symbols(
+    weld: &LinkOutput,
+    layout: &[Placement],
+    weld_file: &ElfFile,
+    ld_file: &ElfFile,
+) -> Result<(), String> {
+    let ld = ld_symbols(ld_file);
+    let mut symbols: Vec<_> = weld_symbols(weld).into_iter().collect();
+    symbols.sort_by_key(|&(_, address)| address);
+    for (key, address) in symbols {
+        let Some(&ld_address) = ld.get(&key) else {
+            continue;
+        };
+        let Some(ours) = expected(layout, weld_file, address) else {
+            continue;
+        };
+        let (ours, theirs) = (Some(ours), locate(ld_file, ld_address));
+        if ours != theirs {
+            let (file, name) = key;
+            let name = match file {
+                Some(file) => format!("{name} (local to {file})"),
+                None => name,
+            };
+            return Err(format!(
+                "symbol {name} is at {} in weld's output but {} in ld's",
+                show(&ours),
+                show(&theirs)
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn compare_relocations(
+    objects: &[Relocatable],
+    weld: &LinkOutput,
+    layout: &[Placement],
+    weld_file: &ElfFile,
+    ld_file: &ElfFile,
+) -> Result<(), String> {
+    let ld = ld_symbols(ld_file);
+    for object in objects {
+        for r in &object.relocations {
+            let Some(input) = weld
+                .map
+                .input_sections
+                .iter()
+                .find(|i| i.file == object.path && i.index == r.section)
+            else {
+                continue; // Not linked, like .eh_frame
+            };
+            let Some(ld_base) = ld_placement(object, r.section, &ld) else {
+                continue;
+            };
+            let weld_base = weld.section(&input.output_section).unwrap().address + input.offset;
+            let offset = r.offset as u64;
+            let ours = target(weld_file, r.relo_type(), weld_base + offset);
+            let theirs = target(ld_file, r.relo_type(), ld_base + offset);
+            // Compared without the addend, which can take S + A out of S's section
+            let Some(ours) =
+                ours.and_then(|t| expected(layout, weld_file, t.wrapping_sub(r.addend as u64)))
+            else {
+                continue;
+            };
+            let (ours, theirs) = (
+                Some(ours),
+                theirs.and_then(|t| locate(ld_file, t.wrapping_sub(r.addend as u64))),
+            );
+            if ours != theirs {
+                return Err(format!(
+                    "{:?} relocation at {}({})+{offset:#x} refers to {} in weld's output but {} in ld's",
+                    r.relo_type(),
+                    object.path,
+                    input.name,
+                    show(&ours),
+                    show(&theirs)
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn show(location: &Option<Location>) -> String {
+    match location {
+        Some((section, offset)) => format!("{section}+{offset:#x}"),
+        None => "nowhere".to_string(),
+    }
+}
+
+// The program in `dir`, linked by both
+struct Linked {
+    objects: Vec<PathBuf>,
+    weld: LinkOutput,
+    weld_path: PathBuf,
+    ld_path: PathBuf,
+}
+
+fn link_both(cc: &str, dir: &Path, level: &str, test: &str) -> Result<Linked, String> {
+    let work = common::work_dir(test, dir, level);
+    let objects = common::compile(cc, dir, level, &work)?;
+
+    // In process, for where it put everything
+    let weld = objects
+        .iter()
+        .fold(Linker::new(), |linker, object| linker.input(object))
+        .link()
+        .map_err(|errors| {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            format!("weld failed:\n{}", errors.join("\n"))
+        })?;
+    let weld_path = work.join("weld.out");
+    fs::write(&weld_path, weld.executable.encode()).unwrap();
+    let ld_path = work.join("ld.out");
+    let linked = Command::new("ld")
+        .args(["-static", "-e", "_start", "-o"])
+        .arg(&ld_path)
+        .args(&objects)
+        .output()
+        .unwrap();
+    if !linked.status.success() {
+        return Err(format!(
+            "ld failed:\n{}",
+            String::from_utf8_lossy(&linked.stderr)
+        ));
+    }
+    Ok(Linked {
+        objects,
+        weld,
+        weld_path,
+        ld_path,
+    })
+}
+
+fn runs_alike(linked: &Linked) -> Result<(), String> {
+    let ours = common::run(&linked.weld_path);
+    let theirs = common::run(&linked.ld_path);
+    if ours != theirs {
+        return Err(format!(
+            "weld's output exited {:?} printing:\n{}\nld's exited {:?} printing:\n{}",
+            ours.0, ours.1, theirs.0, theirs.1
+        ));
+    }
+    Ok(())
+}
+
+fn laid_out_alike(linked: &Linked) -> Result<(), String> {
+    let weld_bytes = fs::read(&linked.weld_path).unwrap();
+    let ld_bytes = fs::read(&linked.ld_path).unwrap();
+    let weld_file = elf_parser::parse_file(&weld_bytes).map_err(|e| e.to_string())?;
+    let ld_file = elf_parser::parse_file(&ld_bytes).map_err(|e| e.to_string())?;
+
+    let contents: Vec<Vec<u8>> = linked
+        .objects
+        .iter()
+        .map(|o| fs::read(o).unwrap())
+        .collect();
+    let objects: Vec<Relocatable> = linked
+        .objects
+        .iter()
+        .zip(&contents)
+        .map(|(path, bytes)| elf_parser::parse(&path.display().to_string(), bytes).unwrap())
+        .collect();
+    let layout = ld_layout(&linked.weld, &objects);
+    compare_symbols(&linked.weld, &layout, &weld_file, &ld_file)?;
+    compare_relocations(&objects, &linked.weld, &layout, &weld_file, &ld_file)
+}
+
+// Runs `check` on every program at every level, linked both ways
+fn each_program(test: &str, check: fn(&Linked) -> Result<(), String>) {
+    let cc = match common::compiler() {
+        Ok(cc) => cc,
+        Err(why) => return eprintln!("skipping: {why}"),
+    };
+    if !common::installed("ld") {
+        return eprintln!("skipping: ld isn't installed");
+    }
+    let mut failures = Vec::new();
+    for dir in common::programs() {
+        for level in common::LEVELS {
+            if let Err(err) = link_both(&cc, &dir, level, test).and_then(|linked| check(&linked)) {
+                failures.push(format!("{} {level}: {err}", common::name(&dir)));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
+
+#[test]
+fn runs_like_gnu_ld() {
+    each_program("differential-run", runs_alike);
+}
+
+#[test]
+fn lays_out_like_gnu_ld() {
+    each_program("differential-layout", laid_out_alike);
+}
@@ -0,0 +1,70 @@
+//! weld exits 0 only when it wrote its output, so build systems stop at a
+//! failed link rather than running a stale or missing executable.
+
+use elf::file::{SectionFlags, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_FUNCTION};
+use elf::logical::RelocationType;
+use elf::object::ObjectBuilder;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// start.o in a new directory for `test`, whose _start refers to `calls`
+fn start(test: &str, calls: Option<&str>) -> PathBuf {
+    let work = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("exit_status")
+        .join(test);
+    let _ = fs::remove_dir_all(&work);
+    fs::create_dir_all(&work).unwrap();
+
+    let mut object = ObjectBuilder::new();
+    let code = SectionFlags::Alloc | SectionFlags::Executable;
+    let text = object.section(".text", code, &[0; 8]);
+    object.symbol(
+        "_start",
+        SYMBOL_BINDING_GLOBAL,
+        SYMBOL_TYPE_FUNCTION,
+        text,
+        0,
+        0,
+    );
+    if let Some(name) = calls {
+        let symbol = object.undefined(name);
+        object.relocation(text, 0, RelocationType::Abs64 as u32, symbol, 0);
+    }
+    fs::write(work.join("start.o"), object.encode()).unwrap();
+    work
+}
+
+fn weld(work: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_driver"))
+        .args(args)
+        .current_dir(work)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn successful_links_exit_0() {
+    let work = start("success", None);
+    let linked = weld(&work, &["start.o"]);
+    assert!(linked.status.success());
+    assert!(work.join("weld.out").exists());
+}
+
+#[test]
+fn failed_links_exit_1() {
+    let work = start("undefined", Some("missing"));
+    let linked = weld(&work, &["start.o"]);
+    assert_eq!(linked.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&linked.stderr).contains("undefined reference to `missing`"));
+    assert!(!work.join("weld.out").exists());
+
+    let work = start("unreadable", None);
+    for args in [
+        &["start.o", "nowhere.o"][..],
+        &["--no-such-option", "start.o"],
+        &["start.o", "-lnowhere"],
+    ] {
+        assert_eq!(weld(&work, args).status.code(), Some(1), "{args:?}");
+    }
+}
@@ -0,0 +1,59 @@
+//! Compiles each program under testdata/N_*, links it with weld at each
+//! optimization level, runs it, and checks what it printed and its exit
+//! status against the directory's `expected` file. That file's first line
+//! is `exit <status>`; the rest is the program's stdout.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn check(cc: &str, dir: &Path, level: &str) -> Result<(), String> {
+    let work = common::work_dir("testdata", dir, level);
+    let objects = common::compile(cc, dir, level, &work)?;
+    let linked = Command::new(env!("CARGO_BIN_EXE_driver"))
+        .args(&objects)
+        .current_dir(&work)
+        .output()
+        .unwrap();
+    // A failed link leaves no weld.out to run, or a stale one
+    if !linked.status.success() {
+        return Err(format!(
+            "weld exited {}:\n{}",
+            linked.status,
+            String::from_utf8_lossy(&linked.stderr)
+        ));
+    }
+    let (code, stdout) = common::run(&work.join("weld.out"));
+
+    let expected = fs::read_to_string(dir.join("expected")).unwrap();
+    let (status, expected_stdout) = expected.split_once('\n').unwrap_or((&expected, ""));
+    let status: i32 = status
+        .strip_prefix("exit ")
+        .and_then(|status| status.parse().ok())
+        .expect("expected's first line should be `exit <status>`");
+    if (code, stdout.as_str()) != (Some(status), expected_stdout) {
+        return Err(format!(
+            "expected exit {status} and output:\n{expected_stdout}\ngot exit {code:?} and output:\n{stdout}"
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn testdata_programs() {
+    let cc = match common::compiler() {
+        Ok(cc) => cc,
+        Err(why) => return eprintln!("skipping: {why}"),
+    };
+    let mut failures = Vec::new();
+    for dir in common::programs() {
+        for level in common::LEVELS {
+            if let Err(err) = check(&cc, &dir, level) {
+                failures.push(format!("{} {level}: {err}", common::name(&dir)));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
@@ -0,0 +1,385 @@
+//! Explicit little-endian (de)serialization of the on-disk structures in
+//! `elf::file`. Fields are written one at a time, so the output doesn't
+//! depend on the host's struct layout or endianness, and decoding never
+//! reinterprets raw bytes as enums that may not hold the value read.
+
+use crate::file::*;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    TooShort { needed: usize, available: usize },
+    UnknownSectionType(u32),
+    UnknownSegmentType(u32),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::TooShort { needed, available } => {
+                write!(f, "needed {needed} bytes but only {available} remain")
+            }
+            DecodeError::UnknownSectionType(t) => write!(f, "unknown section type {t:#x}"),
+            DecodeError::UnknownSegmentType(t) => write!(f, "unknown segment type {t:#x}"),
+        }
+    }
+}
+
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+}
+
+pub trait Decode: Sized {
+    // Encoded size in bytes
+    const SIZE: usize;
+
+    // Decodes from the first `SIZE` bytes of `bytes`
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError>;
+}
+
+// Reads fields in order from a slice known to hold at least as many bytes as are read
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8], size: usize) -> Result<Reader<'a>, DecodeError> {
+        match bytes.get(..size) {
+            Some(bytes) => Ok(Reader { bytes }),
+            None => Err(DecodeError::TooShort {
+                needed: size,
+                available: bytes.len(),
+            }),
+        }
+    }
+
+    pub fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (head, tail) = self.bytes.split_at(N);
+        self.bytes = tail;
+        let mut array = [0; N];
+        array.copy_from_slice(head);
+        array
+    }
+
+    pub fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    pub fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    pub fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    pub fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take())
+    }
+
+    pub fn i64(&mut self) -> i64 {
+        i64::from_le_bytes(self.take())
+    }
+}
+
+impl Encode for FileHeader {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let id = &self.identification;
+        out.extend_from_slice(&id.magic);
+        out.extend_from_slice(&[id.format_class, id.endianness, id.format_version, id.os_abi]);
+        out.extend_from_slice(&[0; 8]); // ABI version and padding
+        out.extend_from_slice(&self.object_file_type.to_le_bytes());
+        out.extend_from_slice(&self.machine_type.to_le_bytes());
+        out.extend_from_slice(&self.object_file_version.to_le_bytes());
+        out.extend_from_slice(&self.entrypoint.to_le_bytes());
+        out.extend_from_slice(&self.program_header_offset.to_le_bytes());
+        out.extend_from_slice(&self.section_header_offset.to_le_bytes());
+        out.extend_from_slice(&self.processor_specific_flags.to_le_bytes());
+        out.extend_from_slice(&self.file_header_size.to_le_bytes());
+        out.extend_from_slice(&self.program_headers_total_size.to_le_bytes());
+        out.extend_from_slice(&self.program_header_entry_count.to_le_bytes());
+        out.extend_from_slice(&self.section_headers_total_size.to_le_bytes());
+        out.extend_from_slice(&self.section_header_entry_count.to_le_bytes());
+        out.extend_from_slice(&self.sh_section_name_stringtab_entry_index.to_le_bytes());
+    }
+}
+
+impl Decode for FileHeader {
+    const SIZE: usize = FILE_HEADER_SIZE;
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(bytes, Self::SIZE)?;
+        let mut header = FileHeader::default();
+        header.identification.magic = r.take();
+        header.identification.format_class = r.u8();
+        header.identification.endianness = r.u8();
+        header.identification.format_version = r.u8();
+        header.identification.os_abi = r.u8();
+        r.take::<8>();
+        header.object_file_type = r.u16();
+        header.machine_type = r.u16();
+        header.object_file_version = r.u32();
+        header.entrypoint = r.u64();
+        header.program_header_offset = r.u64();
+        header.section_header_offset = r.u64();
+        header.processor_specific_flags = r.u32();
+        header.file_header_size = r.u16();
+        header.program_headers_total_size = r.u16();
+        header.program_header_entry_count = r.u16();
+        header.section_headers_total_size = r.u16();
+        header.section_header_entry_count = r.u16();
+        header.sh_section_name_stringtab_entry_index = r.u16();
+        Ok(header)
+    }
+}
+
+impl Encode for SectionHeader {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.name.to_le_bytes());
+        out.extend_from_slice(&(self.section_type as u32).to_le_bytes());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.extend_from_slice(&self.virtual_address.to_le_bytes());
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&self.size.to_le_bytes());
+        out.extend_from_slice(&self.link_to_other_section.to_le_bytes());
+        out.extend_from_slice(&self.misc_info.to_le_bytes());
+        out.extend_from_slice(&self.address_allignment_boundary.to_le_bytes());
+        out.extend_from_slice(&self.entry_size.to_le_bytes());
+    }
+}
+
+impl Decode for SectionHeader {
+    const SIZE: usize = SECTION_HEADER_SIZE;
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(bytes, Self::SIZE)?;
+        let name = r.u32();
+        let section_type = r.u32();
+        Ok(SectionHeader {
+            name,
+            section_type: SectionType::try_from(section_type)
+                .map_err(DecodeError::UnknownSectionType)?,
+            flags: r.u64(),
+            virtual_address: r.u64(),
+            offset: r.u64(),
+            size: r.u64(),
+            link_to_other_section: r.u32(),
+            misc_info: r.u32(),
+            address_allignment_boundary: r.u64(),
+            entry_size: r.u64(),
+        })
+    }
+}
+
+impl Encode for ProgramHeader {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&(self.segment_type.clone() as u32).to_le_bytes());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&self.virtual_address.to_le_bytes());
+        out.extend_from_slice(&self.physical_address.to_le_bytes());
+        out.extend_from_slice(&self.size_in_file.to_le_bytes());
+        out.extend_from_slice(&self.size_in_memory.to_le_bytes());
+        out.extend_from_slice(&self.required_alignment.to_le_bytes());
+    }
+}
+
+impl Decode for ProgramHeader {
+    const SIZE: usize = PROGRAM_HEADER_SIZE;
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(bytes, Self::SIZE)?;
+        let segment_type = r.u32();
+        Ok(ProgramHeader {
+            segment_type: SegmentType::try_from(segment_type)
+                .map_err(DecodeError::UnknownSegmentType)?,
+            flags: r.u32(),
+            offset: r.u64(),
+            virtual_address: r.u64(),
+            physical_address: r.u64(),
+            size_in_file: r.u64(),
+            size_in_memory: r.u64(),
+            required_alignment: r.u64(),
+        })
+    }
+}
+
+impl Encode for Symbol {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.name.to_le_bytes());
+        out.extend_from_slice(&[self.info, self.other]);
+        out.extend_from_slice(&self.relative_to_section.to_le_bytes());
+        out.extend_from_slice(&self.value.to_le_bytes());
+        out.extend_from_slice(&self.size.to_le_bytes());
+    }
+}
+
+impl Decode for Symbol {
+    const SIZE: usize = SYMBOL_SIZE;
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(bytes, Self::SIZE)?;
+        Ok(Symbol {
+            name: r.u32(),
+            info: r.u8(),
+            other: r.u8(),
+            relative_to_section: r.u16(),
+            value: r.u64(),
+            size: r.u64(),
+        })
+    }
+}
+
+impl Encode for RelocationWithAddend {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&self.info.to_le_bytes());
+        out.extend_from_slice(&self.addend.to_le_bytes());
+    }
+}
+
+impl Decode for RelocationWithAddend {
+    const SIZE: usize = RELOCATION_WITH_ADDEND_SIZE;
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(bytes, Self::SIZE)?;
+        Ok(RelocationWithAddend {
+            offset: r.u64(),
+            info: r.u64(),
+            addend: r.i64(),
+        })
+    }
+}
+
+impl Encode for RelocationWithoutAddend {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&self.info.to_le_bytes());
+    }
+}
+
+impl Decode for RelocationWithoutAddend {
+    const SIZE: usize = RELOCATION_WITHOUT_ADDEND_SIZE;
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut r = Reader::new(bytes, Self::SIZE)?;
+        Ok(RelocationWithoutAddend {
+            offset: r.u64(),
+            info: r.u64(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: Encode + Decode + std::fmt::Debug + PartialEq>(value: T) {
+        let bytes = value.to_bytes();
+        assert_eq!(bytes.len(), T::SIZE);
+        assert_eq!(T::decode(&bytes).unwrap(), value);
+        assert_eq!(
+            T::decode(&bytes[..T::SIZE - 1]),
+            Err(DecodeError::TooShort {
+                needed: T::SIZE,
+                available: T::SIZE - 1
+            })
+        );
+    }
+
+    #[test]
+    fn file_header() {
+        let mut header = FileHeader {
+            object_file_type: 2,
+            machine_type: 0x3e,
+            entrypoint: 0x401000,
+            section_header_offset: 0x1234,
+            section_header_entry_count: 5,
+            ..Default::default()
+        };
+        header.identification.magic = [0x7f, b'E', b'L', b'F'];
+        header.identification.format_class = 2;
+
+        let bytes = header.to_bytes();
+        assert_eq!(&bytes[..5], &[0x7f, b'E', b'L', b'F', 2]);
+        assert_eq!(&bytes[24..32], &0x401000u64.to_le_bytes()); // e_entry
+        round_trip(header);
+    }
+
+    #[test]
+    fn section_header() {
+        round_trip(SectionHeader {
+            name: 7,
+            section_type: SectionType::X86_64Unwind,
+            flags: 0x6,
+            virtual_address: 0x401000,
+            offset: 0x1000,
+            size: 0x70,
+            link_to_other_section: 1,
+            misc_info: 2,
+            address_allignment_boundary: 16,
+            entry_size: 24,
+        });
+        let mut bytes = SectionHeader::default().to_bytes();
+        bytes[4] = 0x42;
+        assert_eq!(
+            SectionHeader::decode(&bytes),
+            Err(DecodeError::UnknownSectionType(0x42))
+        );
+    }
+
+    #[test]
+    fn program_header() {
+        round_trip(ProgramHeader {
+            segment_type: SegmentType::GnuStack,
+            flags: 6,
+            offset: 0x1000,
+            virtual_address: 0x401000,
+            physical_address: 0x401000,
+            size_in_file: 0x70,
+            size_in_memory: 0x80,
+            required_alignment: 0x1000,
+        });
+    }
+
+    #[test]
+    fn symbol() {
+        let symbol = Symbol {
+            name: 1,
+            info: 0x12,
+            other: 0,
+            relative_to_section: 0xfff1,
+            value: 0xdeadbeef,
+            size: 28,
+        };
+        assert_eq!(&symbol.to_bytes()[4..8], &[0x12, 0, 0xf1, 0xff]);
+        round_trip(symbol);
+    }
+
+    #[test]
+    fn relocation_with_addend() {
+        let r = RelocationWithAddend {
+            offset: 0xa,
+            info: (9 << 32) | 4,
+            addend: -4,
+        };
+        assert_eq!(&r.to_bytes()[16..], &(-4i64).to_le_bytes());
+        round_trip(r);
+    }
+
+    #[test]
+    fn relocation_without_addend() {
+        let r = RelocationWithoutAddend {
+            offset: 0x10,
+            info: (3 << 32) | 2,
+        };
+        assert_eq!((r.symbol(), r.relo_type()), (3, 2));
+        round_trip(r);
+    }
+}
@@ -24,7 +24,7 @@ type Word = u32;
 type XWord = u64;
 type SignedXWord = i64;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq)]
 #[repr(C)]
 pub struct FileHeader {
     pub identification: Identification,
@@ -43,7 +43,7 @@ pub struct FileHeader {
     pub sh_section_name_stringtab_entry_index: Half,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq)]
 #[repr(C)]
 pub struct Identification {
     pub magic: [u8; 4],
@@ -84,6 +84,7 @@ pub enum SectionFlags {
     Write = 0x1,
     Alloc = 0x2,
     Executable = 0x4,
+    InfoLink = 0x40, // sh_info holds a section index
 }
 
 impl BitOr for SectionFlags {
@@ -118,11 +119,54 @@ pub enum SectionType {
     SectionGroup = 0x11,
     ExtendedSectionIndices = 0x12,
     NumberOfDefinedTypes = 0x13,
+    GnuAttributes = 0x6ffffff5,
+    GnuHash = 0x6ffffff6,
+    GnuLibraryList = 0x6ffffff7,
+    GnuVersionDefinitions = 0x6ffffffd,
+    GnuVersionNeeds = 0x6ffffffe,
+    GnuVersionSymbols = 0x6fffffff,
+    X86_64Unwind = 0x70000001,
+}
+
+impl TryFrom<u32> for SectionType {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        use SectionType::*;
+        Ok(match value {
+            0x0 => None,
+            0x1 => ProgramData,
+            0x2 => SymbolTable,
+            0x3 => StringTable,
+            0x4 => RelocationWithAddend,
+            0x5 => SymbolHashTable,
+            0x6 => DynamicLinkingInfo,
+            0x7 => Notes,
+            0x8 => ProgramSpaceWithNoData,
+            0x9 => RelocationWithoutAddend,
+            0x0A => Reserved,
+            0x0B => DynamicLinkerSymbolTable,
+            0x0E => ArrayOfConstructors,
+            0x0F => ArrayOfDestructors,
+            0x10 => ArrayOfPreConstructors,
+            0x11 => SectionGroup,
+            0x12 => ExtendedSectionIndices,
+            0x13 => NumberOfDefinedTypes,
+            0x6ffffff5 => GnuAttributes,
+            0x6ffffff6 => GnuHash,
+            0x6ffffff7 => GnuLibraryList,
+            0x6ffffffd => GnuVersionDefinitions,
+            0x6ffffffe => GnuVersionNeeds,
+            0x6fffffff => GnuVersionSymbols,
+            0x70000001 => X86_64Unwind,
+            _ => return Err(value),
+        })
+    }
 }
 
 // Relocations
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, PartialEq)]
 #[repr(C)]
 pub struct RelocationWithAddend {
     pub offset: Address, // Location where relocation should be applied
@@ -130,6 +174,9 @@ pub struct RelocationWithAddend {
     pub addend: SignedXWord,
 }
 
+pub const RELOCATION_WITH_ADDEND_SIZE: usize = std::mem::size_of::<RelocationWithAddend>();
+const _ASSERT_RELOCATION_WITH_ADDEND_SIZE: [u8; 24] = [0; RELOCATION_WITH_ADDEND_SIZE];
+
 impl RelocationWithAddend {
     pub fn symbol(&self) -> usize {
         (self.info >> 32) as usize
@@ -140,10 +187,110 @@ impl RelocationWithAddend {
     }
 }
 
+// SHT_REL entries leave the addend in the bytes being relocated
+#[derive(Debug, Default, PartialEq)]
+#[repr(C)]
+pub struct RelocationWithoutAddend {
+    pub offset: Address,
+    pub info: XWord,
+}
+
+pub const RELOCATION_WITHOUT_ADDEND_SIZE: usize = std::mem::size_of::<RelocationWithoutAddend>();
+const _ASSERT_RELOCATION_WITHOUT_ADDEND_SIZE: [u8; 16] = [0; RELOCATION_WITHOUT_ADDEND_SIZE];
+
+impl RelocationWithoutAddend {
+    pub fn symbol(&self) -> usize {
+        (self.info >> 32) as usize
+    }
+
+    pub fn relo_type(&self) -> XWord {
+        self.info & 0xffffffff
+    }
+}
+
+pub const MACHINE_X86_64: Half = 0x3e;
+pub const MACHINE_AARCH64: Half = 0xb7;
+
+// The field a relocation type patches, as (size in bytes, signed): where an
+// SHT_REL relocation's addend is kept. None for types not known here.
+pub fn relocation_field(machine: Half, relocation_type: XWord) -> Option<(usize, bool)> {
+    if machine == MACHINE_AARCH64 {
+        // Only the data types: instructions scatter their immediates, and
+        // AArch64 objects always use SHT_RELA anyway
+        return match relocation_type {
+            0 | 256 => Some((0, false)), // NONE, in both numberings
+            257 => Some((8, true)),
+            258 => Some((4, false)),
+            259 => Some((2, false)),
+            260 => Some((8, true)),
+            261 => Some((4, true)),
+            262 => Some((2, true)),
+            _ => None,
+        };
+    }
+    if machine != MACHINE_X86_64 {
+        return None;
+    }
+    match relocation_type {
+        0 => Some((0, false)), // NONE patches nothing
+        // 64, DTPMOD64, DTPOFF64, TPOFF64, PC64, GOTOFF64
+        1 | 16 | 17 | 18 | 24 | 25 => Some((8, true)),
+        10 => Some((4, false)),
+        // PC32, GOT32, PLT32, GOTPCREL, 32S, the 32-bit TLS types, GOTPC32,
+        // GOTPCRELX and REX_GOTPCRELX
+        2 | 3 | 4 | 9 | 11 | 19..=23 | 26 | 41 | 42 => Some((4, true)),
+        12 => Some((2, false)),
+        13 => Some((2, true)),
+        14 => Some((1, false)),
+        15 => Some((1, true)),
+        _ => None,
+    }
+}
+
+// The psABI's name for a relocation type, as binutils prints it
+pub fn relocation_type_name(machine: Half, relocation_type: XWord) -> Option<&'static str> {
+    if machine != MACHINE_X86_64 {
+        return None;
+    }
+    match relocation_type {
+        0 => Some("R_X86_64_NONE"),
+        1 => Some("R_X86_64_64"),
+        2 => Some("R_X86_64_PC32"),
+        3 => Some("R_X86_64_GOT32"),
+        4 => Some("R_X86_64_PLT32"),
+        5 => Some("R_X86_64_COPY"),
+        6 => Some("R_X86_64_GLOB_DAT"),
+        7 => Some("R_X86_64_JUMP_SLOT"),
+        8 => Some("R_X86_64_RELATIVE"),
+        9 => Some("R_X86_64_GOTPCREL"),
+        10 => Some("R_X86_64_32"),
+        11 => Some("R_X86_64_32S"),
+        12 => Some("R_X86_64_16"),
+        13 => Some("R_X86_64_PC16"),
+        14 => Some("R_X86_64_8"),
+        15 => Some("R_X86_64_PC8"),
+        16 => Some("R_X86_64_DTPMOD64"),
+        17 => Some("R_X86_64_DTPOFF64"),
+        18 => Some("R_X86_64_TPOFF64"),
+        19 => Some("R_X86_64_TLSGD"),
+        20 => Some("R_X86_64_TLSLD"),
+        21 => Some("R_X86_64_DTPOFF32"),
+        22 => Some("R_X86_64_GOTTPOFF"),
+        23 => Some("R_X86_64_TPOFF32"),
+        24 => Some("R_X86_64_PC64"),
+        25 => Some("R_X86_64_GOTOFF64"),
+        26 => Some("R_X86_64_GOTPC32"),
+        37 => Some("R_X86_64_IRELATIVE"),
+        41 => Some("R_X86_64_GOTPCRELX"),
+        42 => Some("R_X86_64_REX_GOTPCRELX"),
+        _ => None,
+    }
+}
+
 // Symbols
 
 // https://docs.oracle.com/cd/E23824_01/html/819-0690/chapter6-79797.html#chapter6-35166
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
 #[repr(C)]
 pub struct Symbol {
     pub name: Word,
@@ -154,10 +301,38 @@ pub struct Symbol {
     pub size: XWord,
 }
 
+pub const SYMBOL_SIZE: usize = std::mem::size_of::<Symbol>();
+const _ASSERT_SYMBOL_SIZE: [u8; 24] = [0; SYMBOL_SIZE];
+
+pub const SYMBOL_TYPE_NONE: u8 = 0;
+pub const SYMBOL_TYPE_OBJECT: u8 = 1;
+pub const SYMBOL_TYPE_FUNCTION: u8 = 2;
+pub const SYMBOL_TYPE_SECTION: u8 = 3;
+pub const SYMBOL_TYPE_FILE: u8 = 4;
+pub const SYMBOL_BINDING_LOCAL: u8 = 0;
+pub const SYMBOL_BINDING_GLOBAL: u8 = 1;
+pub const SYMBOL_BINDING_WEAK: u8 = 2;
+
+// Special values of st_shndx
+pub const SECTION_INDEX_UNDEFINED: u16 = 0;
+pub const SECTION_INDEX_ABSOLUTE: u16 = 0xfff1;
+
+impl Symbol {
+    // Upper 4 bits of st_info, e.g. STB_LOCAL or STB_GLOBAL
+    pub fn binding(&self) -> u8 {
+        self.info >> 4
+    }
+
+    // Lower 4 bits of st_info, e.g. STT_FUNC or STT_SECTION
+    pub fn symbol_type(&self) -> u8 {
+        self.info & 0xf
+    }
+}
+
 //  Executable/Segment-based view
 // ===============================
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
 #[repr(C)]
 pub struct ProgramHeader {
     pub segment_type: SegmentType,
@@ -173,7 +348,7 @@ pub struct ProgramHeader {
 pub const PROGRAM_HEADER_SIZE: usize = std::mem::size_of::<ProgramHeader>();
 const _ASSERT_PROGRAM_HDR_SIZE: [u8; 56] = [0; PROGRAM_HEADER_SIZE];
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
 #[repr(u32)]
 pub enum SegmentType {
     #[default]
@@ -191,6 +366,28 @@ pub enum SegmentType {
     GnuProperty = 1685382483,
 }
 
+impl TryFrom<u32> for SegmentType {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        use SegmentType::*;
+        Ok(match value {
+            0x0 => None,
+            0x1 => Loadable,
+            0x2 => DynamicLinkInfo,
+            0x3 => InterpreterInfo,
+            0x4 => AuxiliaryInfo,
+            0x5 => Reserved,
+            0x6 => ProgramHeaderTableSegment,
+            0x7 => ThreadLocalStorageTemplate,
+            1685382480 => GnuEHFrame,
+            1685382481 => GnuStack,
+            1685382482 => GnuRelRO,
+            1685382483 => GnuProperty,
+            _ => return Err(value),
+        })
+    }
+}
 
 #[derive(Debug)]
 #[repr(u32)]
@@ -206,4 +403,4 @@ impl BitOr for SegmentFlags {
     fn bitor(self, rhs: SegmentFlags) -> u32 {
         (self as u32) | (rhs as u32)
     }
-}
\ No newline at end of file
+}
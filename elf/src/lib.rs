@@ -1,3 +1,5 @@
+pub mod encoding;
 pub mod file;
 pub mod logical;
+pub mod object;
 pub mod string_table;
@@ -6,21 +6,45 @@
 //!  are used in the public API for the weld library. For example, in
 //!  the simplest terms, the weld library takes a collection of `Relocatable`s
 //!  and returns a single `Executable`.
+//!
+//!  Parsed inputs borrow their contents from the file's bytes (which are
+//!  usually memory-mapped); only what the linker builds is owned.
 
 use super::file;
-use crate::string_table;
-use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Instruction};
+use crate::encoding::Encode;
+use std::borrow::Cow;
 use std::fmt;
 
 #[derive(Default)]
-pub struct Section {
+pub struct Section<'a> {
     pub name: String,
-    pub bytes: Vec<u8>,
+    pub section_type: file::SectionType,
+    pub bytes: Cow<'a, [u8]>,
+    pub size: u64, // In memory. Matches `bytes` except for SHT_NOBITS, which has none.
     pub offset: u64,
     pub virtual_address: u64,
+    pub flags: u64,
+    pub alignment: u64,
+}
+
+impl Section<'_> {
+    // DWARF sections aren't loaded at runtime, but debuggers expect to find
+    // them in the output under their original names.
+    pub fn is_debug(&self) -> bool {
+        self.name.starts_with(".debug_") && !self.is_alloc()
+    }
+
+    pub fn is_alloc(&self) -> bool {
+        self.flags & file::SectionFlags::Alloc as u64 != 0
+    }
+
+    pub fn is_nobits(&self) -> bool {
+        self.section_type == file::SectionType::ProgramSpaceWithNoData
+    }
 }
 
-impl fmt::Debug for Section {
+// Code isn't disassembled, as it may be for any machine: weld-objdump -d does
+impl fmt::Debug for Section<'_> {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         if self.name != ".text" {
             return write!(fmt, "<Section {}>", self.name);
@@ -34,52 +58,54 @@ impl fmt::Debug for Section {
             self.offset,
             self.virtual_address
         )?;
-        if self.name == ".text" {
-            fmt.write_str("[Disassembly: \n").unwrap();
-            let mut decoder = Decoder::with_ip(64, &self.bytes, self.offset, DecoderOptions::NONE);
-            let mut formatter = GasFormatter::new();
-            let mut output = String::new();
-            let mut instruction = Instruction::default();
-            while decoder.can_decode() {
-                decoder.decode_out(&mut instruction);
-                output.clear();
-                formatter.format(&instruction, &mut output);
-                fmt.write_fmt(format_args!("{:4X} {}\n", instruction.ip(), output))
-                    .unwrap();
-            }
-            fmt.write_str("\n]").unwrap();
-        }
         fmt.write_str("\n")
     }
 }
 
 #[derive(Debug, Default)]
-pub struct Relocatable {
+pub struct Relocatable<'a> {
     pub path: String,
-    pub sections: Vec<Section>,
+    pub machine: u16, // e_machine
+    pub sections: Vec<Section<'a>>,
     pub relocations: Vec<Relocation>,
     pub symbols: Vec<SymbolInfo>,
 }
 
-impl Relocatable {
+impl Relocatable<'_> {
     pub fn find_section(&self, name: &str) -> Option<usize> {
         self.sections.iter().position(|s| s.name == name)
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u64)]
 pub enum RelocationType {
     #[default]
     None = 0,
     Abs64 = 1,
-    Abs32 = 2,
+    Pc32 = 2,
     Plt32 = 4,
     Copy = 5,
     GlobalData = 6,
     JumpSlot = 7,
     RelativeToReloc = 8,
-    ThreadPtrOffset = 10, // Used with TLS - see https://akkadia.org/drepper/tls.pdf
+    Abs32 = 10,
+    Abs32Signed = 11,
+    ThreadPtrOffset = 18, // Used with TLS - see https://akkadia.org/drepper/tls.pdf
+    Pc64 = 24,
+    // AArch64's numbers start past x86-64's, so one enum holds both
+    Aarch64Abs64 = 257,
+    Aarch64Abs32 = 258,
+    Aarch64Prel32 = 261,
+    AdrPrelPgHi21 = 275,
+    AddAbsLo12Nc = 277,
+    Ldst8AbsLo12Nc = 278,
+    Jump26 = 282,
+    Call26 = 283,
+    Ldst16AbsLo12Nc = 284,
+    Ldst32AbsLo12Nc = 285,
+    Ldst64AbsLo12Nc = 286,
+    Ldst128AbsLo12Nc = 299,
     Unknown = 0xffffffff,
 }
 
@@ -88,22 +114,41 @@ pub struct Relocation {
     pub offset: usize, // Location where relocation should be applied
     pub info: u64,
     pub addend: i64,
-    pub symbol: SymbolInfo,
+    pub symbol: usize,  // Index into the relocatable's symbols
+    pub section: usize, // Index of the section that `offset` is relative to
 }
 
 impl Relocation {
-    pub fn from(r: &file::RelocationWithAddend, symbol: &SymbolInfo) -> Relocation {
+    pub fn from(r: &file::RelocationWithAddend, section: usize) -> Relocation {
         Relocation {
             offset: r.offset as usize,
             info: r.info,
             addend: r.addend,
-            symbol: symbol.clone(),
+            symbol: r.symbol(),
+            section,
         }
     }
     // Processor-specific: https://docs.oracle.com/cd/E19120-01/open.solaris/819-0690/chapter7-2/index.html
     pub fn relo_type(&self) -> RelocationType {
         match self.raw_relo_type() {
+            1 => RelocationType::Abs64,
+            2 => RelocationType::Pc32,
             4 => RelocationType::Plt32,
+            10 => RelocationType::Abs32,
+            11 => RelocationType::Abs32Signed,
+            24 => RelocationType::Pc64,
+            257 => RelocationType::Aarch64Abs64,
+            258 => RelocationType::Aarch64Abs32,
+            261 => RelocationType::Aarch64Prel32,
+            275 => RelocationType::AdrPrelPgHi21,
+            277 => RelocationType::AddAbsLo12Nc,
+            278 => RelocationType::Ldst8AbsLo12Nc,
+            282 => RelocationType::Jump26,
+            283 => RelocationType::Call26,
+            284 => RelocationType::Ldst16AbsLo12Nc,
+            285 => RelocationType::Ldst32AbsLo12Nc,
+            286 => RelocationType::Ldst64AbsLo12Nc,
+            299 => RelocationType::Ldst128AbsLo12Nc,
             _ => RelocationType::Unknown,
         }
     }
@@ -116,9 +161,9 @@ impl Relocation {
 impl fmt::Debug for Relocation {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_fmt(format_args!(
-            "Relocation < symbol=[{:?} name={}] offset={:#x} addend={} raw_type={:#x} type={:?} >",
-            self.symbol.symbol,
-            self.symbol.name,
+            "Relocation < symbol={} section={} offset={:#x} addend={} raw_type={:#x} type={:?} >",
+            self.symbol,
+            self.section,
             self.offset,
             self.addend,
             self.raw_relo_type(),
@@ -142,73 +187,121 @@ impl SymbolInfo {
     }
 
     pub fn is_defined(&self) -> bool {
-        self.symbol.relative_to_section != 0
+        self.symbol.relative_to_section != file::SECTION_INDEX_UNDEFINED
+    }
+
+    pub fn is_section(&self) -> bool {
+        self.symbol.symbol_type() == file::SYMBOL_TYPE_SECTION
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.symbol.symbol_type() == file::SYMBOL_TYPE_FILE
     }
-}
 
-// An executable has a very specific layout
-//   [ 64 bytes             ] File Header
-//   [ 56*(# phrs) bytes    ] Program Header Table
-//   [ `pre_text_pad` bytes ] Padding
-//   [ sh.size bytes        ] Text Section
-//   [ sh.size bytes        ] Section Header String Table
-//   [ 64*(# shrs) bytes    ] Section Header Table
+    pub fn is_global(&self) -> bool {
+        self.symbol.binding() != file::SYMBOL_BINDING_LOCAL
+    }
 
+    pub fn is_weak(&self) -> bool {
+        self.symbol.binding() == file::SYMBOL_BINDING_WEAK
+    }
+}
+
+// An executable is a file header, then program headers, section contents and
+// section headers wherever the headers say they are. Deciding where is up to
+// the linker (see weld_core::layout); gaps are zero-filled.
 #[derive(Debug, Default)]
 pub struct Executable {
-    // Fields match final on-disk layout order
     pub file_header: file::FileHeader,
     pub program_headers: Vec<file::ProgramHeader>,
-    pub pre_text_pad: usize,
-    pub text_section: Vec<u8>,
-    pub shstrtab: string_table::StrTab,
-    pub section_headers: Vec<file::SectionHeader>, // Always last
+    pub sections: Vec<Section<'static>>, // One per section header, in the same order
+    pub section_headers: Vec<file::SectionHeader>,
 }
 
 impl Executable {
+    pub fn find_section(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|s| s.name == name)
+    }
+
     pub fn encode(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
-        bytes.extend_from_slice(as_u8_slice(&self.file_header));
+        self.file_header.encode(&mut bytes);
+        let mut table = Vec::new();
         for phdr in &self.program_headers {
-            bytes.extend_from_slice(as_u8_slice(phdr));
+            phdr.encode(&mut table);
         }
-        bytes.extend(vec![0; self.pre_text_pad]);
-        bytes.extend_from_slice(&self.text_section);
-        bytes.extend_from_slice(self.shstrtab.get_bytes());
+        write_at(&mut bytes, self.file_header.program_header_offset, &table);
+        for (section, shdr) in self.sections.iter().zip(&self.section_headers) {
+            if !section.is_nobits() {
+                write_at(&mut bytes, shdr.offset, &section.bytes);
+            }
+        }
+        table.clear();
         for shdr in &self.section_headers {
-            bytes.extend_from_slice(as_u8_slice(shdr));
+            shdr.encode(&mut table);
         }
+        write_at(&mut bytes, self.file_header.section_header_offset, &table);
         bytes
     }
 }
 
-// Function that converts to byte array.
-// Slightly modified from https://stackoverflow.com/questions/28127165/how-to-convert-struct-to-u8
-fn as_u8_slice<T: Sized>(p: &T) -> &[u8] {
-    unsafe { std::slice::from_raw_parts((p as *const T) as *const u8, ::std::mem::size_of::<T>()) }
+fn write_at(out: &mut Vec<u8>, offset: u64, data: &[u8]) {
+    let start = offset as usize;
+    if out.len() < start + data.len() {
+        out.resize(start + data.len(), 0);
+    }
+    out[start..start + data.len()].copy_from_slice(data);
 }
 
-// Sincer we're using unsafe code for encoding, we need at least some basic checks
-// as the Rust type system can't verify it for us. For example, passing `&phdr`
-// instead of `phdr` to `as_u8_slice` in `encode` will write completely broken
-// outputs (guess how I know...)
+// Field-level encoding is covered in `encoding`; this checks each piece is
+// written where its header says.
 #[cfg(test)]
 mod tests {
-    use super::Executable;
+    use super::{Executable, Section};
     use crate::file::*;
 
     #[test]
     fn trivial_encode() {
         let mut e = Executable::default();
+        e.file_header.program_header_offset = FILE_HEADER_SIZE as u64;
+        e.file_header.section_header_offset = 0x100;
         e.program_headers.push(ProgramHeader::default());
-        e.section_headers.push(SectionHeader::default());
-        e.text_section = vec![1, 2, 3];
-
-        assert_eq!(e.shstrtab.len(), 1); // Null char
-        assert_eq!(
-            e.encode().len(),
-            FILE_HEADER_SIZE + PROGRAM_HEADER_SIZE + SECTION_HEADER_SIZE + 3 + 1
-        );
+        e.sections.push(Section {
+            name: ".text".to_string(),
+            bytes: vec![1, 2, 3].into(),
+            ..Default::default()
+        });
+        e.section_headers.push(SectionHeader {
+            offset: 0x80,
+            size: 3,
+            ..Default::default()
+        });
+
+        let bytes = e.encode();
+        assert_eq!(bytes.len(), 0x100 + SECTION_HEADER_SIZE);
+        assert_eq!(&bytes[0x80..0x83], &[1, 2, 3]);
+        assert!(bytes[FILE_HEADER_SIZE + PROGRAM_HEADER_SIZE..0x80]
+            .iter()
+            .all(|&b| b == 0));
+        assert_eq!(&bytes[0x100..0x104], &[0; 4]); // sh_name
+    }
+
+    #[test]
+    fn nobits_sections_take_no_file_space() {
+        let mut e = Executable::default();
+        e.file_header.section_header_offset = 0x40;
+        e.sections.push(Section {
+            section_type: SectionType::ProgramSpaceWithNoData,
+            size: 0x1000,
+            ..Default::default()
+        });
+        e.section_headers.push(SectionHeader {
+            section_type: SectionType::ProgramSpaceWithNoData,
+            offset: 0x40,
+            size: 0x1000,
+            ..Default::default()
+        });
+        assert_eq!(e.encode().len(), 0x40 + SECTION_HEADER_SIZE);
     }
 }
@@ -0,0 +1,383 @@
+//! Builds relocatable objects (ET_REL) in memory, so tests can describe
+//! exactly the input they need rather than compiling C for it:
+//!
+//! ```
+//! use elf::file::*;
+//! use elf::logical::RelocationType;
+//! use elf::object::ObjectBuilder;
+//!
+//! let mut object = ObjectBuilder::new();
+//! let text = object.section(
+//!     ".text",
+//!     SectionFlags::Alloc | SectionFlags::Executable,
+//!     &[0xe8, 0, 0, 0, 0, 0xc3], // call f; ret
+//! );
+//! object.symbol("main", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_FUNCTION, text, 0, 6);
+//! let f = object.undefined("f");
+//! object.relocation(text, 1, RelocationType::Plt32 as u32, f, -4);
+//! let bytes = object.encode();
+//! ```
+//!
+//! Sections are numbered from 1 in the order they're added, which is also
+//! their index in the encoded file. The relocation, symbol and string table
+//! sections follow them. Locals are moved before globals as ELF requires,
+//! so symbols are referred to by what `symbol` returned, not their index in
+//! the file.
+
+use crate::encoding::Encode;
+use crate::file::*;
+use crate::logical::Section;
+use crate::string_table::StrTab;
+
+const ET_REL: u16 = 1;
+
+#[derive(Debug, Default)]
+pub struct ObjectBuilder {
+    sections: Vec<Section<'static>>,
+    symbols: Vec<(String, Symbol)>,
+    // (section, offset, type, symbol, addend)
+    relocations: Vec<(u16, u64, u32, usize, i64)>,
+    implicit_addends: bool,
+    machine: Option<u16>, // x86-64 if not given
+}
+
+impl ObjectBuilder {
+    pub fn new() -> ObjectBuilder {
+        ObjectBuilder::default()
+    }
+
+    // e_machine, e.g. MACHINE_AARCH64
+    pub fn machine(&mut self, machine: u16) {
+        self.machine = Some(machine);
+    }
+
+    // Any section at all; its index is returned
+    pub fn add_section(&mut self, section: Section<'static>) -> u16 {
+        self.sections.push(section);
+        self.sections.len() as u16
+    }
+
+    // A PROGBITS section holding `bytes`, byte-aligned
+    pub fn section(&mut self, name: &str, flags: u64, bytes: &[u8]) -> u16 {
+        self.add_section(Section {
+            name: name.to_string(),
+            section_type: SectionType::ProgramData,
+            bytes: bytes.to_vec().into(),
+            size: bytes.len() as u64,
+            flags,
+            alignment: 1,
+            ..Default::default()
+        })
+    }
+
+    // A NOBITS section (like .bss) of `size` bytes
+    pub fn nobits(&mut self, name: &str, flags: u64, size: u64) -> u16 {
+        self.add_section(Section {
+            name: name.to_string(),
+            section_type: SectionType::ProgramSpaceWithNoData,
+            size,
+            flags,
+            alignment: 1,
+            ..Default::default()
+        })
+    }
+
+    // `section` is its st_shndx: a section index, SECTION_INDEX_UNDEFINED or
+    // SECTION_INDEX_ABSOLUTE
+    pub fn symbol(
+        &mut self,
+        name: &str,
+        binding: u8,
+        symbol_type: u8,
+        section: u16,
+        value: u64,
+        size: u64,
+    ) -> usize {
+        let symbol = Symbol {
+            info: binding << 4 | symbol_type,
+            relative_to_section: section,
+            value,
+            size,
+            ..Default::default()
+        };
+        self.symbols.push((name.to_string(), symbol));
+        self.symbols.len() - 1
+    }
+
+    // A global reference to something another object defines
+    pub fn undefined(&mut self, name: &str) -> usize {
+        self.symbol(
+            name,
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_NONE,
+            SECTION_INDEX_UNDEFINED,
+            0,
+            0,
+        )
+    }
+
+    // The local symbol compilers use to refer to a section's contents
+    pub fn section_symbol(&mut self, section: u16) -> usize {
+        self.symbol("", SYMBOL_BINDING_LOCAL, SYMBOL_TYPE_SECTION, section, 0, 0)
+    }
+
+    // `relocation_type` is the raw psABI number, so types the linker
+    // doesn't know can be built too
+    pub fn relocation(
+        &mut self,
+        section: u16,
+        offset: u64,
+        relocation_type: u32,
+        symbol: usize,
+        addend: i64,
+    ) {
+        self.relocations
+            .push((section, offset, relocation_type, symbol, addend));
+    }
+
+    // Write SHT_REL sections (.rel.text and so on) rather than SHT_RELA, with
+    // each addend in the field its relocation patches. Addends for fields
+    // of unknown size, or outside their section, are left out.
+    pub fn implicit_addends(&mut self, implicit: bool) {
+        self.implicit_addends = implicit;
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        // Null symbol, locals, then globals
+        let mut order: Vec<usize> = (0..self.symbols.len()).collect();
+        order.sort_by_key(|&i| self.symbols[i].1.binding() != SYMBOL_BINDING_LOCAL);
+        let mut index = vec![0; self.symbols.len()];
+        for (position, &i) in order.iter().enumerate() {
+            index[i] = position + 1;
+        }
+        let first_global = 1 + order
+            .iter()
+            .take_while(|&&i| self.symbols[i].1.binding() == SYMBOL_BINDING_LOCAL)
+            .count();
+
+        let machine = self.machine.unwrap_or(MACHINE_X86_64);
+        let mut sections: Vec<(String, SectionHeader, Vec<u8>)> = self
+            .sections
+            .iter()
+            .map(|s| {
+                let header = SectionHeader {
+                    section_type: s.section_type,
+                    flags: s.flags,
+                    size: s.size,
+                    address_allignment_boundary: s.alignment,
+                    ..Default::default()
+                };
+                (s.name.clone(), header, s.bytes.to_vec())
+            })
+            .collect();
+        let symtab = (sections.len() + 1 + self.relocated().len()) as u32;
+
+        for section in self.relocated() {
+            let mut table = Vec::new();
+            for &(_, offset, relocation_type, symbol, addend) in
+                self.relocations.iter().filter(|r| r.0 == section)
+            {
+                let info = (index[symbol] as u64) << 32 | relocation_type as u64;
+                if !self.implicit_addends {
+                    RelocationWithAddend {
+                        offset,
+                        info,
+                        addend,
+                    }
+                    .encode(&mut table);
+                    continue;
+                }
+                RelocationWithoutAddend { offset, info }.encode(&mut table);
+                let bytes = &mut sections[section as usize - 1].2;
+                let field = relocation_field(machine, relocation_type.into())
+                    .and_then(|(size, _)| bytes.get_mut(offset as usize..)?.get_mut(..size));
+                if let Some(field) = field {
+                    let size = field.len();
+                    field.copy_from_slice(&addend.to_le_bytes()[..size]);
+                }
+            }
+            let (kind, prefix, entry_size) = match self.implicit_addends {
+                true => (
+                    SectionType::RelocationWithoutAddend,
+                    ".rel",
+                    RELOCATION_WITHOUT_ADDEND_SIZE,
+                ),
+                false => (
+                    SectionType::RelocationWithAddend,
+                    ".rela",
+                    RELOCATION_WITH_ADDEND_SIZE,
+                ),
+            };
+            let header = SectionHeader {
+                section_type: kind,
+                flags: SectionFlags::InfoLink as u64,
+                size: table.len() as u64,
+                link_to_other_section: symtab,
+                misc_info: section as u32,
+                address_allignment_boundary: 8,
+                entry_size: entry_size as u64,
+                ..Default::default()
+            };
+            let name = format!("{prefix}{}", self.sections[section as usize - 1].name);
+            sections.push((name, header, table));
+        }
+
+        let mut names = StrTab::default();
+        let mut table = Symbol::default().to_bytes();
+        for &i in &order {
+            let (name, symbol) = &self.symbols[i];
+            let name = match name.is_empty() {
+                true => 0,
+                false => names.insert(name) as u32,
+            };
+            Symbol {
+                name,
+                ..symbol.clone()
+            }
+            .encode(&mut table);
+        }
+        let header = SectionHeader {
+            section_type: SectionType::SymbolTable,
+            size: table.len() as u64,
+            link_to_other_section: symtab + 1,
+            misc_info: first_global as u32,
+            address_allignment_boundary: 8,
+            entry_size: SYMBOL_SIZE as u64,
+            ..Default::default()
+        };
+        sections.push((".symtab".to_string(), header, table));
+        let header = SectionHeader {
+            section_type: SectionType::StringTable,
+            size: names.len() as u64,
+            address_allignment_boundary: 1,
+            ..Default::default()
+        };
+        sections.push((".strtab".to_string(), header, names.get_bytes().to_vec()));
+        let header = SectionHeader {
+            section_type: SectionType::StringTable,
+            address_allignment_boundary: 1,
+            ..Default::default()
+        };
+        sections.push((".shstrtab".to_string(), header, Vec::new()));
+
+        // .shstrtab names itself, so it's filled in last
+        let mut section_names = StrTab::default();
+        for (name, header, _) in &mut sections {
+            header.name = section_names.insert(name) as u32;
+        }
+        let shstrtab = sections.last_mut().unwrap();
+        shstrtab.1.size = section_names.len() as u64;
+        shstrtab.2 = section_names.get_bytes().to_vec();
+
+        let mut contents = vec![0; FILE_HEADER_SIZE];
+        for (_, header, bytes) in &mut sections {
+            if header.section_type == SectionType::ProgramSpaceWithNoData {
+                header.offset = contents.len() as u64;
+                continue;
+            }
+            let offset = contents
+                .len()
+                .next_multiple_of(header.address_allignment_boundary.max(1) as usize);
+            contents.resize(offset, 0);
+            header.offset = offset as u64;
+            contents.extend_from_slice(bytes);
+        }
+        let section_header_offset = contents.len().next_multiple_of(8);
+        contents.resize(section_header_offset, 0);
+        SectionHeader::default().encode(&mut contents);
+        for (_, header, _) in &sections {
+            header.encode(&mut contents);
+        }
+
+        let mut header = FileHeader::default();
+        header.identification.magic = [0x7f, 0x45, 0x4c, 0x46];
+        header.identification.format_class = 2; // 64-bit
+        header.identification.endianness = 1; // little-endian
+        header.identification.format_version = 1; // original ELF
+        header.object_file_type = ET_REL;
+        header.machine_type = machine;
+        header.object_file_version = 1;
+        header.file_header_size = FILE_HEADER_SIZE as u16;
+        header.section_header_offset = section_header_offset as u64;
+        header.section_headers_total_size = SECTION_HEADER_SIZE as u16;
+        header.section_header_entry_count = sections.len() as u16 + 1;
+        header.sh_section_name_stringtab_entry_index = sections.len() as u16;
+        contents[..FILE_HEADER_SIZE].copy_from_slice(&header.to_bytes());
+        contents
+    }
+
+    // Sections with relocations against them, in order
+    fn relocated(&self) -> Vec<u16> {
+        let mut sections: Vec<u16> = self.relocations.iter().map(|r| r.0).collect();
+        sections.sort_unstable();
+        sections.dedup();
+        sections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::Decode;
+
+    #[test]
+    fn layout_follows_the_gabi() {
+        let mut object = ObjectBuilder::new();
+        let text = object.section(
+            ".text",
+            SectionFlags::Alloc | SectionFlags::Executable,
+            &[0; 8],
+        );
+        object.nobits(".bss", SectionFlags::Alloc | SectionFlags::Write, 16);
+        let f = object.undefined("f");
+        let local = object.section_symbol(text);
+        object.relocation(text, 4, 2, f, -4);
+        object.relocation(text, 0, 10, local, 0);
+        let bytes = object.encode();
+
+        let header = FileHeader::decode(&bytes).unwrap();
+        assert_eq!((header.object_file_type, header.machine_type), (1, 0x3e));
+        // null, .text, .bss, .rela.text, .symtab, .strtab, .shstrtab
+        assert_eq!(header.section_header_entry_count, 7);
+        assert_eq!(header.sh_section_name_stringtab_entry_index, 6);
+        let section = |i: usize| {
+            let at = header.section_header_offset as usize + i * SECTION_HEADER_SIZE;
+            SectionHeader::decode(&bytes[at..]).unwrap()
+        };
+        let contents =
+            |shdr: &SectionHeader| &bytes[shdr.offset as usize..(shdr.offset + shdr.size) as usize];
+
+        let rela = section(3);
+        assert_eq!(rela.section_type, SectionType::RelocationWithAddend);
+        assert_eq!((rela.link_to_other_section, rela.misc_info), (4, 1));
+        let relocations: Vec<RelocationWithAddend> = contents(&rela)
+            .chunks_exact(RELOCATION_WITH_ADDEND_SIZE)
+            .map(|entry| RelocationWithAddend::decode(entry).unwrap())
+            .collect();
+        // The section symbol, a local, comes first; f after it
+        assert_eq!(
+            (relocations[0].symbol(), relocations[0].relo_type()),
+            (2, 2)
+        );
+        assert_eq!(
+            (relocations[1].symbol(), relocations[1].relo_type()),
+            (1, 10)
+        );
+
+        let symtab = section(4);
+        assert_eq!((symtab.link_to_other_section, symtab.misc_info), (5, 2));
+        let symbols: Vec<Symbol> = contents(&symtab)
+            .chunks_exact(SYMBOL_SIZE)
+            .map(|entry| Symbol::decode(entry).unwrap())
+            .collect();
+        assert_eq!(symbols[0], Symbol::default());
+        assert_eq!(symbols[1].symbol_type(), SYMBOL_TYPE_SECTION);
+        let names = StrTab::new(contents(&section(5)));
+        assert_eq!(names.get(symbols[2].name as usize).unwrap(), "f");
+
+        let section_names = StrTab::new(contents(&section(6)));
+        assert_eq!(section_names.get(section(2).name as usize).unwrap(), ".bss");
+        assert_eq!(section(2).size, 16);
+        assert_eq!(section(3).offset % 8, 0);
+    }
+}
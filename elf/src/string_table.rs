@@ -16,15 +16,9 @@ impl StrTab {
     }
 
     pub fn get(&self, i: usize) -> Option<String> {
-        let mut buffer = String::new();
-        for j in i..self.bytes.len() {
-            let c = self.bytes[j];
-            if c == 0 {
-                return Some(buffer);
-            }
-            buffer.push(c as char);
-        }
-        None
+        let tail = self.bytes.get(i..)?;
+        let len = tail.iter().position(|&c| c == 0)?;
+        Some(String::from_utf8_lossy(&tail[..len]).into_owned())
     }
 
     pub fn insert(&mut self, s: &str) -> usize {
@@ -38,6 +32,10 @@ impl StrTab {
         self.bytes.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
     pub fn get_bytes(&self) -> &[u8] {
         &self.bytes
     }
@@ -74,5 +72,12 @@ mod tests {
         let st = StrTab::new(&buf);
         assert_eq!(st.get(0), Some("".to_string()));
         assert_eq!(st.get(1), Some("ABC".to_string()));
+        assert_eq!(st.get(5), None);
+    }
+
+    #[test]
+    fn utf8_names() {
+        let st = StrTab::new("\0caf\u{e9}\0".as_bytes());
+        assert_eq!(st.get(1), Some("caf\u{e9}".to_string()));
     }
 }
@@ -0,0 +1,158 @@
+//! `ar` archives (static libraries), in the GNU and BSD variants. Member
+//! contents are borrowed from the archive. The symbol index and GNU long
+//! name table are consumed here rather than returned as members.
+
+use crate::{slice, ParseError};
+
+pub const MAGIC: &[u8; 8] = b"!<arch>\n";
+const THIN_MAGIC: &[u8; 8] = b"!<thin>\n";
+const HEADER_SIZE: u64 = 60;
+const HEADER_END: &[u8; 2] = b"`\n";
+
+#[derive(Debug, PartialEq)]
+pub struct Member<'a> {
+    pub name: String,
+    pub bytes: &'a [u8],
+}
+
+pub fn is_archive(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC) || bytes.starts_with(THIN_MAGIC)
+}
+
+pub fn parse_archive(bytes: &[u8]) -> Result<Vec<Member<'_>>, ParseError> {
+    if bytes.starts_with(THIN_MAGIC) {
+        return Err(ParseError::BadArchive(
+            "thin archives are not supported".to_string(),
+        ));
+    }
+    if !bytes.starts_with(MAGIC) {
+        return Err(ParseError::BadArchive("bad magic".to_string()));
+    }
+
+    let mut members = Vec::new();
+    let mut long_names: &[u8] = &[];
+    let mut offset = MAGIC.len() as u64;
+    while offset < bytes.len() as u64 {
+        let header = slice(bytes, offset, HEADER_SIZE, || {
+            format!("archive member header at {offset:#x}")
+        })?;
+        if &header[58..60] != HEADER_END {
+            return Err(ParseError::BadArchive(format!(
+                "member header at {offset:#x} is corrupt"
+            )));
+        }
+        let field = |range: std::ops::Range<usize>| {
+            String::from_utf8_lossy(&header[range])
+                .trim_end()
+                .to_string()
+        };
+        let size: u64 = field(48..58)
+            .parse()
+            .map_err(|_| ParseError::BadArchive(format!("member at {offset:#x} has a bad size")))?;
+        let data = slice(bytes, offset + HEADER_SIZE, size, || {
+            format!("archive member at {offset:#x}")
+        })?;
+        // Members start on even offsets
+        offset = (offset + HEADER_SIZE + size).next_multiple_of(2);
+
+        let name = field(0..16);
+        let (name, data) = match name.as_str() {
+            // Symbol index
+            "/" | "/SYM64/" | "__.SYMDEF" | "__.SYMDEF SORTED" => continue,
+            "//" => {
+                long_names = data;
+                continue;
+            }
+            _ => {
+                if let Some(at) = name.strip_prefix("#1/") {
+                    // BSD: the name is the first `at` bytes of the data
+                    let length: usize =
+                        at.parse()
+                            .ok()
+                            .filter(|&n| n <= data.len())
+                            .ok_or_else(|| {
+                                ParseError::BadArchive(format!(
+                                    "member at {offset:#x} has a bad name"
+                                ))
+                            })?;
+                    let name = String::from_utf8_lossy(&data[..length]);
+                    (name.trim_end_matches('\0').to_string(), &data[length..])
+                } else if let Some(at) = name.strip_prefix('/') {
+                    // GNU: an offset into the long name table, where names end in "/\n"
+                    let name = at
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|at| long_names.get(at..))
+                        .and_then(|rest| rest.split(|&b| b == b'\n').next())
+                        .ok_or_else(|| {
+                            ParseError::BadArchive(format!(
+                                "member at {offset:#x} has a bad long name"
+                            ))
+                        })?;
+                    let name = String::from_utf8_lossy(name);
+                    (name.trim_end_matches('/').to_string(), data)
+                } else {
+                    (name.trim_end_matches('/').to_string(), data)
+                }
+            }
+        };
+        members.push(Member { name, bytes: data });
+    }
+    Ok(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut bytes = format!(
+            "{name:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+            0,
+            0,
+            0,
+            644,
+            data.len()
+        )
+        .into_bytes();
+        bytes.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            bytes.push(b'\n');
+        }
+        bytes
+    }
+
+    #[test]
+    fn gnu_archive() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(member("/", &[0; 8]));
+        bytes.extend(member("//", b"a_rather_long_file_name.o/\n"));
+        bytes.extend(member("short.o/", b"abc"));
+        bytes.extend(member("/0", b"defg"));
+        assert_eq!(
+            parse_archive(&bytes).unwrap(),
+            [
+                Member {
+                    name: "short.o".to_string(),
+                    bytes: b"abc"
+                },
+                Member {
+                    name: "a_rather_long_file_name.o".to_string(),
+                    bytes: b"defg"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn bsd_names_and_truncation() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(member("#1/8", b"bsd.o\0\0\0xyz"));
+        let members = parse_archive(&bytes).unwrap();
+        assert_eq!(members[0].name, "bsd.o");
+        assert_eq!(members[0].bytes, b"xyz");
+
+        assert!(parse_archive(&bytes[..bytes.len() - 4]).is_err());
+        assert!(parse_archive(b"!<thin>\n").is_err());
+    }
+}
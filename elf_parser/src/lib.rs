@@ -1,148 +1,875 @@
+//! Parses relocatable objects into `elf::logical::Relocatable`s, and any
+//! ELF file into an `ElfFile` for tools that inspect rather than link.
+//!
+//! Inputs can come from any compiler (or be truncated, or malicious), so
+//! every offset and size read from the file is checked against the buffer
+//! before it is used, and structures are decoded field by field rather than
+//! by casting pointers into the buffer.
+
+use elf::encoding::{Decode, DecodeError};
+use std::fmt;
 use std::vec::Vec;
 
-pub fn parse(path: &str, bytes: &[u8]) -> elf::logical::Relocatable {
-    let header = parse_header(bytes);
-    let section_headers = parse_section_headers(bytes, &header);
-    let section_names = parse_section_name_string_table(bytes, &section_headers, &header);
-    let symbols = parse_symbol_table(bytes, &section_headers, &header);
-    let relocations = parse_relocations(bytes, &section_headers, &symbols);
+pub mod archive;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ET_REL: u16 = 1;
+const EM_X86_64: u16 = 0x3e;
+const EM_AARCH64: u16 = 0xb7;
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    NotElf,
+    UnsupportedClass(u8),
+    UnsupportedEndianness(u8),
+    UnsupportedMachine(u16),
+    NotRelocatable(u16),
+    PastEndOfFile(String),
+    PastEndOfSection(String),
+    BadEntrySize { what: String, size: u64 },
+    UnknownSectionType { index: usize, section_type: u32 },
+    BadSectionIndex { what: String, index: usize },
+    BadSymbolIndex { what: String, index: usize },
+    BadName { what: String, offset: usize },
+    UnknownRelocationType { what: String, relocation_type: u64 },
+    BadArchive(String),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::NotElf => write!(f, "not an ELF file (bad magic)"),
+            ParseError::UnsupportedClass(class) => {
+                write!(
+                    f,
+                    "unsupported ELF class {class} (only 64-bit is supported)"
+                )
+            }
+            ParseError::UnsupportedEndianness(data) => write!(
+                f,
+                "unsupported data encoding {data} (only little-endian is supported)"
+            ),
+            ParseError::UnsupportedMachine(machine) => {
+                write!(
+                    f,
+                    "unsupported machine {machine:#x} (only x86-64 and AArch64 are supported)"
+                )
+            }
+            ParseError::NotRelocatable(file_type) => {
+                write!(f, "not a relocatable object (ELF type {file_type})")
+            }
+            ParseError::PastEndOfFile(what) => write!(f, "{what} extends past end of file"),
+            ParseError::PastEndOfSection(what) => {
+                write!(f, "{what} extends past the end of its section")
+            }
+            ParseError::BadEntrySize { what, size } => {
+                write!(f, "{what} has unexpected entry size {size}")
+            }
+            ParseError::UnknownSectionType {
+                index,
+                section_type,
+            } => write!(
+                f,
+                "section header {index} has unknown type {section_type:#x}"
+            ),
+            ParseError::BadSectionIndex { what, index } => {
+                write!(f, "{what} refers to section {index}, which does not exist")
+            }
+            ParseError::BadSymbolIndex { what, index } => {
+                write!(f, "{what} refers to symbol {index}, which does not exist")
+            }
+            ParseError::BadName { what, offset } => write!(
+                f,
+                "{what} has name offset {offset}, which is outside its string table"
+            ),
+            ParseError::UnknownRelocationType {
+                what,
+                relocation_type,
+            } => write!(
+                f,
+                "{what} has relocation type {relocation_type}, whose addend can't be read"
+            ),
+            ParseError::BadArchive(reason) => write!(f, "malformed archive: {reason}"),
+            ParseError::Decode(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Section contents are borrowed from `bytes`, not copied
+pub fn parse<'a>(path: &str, bytes: &'a [u8]) -> Result<elf::logical::Relocatable<'a>, ParseError> {
+    let header = parse_header(bytes)?;
+    if !matches!(header.machine_type, EM_X86_64 | EM_AARCH64) {
+        return Err(ParseError::UnsupportedMachine(header.machine_type));
+    }
+    if header.object_file_type != ET_REL {
+        return Err(ParseError::NotRelocatable(header.object_file_type));
+    }
+    let section_headers = parse_section_headers(bytes, &header)?;
+    let section_names = parse_section_name_string_table(bytes, &section_headers, &header)?;
+    let symbols = parse_symbol_table(bytes, &section_headers)?;
+    let relocations = parse_relocations(bytes, header.machine_type, &section_headers, &symbols)?;
 
     let mut result = elf::logical::Relocatable {
         path: path.to_string(),
+        machine: header.machine_type,
         symbols,
         relocations,
         ..Default::default()
     };
 
-    for shdr in section_headers {
+    for (i, shdr) in section_headers.iter().enumerate() {
+        let name = match &section_names {
+            Some(names) => names.get(shdr.name as usize).ok_or(ParseError::BadName {
+                what: format!("section header {i}"),
+                offset: shdr.name as usize,
+            })?,
+            None => String::new(),
+        };
+        // SHT_NOBITS sections (.bss) occupy no space in the file
+        let bytes = match shdr.section_type {
+            elf::file::SectionType::ProgramSpaceWithNoData => Default::default(),
+            _ => section_bytes(bytes, shdr, i)?.into(),
+        };
         let section = elf::logical::Section {
-            name: section_names.get(shdr.name as usize).unwrap().clone(),
-            bytes: bytes[(shdr.offset as usize)..((shdr.offset as usize) + (shdr.size as usize))]
-                .to_vec(),
+            name,
+            section_type: shdr.section_type,
+            bytes,
+            size: shdr.size,
             offset: shdr.offset,
             virtual_address: shdr.virtual_address,
+            flags: shdr.flags,
+            alignment: shdr.address_allignment_boundary,
         };
         result.sections.push(section);
     }
 
-    result
+    Ok(result)
 }
 
-fn parse_header(bytes: &[u8]) -> elf::file::FileHeader {
-    let hdr_bytes = &bytes[..elf::file::FILE_HEADER_SIZE];
-    let header: elf::file::FileHeader = unsafe { std::ptr::read(hdr_bytes.as_ptr() as *const _) };
-    header
+// `bytes` must already be bounds-checked; `index` identifies the entry in errors
+fn decode<T: Decode>(bytes: &[u8], index: usize) -> Result<T, ParseError> {
+    T::decode(bytes).map_err(|err| match err {
+        DecodeError::UnknownSectionType(section_type) => ParseError::UnknownSectionType {
+            index,
+            section_type,
+        },
+        err => ParseError::Decode(err),
+    })
 }
 
-fn parse_section_headers(
+// Returns `bytes[offset..offset + size]`, or an error describing `what` overran the file
+fn slice(
     bytes: &[u8],
-    file_header: &elf::file::FileHeader,
-) -> Vec<elf::file::SectionHeader> {
-    let mut section_headers = Vec::new();
+    offset: u64,
+    size: u64,
+    what: impl FnOnce() -> String,
+) -> Result<&[u8], ParseError> {
+    let end = offset.checked_add(size);
+    match end {
+        Some(end) if end <= bytes.len() as u64 => Ok(&bytes[offset as usize..end as usize]),
+        _ => Err(ParseError::PastEndOfFile(what())),
+    }
+}
+
+fn section_bytes<'a>(
+    bytes: &'a [u8],
+    header: &elf::file::SectionHeader,
+    index: usize,
+) -> Result<&'a [u8], ParseError> {
+    slice(bytes, header.offset, header.size, || {
+        format!("section {index}")
+    })
+}
 
-    for i in 0..file_header.section_header_entry_count {
-        let base: usize = (file_header.section_header_offset as usize)
-            + (i as usize) * elf::file::SECTION_HEADER_SIZE;
-        let shdr: elf::file::SectionHeader =
-            unsafe { std::ptr::read(bytes[base..].as_ptr() as *const _) };
-        section_headers.push(shdr);
+fn parse_header(bytes: &[u8]) -> Result<elf::file::FileHeader, ParseError> {
+    if bytes.len() < ELF_MAGIC.len() || bytes[..ELF_MAGIC.len()] != ELF_MAGIC {
+        return Err(ParseError::NotElf);
     }
-    section_headers
+    let header = decode::<elf::file::FileHeader>(
+        slice(bytes, 0, elf::file::FILE_HEADER_SIZE as u64, || {
+            "file header".to_string()
+        })?,
+        0,
+    )?;
+
+    if header.identification.format_class != ELFCLASS64 {
+        return Err(ParseError::UnsupportedClass(
+            header.identification.format_class,
+        ));
+    }
+    if header.identification.endianness != ELFDATA2LSB {
+        return Err(ParseError::UnsupportedEndianness(
+            header.identification.endianness,
+        ));
+    }
+    if header.section_header_entry_count > 0
+        && header.section_headers_total_size as usize != elf::file::SECTION_HEADER_SIZE
+    {
+        return Err(ParseError::BadEntrySize {
+            what: "section header table".to_string(),
+            size: header.section_headers_total_size as u64,
+        });
+    }
+    if header.program_header_entry_count > 0
+        && header.program_headers_total_size as usize != elf::file::PROGRAM_HEADER_SIZE
+    {
+        return Err(ParseError::BadEntrySize {
+            what: "program header table".to_string(),
+            size: header.program_headers_total_size as u64,
+        });
+    }
+    Ok(header)
 }
 
-fn parse_symbol_string_table(
+fn parse_program_headers(
     bytes: &[u8],
-    section_headers: &[elf::file::SectionHeader],
     file_header: &elf::file::FileHeader,
-) -> elf::string_table::StrTab {
-    let shstrtab_header =
-        &section_headers[file_header.sh_section_name_stringtab_entry_index as usize];
+) -> Result<Vec<elf::file::ProgramHeader>, ParseError> {
+    let mut program_headers = Vec::new();
 
-    let header = section_headers
-        .iter()
-        .find(|&hdr| {
-            matches!(hdr.section_type, elf::file::SectionType::StringTable)
-                && hdr != shstrtab_header
-        })
-        .unwrap();
+    for i in 0..file_header.program_header_entry_count as usize {
+        let base = file_header
+            .program_header_offset
+            .saturating_add((i * elf::file::PROGRAM_HEADER_SIZE) as u64);
+        let header_bytes = slice(bytes, base, elf::file::PROGRAM_HEADER_SIZE as u64, || {
+            format!("program header {i}")
+        })?;
+        program_headers.push(decode(header_bytes, i)?);
+    }
+    Ok(program_headers)
+}
 
-    parse_string_table(bytes, header)
+fn parse_section_headers(
+    bytes: &[u8],
+    file_header: &elf::file::FileHeader,
+) -> Result<Vec<elf::file::SectionHeader>, ParseError> {
+    let mut section_headers = Vec::new();
+
+    for i in 0..file_header.section_header_entry_count as usize {
+        let base = file_header
+            .section_header_offset
+            .saturating_add((i * elf::file::SECTION_HEADER_SIZE) as u64);
+        let header_bytes = slice(bytes, base, elf::file::SECTION_HEADER_SIZE as u64, || {
+            format!("section header {i}")
+        })?;
+        section_headers.push(decode(header_bytes, i)?);
+    }
+    Ok(section_headers)
 }
 
 fn parse_string_table(
     bytes: &[u8],
-    header: &elf::file::SectionHeader,
-) -> elf::string_table::StrTab {
-    elf::string_table::StrTab::new(
-        &bytes[(header.offset as usize)..((header.offset + header.size) as usize)],
-    )
+    section_headers: &[elf::file::SectionHeader],
+    index: usize,
+    what: impl FnOnce() -> String,
+) -> Result<elf::string_table::StrTab, ParseError> {
+    let header = section_headers
+        .get(index)
+        .ok_or_else(|| ParseError::BadSectionIndex {
+            what: what(),
+            index,
+        })?;
+    Ok(elf::string_table::StrTab::new(section_bytes(
+        bytes, header, index,
+    )?))
 }
 
+// Objects without section names are unusual but legal (e_shstrndx is SHN_UNDEF)
 fn parse_section_name_string_table(
     bytes: &[u8],
     section_headers: &[elf::file::SectionHeader],
     file_header: &elf::file::FileHeader,
-) -> elf::string_table::StrTab {
-    let section_header =
-        &section_headers[file_header.sh_section_name_stringtab_entry_index as usize];
-    parse_string_table(bytes, section_header)
+) -> Result<Option<elf::string_table::StrTab>, ParseError> {
+    match file_header.sh_section_name_stringtab_entry_index as usize {
+        0 => Ok(None),
+        index => parse_string_table(bytes, section_headers, index, || {
+            "file header's section name table index".to_string()
+        })
+        .map(Some),
+    }
 }
 
+// An object without a symbol table has no symbols, and so can't have relocations either
 fn parse_symbol_table(
     bytes: &[u8],
     section_headers: &[elf::file::SectionHeader],
-    file_header: &elf::file::FileHeader,
-) -> Vec<elf::logical::SymbolInfo> {
-    let header = section_headers
+) -> Result<Vec<elf::logical::SymbolInfo>, ParseError> {
+    let Some(index) = section_headers
         .iter()
-        .find(|&hdr| matches!(hdr.section_type, elf::file::SectionType::SymbolTable))
-        .unwrap();
+        .position(|hdr| matches!(hdr.section_type, elf::file::SectionType::SymbolTable))
+    else {
+        return Ok(Vec::new());
+    };
+    parse_symbols(bytes, section_headers, index)
+}
+
+// The symbols in section `index`, a .symtab or .dynsym
+fn parse_symbols(
+    bytes: &[u8],
+    section_headers: &[elf::file::SectionHeader],
+    index: usize,
+) -> Result<Vec<elf::logical::SymbolInfo>, ParseError> {
+    let header = &section_headers[index];
+    if header.entry_size as usize != elf::file::SYMBOL_SIZE {
+        return Err(ParseError::BadEntrySize {
+            what: format!("symbol table (section {index})"),
+            size: header.entry_size,
+        });
+    }
 
-    let num_symbols = (header.size as usize) / std::mem::size_of::<elf::file::Symbol>();
-    let symbol_names = parse_symbol_string_table(bytes, section_headers, file_header);
+    let table = section_bytes(bytes, header, index)?;
+    let symbol_names = parse_string_table(
+        bytes,
+        section_headers,
+        header.link_to_other_section as usize,
+        || format!("symbol table (section {index})"),
+    )?;
     let mut symbols = Vec::new();
 
-    for i in 0..num_symbols {
-        let sym_bytes =
-            &bytes[(header.offset as usize) + i * std::mem::size_of::<elf::file::Symbol>()..];
-        let symbol: elf::file::Symbol = unsafe { std::ptr::read(sym_bytes.as_ptr() as *const _) };
-        let symbol_info = elf::logical::SymbolInfo {
-            name: symbol_names.get(symbol.name as usize).unwrap().clone(),
-            symbol,
-        };
-        symbols.push(symbol_info.clone());
+    for (i, entry) in table.chunks_exact(elf::file::SYMBOL_SIZE).enumerate() {
+        let symbol: elf::file::Symbol = decode(entry, i)?;
+        let name = symbol_names
+            .get(symbol.name as usize)
+            .ok_or(ParseError::BadName {
+                what: format!("symbol {i}"),
+                offset: symbol.name as usize,
+            })?;
+        symbols.push(elf::logical::SymbolInfo { name, symbol });
     }
-    symbols
+    Ok(symbols)
 }
 
+// SHT_RELA and SHT_REL sections alike. An SHT_REL relocation's addend is
+// read from the bytes it relocates, so all of them come out with one.
 fn parse_relocations(
     bytes: &[u8],
+    machine: u16,
     section_headers: &[elf::file::SectionHeader],
     symbol_table: &[elf::logical::SymbolInfo],
-) -> Vec<elf::logical::Relocation> {
+) -> Result<Vec<elf::logical::Relocation>, ParseError> {
     let mut relocations = Vec::new();
 
-    let header_or = section_headers.iter().find(|&hdr| {
+    // Each relocation section applies to the section indexed by its sh_info
+    let headers = section_headers.iter().enumerate().filter(|(_, hdr)| {
         matches!(
             hdr.section_type,
             elf::file::SectionType::RelocationWithAddend
+                | elf::file::SectionType::RelocationWithoutAddend
         )
     });
 
-    let Some(header) = header_or else {
-        return relocations;
+    for (index, header) in headers {
+        let target = header.misc_info as usize;
+        if target >= section_headers.len() {
+            return Err(ParseError::BadSectionIndex {
+                what: format!("relocation section {index}"),
+                index: target,
+            });
+        }
+        let entries = relocation_entries(bytes, machine, section_headers, index)?;
+        for (i, r) in entries.iter().enumerate() {
+            if r.symbol() >= symbol_table.len() {
+                return Err(ParseError::BadSymbolIndex {
+                    what: format!("relocation {i} in section {index}"),
+                    index: r.symbol(),
+                });
+            }
+            relocations.push(elf::logical::Relocation::from(r, target));
+        }
+    }
+
+    Ok(relocations)
+}
+
+// The entries of relocation section `index`, SHT_RELA or SHT_REL, all with
+// addends. An SHT_REL entry's is read from the bytes it relocates in the
+// section sh_info names; dynamic relocations, which name none, get 0.
+fn relocation_entries(
+    bytes: &[u8],
+    machine: u16,
+    section_headers: &[elf::file::SectionHeader],
+    index: usize,
+) -> Result<Vec<elf::file::RelocationWithAddend>, ParseError> {
+    let header = &section_headers[index];
+    let explicit = header.section_type == elf::file::SectionType::RelocationWithAddend;
+    let entry_size = match explicit {
+        true => elf::file::RELOCATION_WITH_ADDEND_SIZE,
+        false => elf::file::RELOCATION_WITHOUT_ADDEND_SIZE,
+    };
+    if header.entry_size as usize != entry_size {
+        return Err(ParseError::BadEntrySize {
+            what: format!("relocation section {index}"),
+            size: header.entry_size,
+        });
+    }
+    let table = section_bytes(bytes, header, index)?;
+    if explicit {
+        return table
+            .chunks_exact(entry_size)
+            .enumerate()
+            .map(|(i, entry)| decode(entry, i))
+            .collect();
+    }
+
+    let target = header.misc_info as usize;
+    let target_bytes = match section_headers.get(target) {
+        _ if target == 0 => None,
+        None => {
+            return Err(ParseError::BadSectionIndex {
+                what: format!("relocation section {index}"),
+                index: target,
+            })
+        }
+        Some(shdr) if shdr.section_type == elf::file::SectionType::ProgramSpaceWithNoData => {
+            Some(&[][..])
+        }
+        Some(shdr) => Some(section_bytes(bytes, shdr, target)?),
     };
-    let num_relocs = header.size as usize / std::mem::size_of::<elf::file::RelocationWithAddend>();
-    for i in 0..num_relocs {
-        let base =
-            (header.offset as usize) + i * std::mem::size_of::<elf::file::RelocationWithAddend>();
-        let r: elf::file::RelocationWithAddend =
-            unsafe { std::ptr::read(bytes[base..].as_ptr() as *const _) };
-        relocations.push(elf::logical::Relocation::from(
-            &r,
-            &symbol_table[r.symbol()],
-        ));
+    let mut entries = Vec::new();
+    for (i, entry) in table.chunks_exact(entry_size).enumerate() {
+        let r: elf::file::RelocationWithoutAddend = decode(entry, i)?;
+        let addend = match target_bytes {
+            Some(target_bytes) => {
+                implicit_addend(machine, target_bytes, r.offset, r.relo_type(), || {
+                    format!("relocation {i} in section {index}")
+                })?
+            }
+            None => 0,
+        };
+        entries.push(elf::file::RelocationWithAddend {
+            offset: r.offset,
+            info: r.info,
+            addend,
+        });
+    }
+    Ok(entries)
+}
+
+// The addend an SHT_REL relocation of `relocation_type` keeps in the field at
+// `offset` in `section`, sign-extended if the field is signed
+fn implicit_addend(
+    machine: u16,
+    section: &[u8],
+    offset: u64,
+    relocation_type: u64,
+    what: impl Fn() -> String,
+) -> Result<i64, ParseError> {
+    let (size, signed) =
+        elf::file::relocation_field(machine, relocation_type).ok_or_else(|| {
+            ParseError::UnknownRelocationType {
+                what: what(),
+                relocation_type,
+            }
+        })?;
+    let field = offset
+        .checked_add(size as u64)
+        .and_then(|end| section.get(offset as usize..end as usize))
+        .ok_or_else(|| ParseError::PastEndOfSection(format!("{}'s addend", what())))?;
+    let mut value = [0; 8];
+    value[..size].copy_from_slice(field);
+    let value = u64::from_le_bytes(value);
+    let unused = 64 - 8 * size as u32;
+    Ok(match signed {
+        true => (value << unused) as i64 >> unused,
+        false => value as i64,
+    })
+}
+
+// A whole ELF file as its headers describe it. Unlike `parse`, this accepts
+// executables and shared objects and any machine, reads every symbol table,
+// and keeps relocations as they are in the file.
+#[derive(Debug)]
+pub struct ElfFile<'a> {
+    pub header: elf::file::FileHeader,
+    pub section_headers: Vec<elf::file::SectionHeader>,
+    pub section_names: Vec<String>,
+    pub program_headers: Vec<elf::file::ProgramHeader>,
+    // (section index, its symbols) for .symtab and .dynsym
+    pub symbol_tables: Vec<(usize, Vec<elf::logical::SymbolInfo>)>,
+    // (section index, its entries) for each SHT_RELA and SHT_REL section.
+    // SHT_REL entries have the addend they keep in the relocated bytes.
+    pub relocation_tables: Vec<(usize, Vec<elf::file::RelocationWithAddend>)>,
+    pub bytes: &'a [u8],
+}
+
+impl<'a> ElfFile<'a> {
+    // The contents of section `index`; nothing for SHT_NOBITS
+    pub fn section_bytes(&self, index: usize) -> Result<&'a [u8], ParseError> {
+        let header =
+            self.section_headers
+                .get(index)
+                .ok_or_else(|| ParseError::BadSectionIndex {
+                    what: "section lookup".to_string(),
+                    index,
+                })?;
+        match header.section_type {
+            elf::file::SectionType::ProgramSpaceWithNoData => Ok(&[]),
+            _ => section_bytes(self.bytes, header, index),
+        }
+    }
+}
+
+pub fn parse_file(bytes: &[u8]) -> Result<ElfFile<'_>, ParseError> {
+    let header = parse_header(bytes)?;
+    let section_headers = parse_section_headers(bytes, &header)?;
+    let program_headers = parse_program_headers(bytes, &header)?;
+    let section_names = match parse_section_name_string_table(bytes, &section_headers, &header)? {
+        Some(names) => section_headers
+            .iter()
+            .enumerate()
+            .map(|(i, shdr)| {
+                names.get(shdr.name as usize).ok_or(ParseError::BadName {
+                    what: format!("section header {i}"),
+                    offset: shdr.name as usize,
+                })
+            })
+            .collect::<Result<_, _>>()?,
+        None => vec![String::new(); section_headers.len()],
+    };
+
+    let mut symbol_tables = Vec::new();
+    let mut relocation_tables = Vec::new();
+    for (index, shdr) in section_headers.iter().enumerate() {
+        match shdr.section_type {
+            elf::file::SectionType::SymbolTable
+            | elf::file::SectionType::DynamicLinkerSymbolTable => {
+                symbol_tables.push((index, parse_symbols(bytes, &section_headers, index)?));
+            }
+            elf::file::SectionType::RelocationWithAddend
+            | elf::file::SectionType::RelocationWithoutAddend => {
+                let machine = header.machine_type;
+                let entries = relocation_entries(bytes, machine, &section_headers, index)?;
+                relocation_tables.push((index, entries));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ElfFile {
+        header,
+        section_headers,
+        section_names,
+        program_headers,
+        symbol_tables,
+        relocation_tables,
+        bytes,
+    })
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Note<'a> {
+    pub name: String,
+    pub note_type: u32,
+    pub descriptor: &'a [u8],
+}
+
+// The entries of an SHT_NOTE section or PT_NOTE segment. Names and
+// descriptors are padded to `alignment`: 4, except 8 for .note.gnu.property.
+pub fn parse_notes(bytes: &[u8], alignment: u64) -> Result<Vec<Note<'_>>, ParseError> {
+    let alignment = alignment.max(4);
+    // Where `size` bytes from `start` end once padded. A hostile alignment
+    // can put that past any file.
+    let padded = |start: u64, size: u64| {
+        size.checked_next_multiple_of(alignment)
+            .and_then(|size| start.checked_add(size))
+    };
+    let mut notes = Vec::new();
+    let mut offset = 0u64;
+    while offset < bytes.len() as u64 {
+        let i = notes.len();
+        let header = slice(bytes, offset, 12, || format!("note {i}"))?;
+        let word = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let (name_size, descriptor_size, note_type) = (word(0) as u64, word(4) as u64, word(8));
+        let name_offset = offset + 12;
+        let name = slice(bytes, name_offset, name_size, || format!("note {i}'s name"))?;
+        let descriptor_offset = padded(name_offset, name_size)
+            .ok_or_else(|| ParseError::PastEndOfFile(format!("note {i}'s descriptor")))?;
+        let descriptor = slice(bytes, descriptor_offset, descriptor_size, || {
+            format!("note {i}'s descriptor")
+        })?;
+        notes.push(Note {
+            name: String::from_utf8_lossy(name)
+                .trim_end_matches('\0')
+                .to_string(),
+            note_type,
+            descriptor,
+        });
+        offset = padded(descriptor_offset, descriptor_size)
+            .ok_or_else(|| ParseError::PastEndOfFile(format!("note {i}")))?;
+    }
+    Ok(notes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 64-bit little-endian x86-64 ET_REL header with no sections
+    fn header() -> Vec<u8> {
+        let mut bytes = vec![0; elf::file::FILE_HEADER_SIZE];
+        bytes[..4].copy_from_slice(&ELF_MAGIC);
+        bytes[4] = ELFCLASS64;
+        bytes[5] = ELFDATA2LSB;
+        bytes[6] = 1;
+        bytes[16..18].copy_from_slice(&ET_REL.to_le_bytes());
+        bytes[18..20].copy_from_slice(&EM_X86_64.to_le_bytes());
+        bytes[58..60].copy_from_slice(&(elf::file::SECTION_HEADER_SIZE as u16).to_le_bytes());
+        bytes
     }
 
-    relocations
+    #[test]
+    fn empty_object() {
+        let bytes = header();
+        let reloc = parse("empty.o", &bytes).unwrap();
+        assert!(reloc.sections.is_empty());
+        assert!(reloc.symbols.is_empty());
+    }
+
+    #[test]
+    fn rejects_non_elf_and_truncated_headers() {
+        assert_eq!(parse("a.o", b"").unwrap_err(), ParseError::NotElf);
+        assert_eq!(parse("a.o", b"MZ\x90\x00").unwrap_err(), ParseError::NotElf);
+        assert_eq!(
+            parse("a.o", &header()[..20]).unwrap_err().to_string(),
+            "file header extends past end of file"
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_targets() {
+        let mut bytes = header();
+        bytes[4] = 1;
+        assert_eq!(
+            parse("a.o", &bytes).unwrap_err(),
+            ParseError::UnsupportedClass(1)
+        );
+
+        let mut bytes = header();
+        bytes[18] = 0x28; // 32-bit Arm
+        assert_eq!(
+            parse("a.o", &bytes).unwrap_err(),
+            ParseError::UnsupportedMachine(0x28)
+        );
+
+        let mut bytes = header();
+        bytes[18] = 0xb7; // AArch64
+        assert_eq!(parse("a.o", &bytes).unwrap().machine, EM_AARCH64);
+    }
+
+    #[test]
+    fn section_headers_past_end_of_file() {
+        let mut bytes = header();
+        let shoff = bytes.len() as u64;
+        bytes[40..48].copy_from_slice(&shoff.to_le_bytes());
+        bytes[60..62].copy_from_slice(&8u16.to_le_bytes()); // e_shnum
+        bytes.extend(vec![0; 7 * elf::file::SECTION_HEADER_SIZE]);
+        assert_eq!(
+            parse("a.o", &bytes).unwrap_err().to_string(),
+            "section header 7 extends past end of file"
+        );
+    }
+
+    #[test]
+    fn huge_offsets_do_not_overflow() {
+        let mut bytes = header();
+        bytes[40..48].copy_from_slice(&u64::MAX.to_le_bytes());
+        bytes[60..62].copy_from_slice(&1u16.to_le_bytes());
+        assert_eq!(
+            parse("a.o", &bytes).unwrap_err(),
+            ParseError::PastEndOfFile("section header 0".to_string())
+        );
+    }
+
+    // testdata/0_simple's main.o: main calls square
+    fn main_o() -> Vec<u8> {
+        use elf::file::*;
+        use elf::logical::RelocationType;
+        let mut object = elf::object::ObjectBuilder::new();
+        let text = object.section(
+            ".text",
+            SectionFlags::Alloc | SectionFlags::Executable,
+            &[
+                0x55, 0x48, 0x89, 0xe5, 0xbf, 0x04, 0x00, 0x00, 0x00, 0xe8, 0x00, 0x00, 0x00, 0x00,
+                0x5d, 0xc3,
+            ],
+        );
+        object.symbol(
+            "main",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_FUNCTION,
+            text,
+            0,
+            0x10,
+        );
+        let square = object.undefined("square");
+        object.relocation(text, 0xa, RelocationType::Plt32 as u32, square, -4);
+        object.encode()
+    }
+
+    #[test]
+    fn sections_borrow_from_the_input() {
+        let bytes = main_o();
+        let reloc = parse("main.o", &bytes).unwrap();
+        let text = &reloc.sections[reloc.find_section(".text").unwrap()];
+        assert!(matches!(text.bytes, std::borrow::Cow::Borrowed(_)));
+        assert!(bytes
+            .windows(text.bytes.len())
+            .any(|w| w == &text.bytes[..]));
+
+        // main calls square
+        assert!(reloc
+            .relocations
+            .iter()
+            .any(|r| reloc.symbols[r.symbol].name == "square"));
+    }
+
+    #[test]
+    fn whole_file_view_matches_the_linker_view() {
+        let bytes = main_o();
+        let reloc = parse("main.o", &bytes).unwrap();
+        let file = parse_file(&bytes).unwrap();
+        assert_eq!(file.section_names.len(), reloc.sections.len());
+        assert!(file.program_headers.is_empty());
+        assert_eq!(file.symbol_tables.len(), 1);
+        assert_eq!(file.symbol_tables[0].1.len(), reloc.symbols.len());
+        let relocations: usize = file.relocation_tables.iter().map(|(_, r)| r.len()).sum();
+        assert_eq!(relocations, reloc.relocations.len());
+        let text = file
+            .section_names
+            .iter()
+            .position(|n| n == ".text")
+            .unwrap();
+        assert_eq!(
+            file.section_bytes(text).unwrap(),
+            &reloc.sections[text].bytes[..]
+        );
+    }
+
+    #[test]
+    fn built_objects_parse() {
+        use elf::file::*;
+        let mut object = elf::object::ObjectBuilder::new();
+        let data = object.section(".data", SectionFlags::Alloc | SectionFlags::Write, &[0; 8]);
+        object.nobits(".bss", SectionFlags::Alloc | SectionFlags::Write, 4);
+        object.symbol("x", SYMBOL_BINDING_WEAK, SYMBOL_TYPE_OBJECT, data, 0, 8);
+        let y = object.undefined("y");
+        object.relocation(data, 0, 1, y, 16);
+        let bytes = object.encode();
+
+        let reloc = parse("built.o", &bytes).unwrap();
+        let names: Vec<&str> = reloc.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "",
+                ".data",
+                ".bss",
+                ".rela.data",
+                ".symtab",
+                ".strtab",
+                ".shstrtab"
+            ]
+        );
+        assert_eq!(
+            (reloc.sections[2].size, reloc.sections[2].bytes.len()),
+            (4, 0)
+        );
+        let x = reloc.symbols.iter().find(|s| s.name == "x").unwrap();
+        assert!(x.is_weak() && x.is_defined());
+        let r = &reloc.relocations[0];
+        assert_eq!((r.section, r.addend), (1, 16));
+        assert_eq!(r.relo_type(), elf::logical::RelocationType::Abs64);
+        assert_eq!(reloc.symbols[r.symbol].name, "y");
+    }
+
+    // Relocations of each width and signedness against .text, as SHT_REL
+    // or SHT_RELA
+    fn relocated(implicit: bool, extra: &[(u64, u32)]) -> Vec<u8> {
+        use elf::file::*;
+        let mut object = elf::object::ObjectBuilder::new();
+        object.implicit_addends(implicit);
+        let code = SectionFlags::Alloc | SectionFlags::Executable;
+        let text = object.section(".text", code, &[0x90; 24]);
+        let f = object.undefined("f");
+        object.relocation(text, 0, 2, f, -4); // PC32
+        object.relocation(text, 4, 10, f, 0xffff_fff0); // 32
+        object.relocation(text, 8, 1, f, -0x1234); // 64
+        object.relocation(text, 16, 14, f, 0x80); // 8
+        for &(offset, relocation_type) in extra {
+            object.relocation(text, offset, relocation_type, f, 0);
+        }
+        object.encode()
+    }
+
+    #[test]
+    fn rel_addends_come_from_the_relocated_bytes() {
+        let summary = |bytes: &[u8]| -> Vec<(usize, u64, i64, usize)> {
+            let reloc = parse("a.o", bytes).unwrap();
+            reloc
+                .relocations
+                .iter()
+                .map(|r| (r.offset, r.info, r.addend, r.section))
+                .collect()
+        };
+        let rel = relocated(true, &[]);
+        let reloc = parse("a.o", &rel).unwrap();
+        assert_eq!(reloc.sections[2].name, ".rel.text");
+        assert_eq!(
+            reloc
+                .relocations
+                .iter()
+                .map(|r| r.addend)
+                .collect::<Vec<_>>(),
+            [-4, 0xffff_fff0, -0x1234, 0x80]
+        );
+        assert_eq!(summary(&rel), summary(&relocated(false, &[])));
+    }
+
+    #[test]
+    fn rel_addends_must_be_readable() {
+        let past_end = relocated(true, &[(22, 2)]);
+        assert_eq!(
+            parse("a.o", &past_end).unwrap_err().to_string(),
+            "relocation 4 in section 2's addend extends past the end of its section"
+        );
+        let unknown = relocated(true, &[(20, 37)]); // IRELATIVE
+        assert_eq!(
+            parse("a.o", &unknown).unwrap_err(),
+            ParseError::UnknownRelocationType {
+                what: "relocation 4 in section 2".to_string(),
+                relocation_type: 37
+            }
+        );
+    }
+
+    #[test]
+    fn notes() {
+        let mut bytes = Vec::new();
+        for word in [4u32, 3, 3] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.extend_from_slice(b"GNU\0\x01\x02\x03\0");
+        let notes = parse_notes(&bytes, 4).unwrap();
+        assert_eq!(
+            notes,
+            [Note {
+                name: "GNU".to_string(),
+                note_type: 3,
+                descriptor: &[1, 2, 3]
+            }]
+        );
+        assert_eq!(
+            parse_notes(&bytes[..17], 4).unwrap_err(),
+            ParseError::PastEndOfFile("note 0's descriptor".to_string())
+        );
+        assert_eq!(
+            parse_notes(&bytes, u64::MAX - 1).unwrap_err(),
+            ParseError::PastEndOfFile("note 0's descriptor".to_string())
+        );
+    }
 }
@@ -0,0 +1,5 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|objects: Vec<&[u8]>| weld_fuzz::link(&objects));
@@ -0,0 +1,5 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: weld_fuzz::MutatedLink| weld_fuzz::link_mutated(&input));
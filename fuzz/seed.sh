@@ -0,0 +1,21 @@
+#!/bin/sh
+# Builds every testdata program and copies the objects into fuzz/seeds
+# (for link_mutated) and each target's corpus directory, so fuzzing can
+# start from realistic inputs without network access:
+#
+#   ./fuzz/seed.sh
+#   cargo +nightly fuzz run parse fuzz/corpus/parse
+set -e
+
+cd "$(dirname "$0")/.."
+mkdir -p fuzz/seeds fuzz/corpus/parse fuzz/corpus/link fuzz/corpus/link_mutated fuzz/corpus/strtab_get
+
+for dir in testdata/*/; do
+    [ -x "$dir/build.sh" ] || continue # testdata/runtime
+    (cd "$dir" && ./build.sh)
+    name=$(basename "$dir")
+    for obj in "$dir"*.o; do
+        cp "$obj" "fuzz/seeds/${name}_$(basename "$obj")"
+        cp "$obj" "fuzz/corpus/parse/${name}_$(basename "$obj")"
+    done
+done
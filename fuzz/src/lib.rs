@@ -0,0 +1,346 @@
+//! Bodies of the fuzz targets in `fuzz_targets/`, kept here so they can also
+//! be driven by the stable-Rust smoke test below.
+//!
+//! `link_mutated` is the structured target: rather than flipping raw bytes
+//! (which the parser rejects almost immediately), it takes valid seed
+//! objects and rewrites individual fields - symbol indices, section offsets,
+//! relocation types - so the linker sees inputs that are *almost* right.
+//!
+//! Seeds are the `.o` files in `fuzz/seeds` (or `$WELD_FUZZ_SEEDS`), which
+//! `fuzz/seed.sh` builds from `testdata`.
+
+use arbitrary::Arbitrary;
+use std::sync::OnceLock;
+
+pub fn parse(data: &[u8]) {
+    let _ = elf_parser::parse("fuzz.o", data);
+}
+
+// The first two bytes pick the index, the rest is the table
+pub fn strtab_get(data: &[u8]) {
+    if data.len() < 2 {
+        return;
+    }
+    let index = u16::from_le_bytes([data[0], data[1]]) as usize;
+    let table = elf::string_table::StrTab::new(&data[2..]);
+    let terminated = data[2..].iter().skip(index).any(|&c| c == 0);
+    assert_eq!(table.get(index).is_some(), terminated);
+}
+
+pub fn link(objects: &[&[u8]]) {
+    let parsed: Result<Vec<_>, _> = objects
+        .iter()
+        .enumerate()
+        .map(|(i, bytes)| elf_parser::parse(&format!("fuzz{i}.o"), bytes))
+        .collect();
+    if let Ok(relocatables) = parsed {
+        link_and_encode(&relocatables);
+    }
+}
+
+fn link_and_encode(relocatables: &[elf::logical::Relocatable]) {
+    let options = weld_core::LinkOptions {
+        build_id: Some(weld_core::build_id::BuildId::Fast),
+        ..Default::default()
+    };
+    if let Ok(output) = weld_core::link(relocatables, &options) {
+        let _ = output.executable.encode();
+    }
+}
+
+// Indices are taken modulo the number of entries in the seed, so every
+// mutation lands on a real structure.
+#[derive(Arbitrary, Debug)]
+pub enum Mutation {
+    RelocationSymbol {
+        relocation: u16,
+        symbol: u32,
+    },
+    RelocationType {
+        relocation: u16,
+        relocation_type: u32,
+    },
+    RelocationOffset {
+        relocation: u16,
+        offset: u64,
+    },
+    RelocationAddend {
+        relocation: u16,
+        addend: i64,
+    },
+    SectionOffset {
+        section: u16,
+        offset: u64,
+    },
+    SectionSize {
+        section: u16,
+        size: u64,
+    },
+    SectionLink {
+        section: u16,
+        link: u32,
+    },
+    SectionInfo {
+        section: u16,
+        info: u32,
+    },
+    SymbolSection {
+        symbol: u16,
+        section: u16,
+    },
+    SymbolValue {
+        symbol: u16,
+        value: u64,
+    },
+    SymbolName {
+        symbol: u16,
+        name: u32,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct MutatedObject {
+    pub seed: u8,
+    pub mutations: Vec<Mutation>,
+}
+
+#[derive(Arbitrary, Debug)]
+pub struct MutatedLink {
+    pub objects: Vec<MutatedObject>,
+}
+
+pub fn link_mutated(input: &MutatedLink) {
+    let seeds = seeds();
+    if seeds.is_empty() {
+        return;
+    }
+    let objects: Vec<Vec<u8>> = input
+        .objects
+        .iter()
+        .map(|object| {
+            let mut bytes = seeds[object.seed as usize % seeds.len()].clone();
+            for mutation in &object.mutations {
+                mutate(&mut bytes, mutation);
+            }
+            bytes
+        })
+        .collect();
+    let mut relocatables = Vec::new();
+    for (i, bytes) in objects.iter().enumerate() {
+        match elf_parser::parse(&format!("fuzz{i}.o"), bytes) {
+            Ok(reloc) => relocatables.push(reloc),
+            Err(_) => return,
+        }
+    }
+    link_and_encode(&relocatables);
+}
+
+pub fn seeds() -> &'static [Vec<u8>] {
+    static SEEDS: OnceLock<Vec<Vec<u8>>> = OnceLock::new();
+    SEEDS.get_or_init(|| {
+        let dir = std::env::var("WELD_FUZZ_SEEDS")
+            .unwrap_or_else(|_| concat!(env!("CARGO_MANIFEST_DIR"), "/seeds").to_string());
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<_> = entries.flatten().map(|e| e.path()).collect();
+        paths.sort();
+        paths
+            .iter()
+            .filter(|p| p.extension().is_some_and(|ext| ext == "o"))
+            .filter_map(|p| std::fs::read(p).ok())
+            .collect()
+    })
+}
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+const ENTRY_SIZE: usize = 24; // Both Elf64_Sym and Elf64_Rela
+
+fn read_u64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+fn write(bytes: &mut [u8], at: usize, value: &[u8]) {
+    if let Some(site) = bytes.get_mut(at..at + value.len()) {
+        site.copy_from_slice(value);
+    }
+}
+
+// File offsets of each section header
+fn section_headers(bytes: &[u8]) -> Vec<usize> {
+    let (Some(shoff), Some(shnum)) = (read_u64(bytes, 0x28), bytes.get(0x3c..0x3e)) else {
+        return Vec::new();
+    };
+    let shnum = u16::from_le_bytes([shnum[0], shnum[1]]) as usize;
+    (0..shnum)
+        .filter_map(|i| (shoff as usize).checked_add(i * elf::file::SECTION_HEADER_SIZE))
+        .filter(|&at| at < bytes.len() - elf::file::SECTION_HEADER_SIZE.min(bytes.len()))
+        .collect()
+}
+
+// File offsets of each entry in all sections of `section_type`
+fn entries(bytes: &[u8], section_type: u32) -> Vec<usize> {
+    let mut result = Vec::new();
+    for header in section_headers(bytes) {
+        if bytes.get(header + 4..header + 8) != Some(&section_type.to_le_bytes()) {
+            continue;
+        }
+        let (Some(offset), Some(size)) =
+            (read_u64(bytes, header + 24), read_u64(bytes, header + 32))
+        else {
+            continue;
+        };
+        // Earlier mutations may have pointed the section anywhere
+        let available = (bytes.len() as u64).saturating_sub(offset);
+        let count = (size.min(available) as usize) / ENTRY_SIZE;
+        result.extend((0..count).map(|i| offset as usize + i * ENTRY_SIZE));
+    }
+    result
+}
+
+fn pick(offsets: &[usize], index: u16) -> Option<usize> {
+    (!offsets.is_empty()).then(|| offsets[index as usize % offsets.len()])
+}
+
+pub fn mutate(bytes: &mut [u8], mutation: &Mutation) {
+    let relocations = entries(bytes, SHT_RELA);
+    let symbols = entries(bytes, SHT_SYMTAB);
+    let sections = section_headers(bytes);
+
+    match *mutation {
+        Mutation::RelocationSymbol { relocation, symbol } => {
+            if let Some(at) = pick(&relocations, relocation) {
+                write(bytes, at + 12, &symbol.to_le_bytes());
+            }
+        }
+        Mutation::RelocationType {
+            relocation,
+            relocation_type,
+        } => {
+            if let Some(at) = pick(&relocations, relocation) {
+                write(bytes, at + 8, &relocation_type.to_le_bytes());
+            }
+        }
+        Mutation::RelocationOffset { relocation, offset } => {
+            if let Some(at) = pick(&relocations, relocation) {
+                write(bytes, at, &offset.to_le_bytes());
+            }
+        }
+        Mutation::RelocationAddend { relocation, addend } => {
+            if let Some(at) = pick(&relocations, relocation) {
+                write(bytes, at + 16, &addend.to_le_bytes());
+            }
+        }
+        Mutation::SectionOffset { section, offset } => {
+            if let Some(at) = pick(&sections, section) {
+                write(bytes, at + 24, &offset.to_le_bytes());
+            }
+        }
+        Mutation::SectionSize { section, size } => {
+            if let Some(at) = pick(&sections, section) {
+                write(bytes, at + 32, &size.to_le_bytes());
+            }
+        }
+        Mutation::SectionLink { section, link } => {
+            if let Some(at) = pick(&sections, section) {
+                write(bytes, at + 40, &link.to_le_bytes());
+            }
+        }
+        Mutation::SectionInfo { section, info } => {
+            if let Some(at) = pick(&sections, section) {
+                write(bytes, at + 44, &info.to_le_bytes());
+            }
+        }
+        Mutation::SymbolSection { symbol, section } => {
+            if let Some(at) = pick(&symbols, symbol) {
+                write(bytes, at + 6, &section.to_le_bytes());
+            }
+        }
+        Mutation::SymbolValue { symbol, value } => {
+            if let Some(at) = pick(&symbols, symbol) {
+                write(bytes, at + 8, &value.to_le_bytes());
+            }
+        }
+        Mutation::SymbolName { symbol, name } => {
+            if let Some(at) = pick(&symbols, symbol) {
+                write(bytes, at, &name.to_le_bytes());
+            }
+        }
+    }
+}
+
+// Runs every target on pseudo-random inputs derived from the seeds, so the
+// targets are exercised (on stable, offline) without cargo-fuzz:
+//     cd fuzz && cargo test --release
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arbitrary::Unstructured;
+
+    // xorshift64*, so runs are reproducible
+    fn noise(state: &mut u64, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                *state ^= *state >> 12;
+                *state ^= *state << 25;
+                *state ^= *state >> 27;
+                (state.wrapping_mul(0x2545f4914f6cdd1d) >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn smoke() {
+        let mut state = 0x5eed;
+        for seed in seeds() {
+            parse(seed);
+            link(&[seed]);
+            for len in [1, 7, 64, seed.len() / 2] {
+                parse(&seed[..len.min(seed.len())]);
+            }
+        }
+
+        for _ in 0..2000 {
+            let data = noise(&mut state, 256);
+            parse(&data);
+            strtab_get(&data);
+            if let Ok(input) = MutatedLink::arbitrary(&mut Unstructured::new(&data)) {
+                link_mutated(&input);
+            }
+        }
+
+        // A full link of every seed with one field changed, which is far more
+        // likely to get past the parser than fully arbitrary input
+        for _ in 0..2000 {
+            let data = noise(&mut state, 64);
+            let mut u = Unstructured::new(&data);
+            let objects = (0..seeds().len())
+                .map(|seed| MutatedObject {
+                    seed: seed as u8,
+                    mutations: Mutation::arbitrary(&mut u).into_iter().collect(),
+                })
+                .collect();
+            link_mutated(&MutatedLink { objects });
+        }
+    }
+
+    #[test]
+    fn mutations_hit_seed_structures() {
+        let Some(seed) = seeds().first() else {
+            return;
+        };
+        assert!(!entries(seed, SHT_SYMTAB).is_empty());
+
+        let mut bytes = seed.clone();
+        let at = entries(&bytes, SHT_RELA)[0];
+        mutate(
+            &mut bytes,
+            &Mutation::RelocationType {
+                relocation: 0,
+                relocation_type: 0xdead,
+            },
+        );
+        assert_eq!(bytes[at + 8..at + 12], 0xdeadu32.to_le_bytes());
+    }
+}
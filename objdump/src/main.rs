@@ -0,0 +1,83 @@
+//! weld-objdump: disassembles objects, archives and executables (weld's
+//! own output included) the way `objdump -dr` does, with code labelled and
+//! branch targets named from the symbol table.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::ExitCode;
+
+mod disasm;
+
+fn main() -> ExitCode {
+    let mut only = Vec::new();
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if let Some(section) = arg.strip_prefix("--section=") {
+            only.push(section.to_string());
+        } else if arg == "-j" || arg == "--section" {
+            match args.next() {
+                Some(section) => only.push(section),
+                None => return usage(&format!("{arg} requires a section name")),
+            }
+        } else if let Some(section) = arg.strip_prefix("-j") {
+            only.push(section.to_string());
+        } else if arg == "-d" || arg == "-r" || arg == "-dr" {
+            // What weld-objdump always does; accepted so objdump command lines work
+        } else if arg.starts_with('-') {
+            return usage(&format!("unrecognized option '{arg}'"));
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.is_empty() {
+        return usage("no input files");
+    }
+
+    let mut failed = false;
+    let mut stdout = std::io::stdout().lock();
+    for path in &paths {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                eprintln!("weld-objdump: {path}: {err}");
+                failed = true;
+                continue;
+            }
+        };
+        let files = match elf_parser::archive::is_archive(&bytes) {
+            true => match elf_parser::archive::parse_archive(&bytes) {
+                Ok(members) => {
+                    writeln!(stdout, "In archive {path}:").expect("Write to output failed");
+                    members.into_iter().map(|m| (m.name, m.bytes)).collect()
+                }
+                Err(err) => {
+                    eprintln!("weld-objdump: {path}: {err}");
+                    failed = true;
+                    continue;
+                }
+            },
+            false => vec![(path.clone(), &bytes[..])],
+        };
+        for (name, bytes) in files {
+            let listed = elf_parser::parse_file(bytes)
+                .and_then(|file| disasm::disassemble(&name, &file, &only, &mut stdout));
+            if let Err(err) = listed {
+                eprintln!("weld-objdump: {name}: {err}");
+                failed = true;
+            }
+        }
+    }
+
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
+
+fn usage(err: &str) -> ExitCode {
+    eprintln!("weld-objdump: {err}");
+    eprintln!("Usage: weld-objdump [-j <section>] <files>");
+    ExitCode::FAILURE
+}
@@ -0,0 +1,198 @@
+//! `--json`: the same information as the text output, with numbers as
+//! numbers and names spelled as readelf spells them.
+
+use crate::names;
+use crate::Show;
+use elf_parser::{ElfFile, ParseError};
+use serde_json::{json, Map, Value};
+
+pub fn file(name: &str, file: &ElfFile, show: &Show) -> Result<Value, ParseError> {
+    let mut report = Map::new();
+    report.insert("file".to_string(), json!(name));
+    if show.header {
+        report.insert("header".to_string(), header(file));
+    }
+    if show.sections {
+        report.insert("sections".to_string(), sections(file));
+    }
+    if show.segments {
+        report.insert("program_headers".to_string(), segments(file));
+    }
+    if show.relocations {
+        report.insert("relocations".to_string(), relocations(file));
+    }
+    if show.symbols {
+        report.insert("symbol_tables".to_string(), symbols(file));
+    }
+    if show.notes {
+        report.insert("notes".to_string(), notes(file)?);
+    }
+    Ok(Value::Object(report))
+}
+
+fn header(file: &ElfFile) -> Value {
+    let h = &file.header;
+    json!({
+        "class": "ELF64",
+        "data": "little endian",
+        "os_abi": names::os_abi(h.identification.os_abi),
+        "type": names::file_type(h.object_file_type),
+        "machine": names::machine(h.machine_type),
+        "entry": h.entrypoint,
+        "program_header_offset": h.program_header_offset,
+        "section_header_offset": h.section_header_offset,
+        "program_header_count": h.program_header_entry_count,
+        "section_header_count": h.section_header_entry_count,
+        "section_name_table_index": h.sh_section_name_stringtab_entry_index,
+    })
+}
+
+fn sections(file: &ElfFile) -> Value {
+    file.section_headers
+        .iter()
+        .zip(&file.section_names)
+        .enumerate()
+        .map(|(i, (shdr, name))| {
+            json!({
+                "index": i,
+                "name": name,
+                "type": names::section_type(shdr.section_type),
+                "address": shdr.virtual_address,
+                "offset": shdr.offset,
+                "size": shdr.size,
+                "entry_size": shdr.entry_size,
+                "flags": names::section_flags(shdr.flags),
+                "link": shdr.link_to_other_section,
+                "info": shdr.misc_info,
+                "alignment": shdr.address_allignment_boundary,
+            })
+        })
+        .collect()
+}
+
+fn segments(file: &ElfFile) -> Value {
+    file.program_headers
+        .iter()
+        .map(|phdr| {
+            json!({
+                "type": names::segment_type(&phdr.segment_type),
+                "offset": phdr.offset,
+                "virtual_address": phdr.virtual_address,
+                "physical_address": phdr.physical_address,
+                "file_size": phdr.size_in_file,
+                "memory_size": phdr.size_in_memory,
+                "flags": names::segment_flags(phdr.flags).replace(' ', ""),
+                "alignment": phdr.required_alignment,
+            })
+        })
+        .collect()
+}
+
+fn relocations(file: &ElfFile) -> Value {
+    file.relocation_tables
+        .iter()
+        .map(|(index, entries)| {
+            let entries: Vec<Value> = entries
+                .iter()
+                .map(|r| {
+                    let symbol = crate::relocation_symbol(file, *index, r.symbol())
+                        .filter(|_| r.symbol() != 0);
+                    json!({
+                        "offset": r.offset,
+                        "type": names::relocation_type(file.header.machine_type, r.relo_type()),
+                        "symbol": symbol.map(|s| crate::symbol_name(file, s)),
+                        "symbol_value": symbol.map(|s| s.symbol.value),
+                        "addend": r.addend,
+                    })
+                })
+                .collect();
+            json!({
+                "section": file.section_names[*index],
+                "offset": file.section_headers[*index].offset,
+                "entries": entries,
+            })
+        })
+        .collect()
+}
+
+fn symbols(file: &ElfFile) -> Value {
+    file.symbol_tables
+        .iter()
+        .map(|(index, symbols)| {
+            let symbols: Vec<Value> = symbols
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    json!({
+                        "index": i,
+                        "name": s.name,
+                        "value": s.symbol.value,
+                        "size": s.symbol.size,
+                        "type": names::symbol_type(s.symbol.symbol_type()),
+                        "binding": names::symbol_binding(s.symbol.binding()),
+                        "visibility": names::symbol_visibility(s.symbol.other),
+                        "section": names::symbol_section(s.symbol.relative_to_section),
+                    })
+                })
+                .collect();
+            json!({ "section": file.section_names[*index], "symbols": symbols })
+        })
+        .collect()
+}
+
+fn notes(file: &ElfFile) -> Result<Value, ParseError> {
+    let mut found = Vec::new();
+    for (source, notes) in crate::notes(file)? {
+        for note in notes {
+            found.push(json!({
+                "source": source,
+                "owner": note.name,
+                "type": names::note_type(&note.name, note.note_type),
+                "descriptor": names::hex(note.descriptor),
+            }));
+        }
+    }
+    Ok(Value::Array(found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elf::file::*;
+    use elf::logical::RelocationType;
+    use elf::object::ObjectBuilder;
+
+    #[test]
+    fn rel_sections_are_listed_with_their_addends() {
+        let mut object = ObjectBuilder::new();
+        let code = SectionFlags::Alloc | SectionFlags::Executable;
+        let text = object.section(".text", code, &[0; 12]);
+        let f = object.undefined("f");
+        object.relocation(text, 0, RelocationType::Abs64 as u32, f, 0x10);
+        object.relocation(text, 8, RelocationType::Pc32 as u32, f, -4);
+        object.implicit_addends(true);
+        let bytes = object.encode();
+
+        let file = elf_parser::parse_file(&bytes).unwrap();
+        let show = Show {
+            relocations: true,
+            ..Default::default()
+        };
+        let report = super::file("rel.o", &file, &show).unwrap();
+        let table = &report["relocations"][0];
+        assert_eq!(table["section"], ".rel.text");
+        let entries: Vec<(&Value, &Value, &Value)> = table["entries"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| (&r["type"], &r["symbol"], &r["addend"]))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (&json!("R_X86_64_64"), &json!("f"), &json!(0x10)),
+                (&json!("R_X86_64_PC32"), &json!("f"), &json!(-4)),
+            ]
+        );
+    }
+}
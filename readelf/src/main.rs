@@ -0,0 +1,234 @@
+//! weld-readelf: prints what `elf_parser` makes of objects, archives and
+//! executables, laid out like binutils' `readelf -W` so the two can be
+//! compared line by line, or as JSON (`--json`).
+
+use elf::logical::SymbolInfo;
+use elf_parser::{ElfFile, Note, ParseError};
+use std::env;
+use std::fs;
+use std::process::ExitCode;
+
+mod json;
+mod names;
+mod text;
+
+// Which parts of each file to print
+#[derive(Default)]
+pub struct Show {
+    pub header: bool,
+    pub sections: bool,
+    pub segments: bool,
+    pub symbols: bool,
+    pub relocations: bool,
+    pub notes: bool,
+}
+
+impl Show {
+    fn all() -> Show {
+        Show {
+            header: true,
+            sections: true,
+            segments: true,
+            symbols: true,
+            relocations: true,
+            notes: true,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let mut show = Show::default();
+    let mut json = false;
+    let mut paths = Vec::new();
+    for arg in env::args().skip(1) {
+        let flags = match arg.as_str() {
+            "--file-header" => "h",
+            "--section-headers" | "--sections" => "S",
+            "--program-headers" | "--segments" => "l",
+            "--symbols" | "--syms" => "s",
+            "--relocs" => "r",
+            "--notes" => "n",
+            "--all" => "a",
+            "--json" => {
+                json = true;
+                continue;
+            }
+            _ if arg.starts_with("--") => return usage(&format!("unrecognized option '{arg}'")),
+            // Short options can be combined, as in -hS
+            _ => match arg.strip_prefix('-') {
+                Some(flags) => flags,
+                None => {
+                    paths.push(arg.clone());
+                    continue;
+                }
+            },
+        };
+        for flag in flags.chars() {
+            match flag {
+                'h' => show.header = true,
+                'S' => show.sections = true,
+                'l' => show.segments = true,
+                's' => show.symbols = true,
+                'r' => show.relocations = true,
+                'n' => show.notes = true,
+                'a' => show = Show::all(),
+                _ => return usage(&format!("invalid option -- '{flag}'")),
+            }
+        }
+    }
+    if paths.is_empty() {
+        return usage("no input files");
+    }
+    if !(show.header
+        || show.sections
+        || show.segments
+        || show.symbols
+        || show.relocations
+        || show.notes)
+    {
+        show = Show::all();
+    }
+
+    // Each file, or archive member, as (name, contents)
+    let mut failed = false;
+    let mut contents = Vec::new();
+    for path in &paths {
+        match fs::read(path) {
+            Ok(bytes) => contents.push((path, bytes)),
+            Err(err) => {
+                eprintln!("weld-readelf: {path}: {err}");
+                failed = true;
+            }
+        }
+    }
+    let mut files = Vec::new();
+    for (path, bytes) in &contents {
+        if elf_parser::archive::is_archive(bytes) {
+            match elf_parser::archive::parse_archive(bytes) {
+                Ok(members) => files.extend(
+                    members
+                        .into_iter()
+                        .map(|m| (format!("{path}({})", m.name), m.bytes)),
+                ),
+                Err(err) => {
+                    eprintln!("weld-readelf: {path}: {err}");
+                    failed = true;
+                }
+            }
+        } else {
+            files.push((path.to_string(), &bytes[..]));
+        }
+    }
+
+    let mut reports = Vec::new();
+    let many = files.len() > 1;
+    for (name, bytes) in files {
+        let report = elf_parser::parse_file(bytes).and_then(|file| match json {
+            true => json::file(&name, &file, &show).map(|report| reports.push(report)),
+            false => {
+                if many {
+                    println!("\nFile: {name}");
+                }
+                text::print(&file, &show)
+            }
+        });
+        if let Err(err) = report {
+            eprintln!("weld-readelf: {name}: {err}");
+            failed = true;
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    }
+
+    match failed {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
+
+fn usage(err: &str) -> ExitCode {
+    eprintln!("weld-readelf: {err}");
+    eprintln!("Usage: weld-readelf [options] <files>");
+    eprintln!("  -h, --file-header");
+    eprintln!("  -S, --section-headers");
+    eprintln!("  -l, --program-headers");
+    eprintln!("  -s, --symbols");
+    eprintln!("  -r, --relocs");
+    eprintln!("  -n, --notes");
+    eprintln!("  -a, --all (the default)");
+    eprintln!("  --json");
+    ExitCode::FAILURE
+}
+
+// Notes, by where they were found: the SHT_NOTE sections, or for files
+// without section headers the PT_NOTE segments
+pub fn notes<'a>(file: &ElfFile<'a>) -> Result<Vec<(String, Vec<Note<'a>>)>, ParseError> {
+    let mut found = Vec::new();
+    for (i, shdr) in file.section_headers.iter().enumerate() {
+        if shdr.section_type == elf::file::SectionType::Notes {
+            let notes =
+                elf_parser::parse_notes(file.section_bytes(i)?, shdr.address_allignment_boundary)?;
+            found.push((file.section_names[i].clone(), notes));
+        }
+    }
+    if !file.section_headers.is_empty() {
+        return Ok(found);
+    }
+    for (i, phdr) in file.program_headers.iter().enumerate() {
+        if phdr.segment_type == elf::file::SegmentType::AuxiliaryInfo {
+            let bytes = phdr
+                .offset
+                .checked_add(phdr.size_in_file)
+                .and_then(|end| file.bytes.get(phdr.offset as usize..end as usize))
+                .ok_or_else(|| ParseError::PastEndOfFile(format!("program header {i}")))?;
+            let notes = elf_parser::parse_notes(bytes, phdr.required_alignment)?;
+            found.push((format!("segment {i}"), notes));
+        }
+    }
+    Ok(found)
+}
+
+// Whether section `index` is loaded as part of `phdr`, as readelf decides
+// for its section to segment mapping
+pub fn in_segment(file: &ElfFile, index: usize, phdr: &elf::file::ProgramHeader) -> bool {
+    let shdr = &file.section_headers[index];
+    let alloc = shdr.flags & elf::file::SectionFlags::Alloc as u64 != 0;
+    let nobits = shdr.section_type == elf::file::SectionType::ProgramSpaceWithNoData;
+    let within = |start: u64, size: u64, at: u64, length: u64| {
+        at >= start && at.saturating_add(length) <= start.saturating_add(size)
+    };
+    alloc
+        && shdr.size > 0
+        && within(
+            phdr.virtual_address,
+            phdr.size_in_memory,
+            shdr.virtual_address,
+            shdr.size,
+        )
+        && (nobits || within(phdr.offset, phdr.size_in_file, shdr.offset, shdr.size))
+}
+
+// The symbol entry `symbol` of relocation section `relocations` refers to
+pub fn relocation_symbol<'f>(
+    file: &'f ElfFile,
+    relocations: usize,
+    symbol: usize,
+) -> Option<&'f SymbolInfo> {
+    let table = file.section_headers[relocations].link_to_other_section as usize;
+    let (_, symbols) = file.symbol_tables.iter().find(|(i, _)| *i == table)?;
+    symbols.get(symbol)
+}
+
+// How a symbol is named in a relocation listing: section symbols by their section
+pub fn symbol_name(file: &ElfFile, s: &SymbolInfo) -> String {
+    if s.symbol.symbol_type() == elf::file::SYMBOL_TYPE_SECTION {
+        if let Some(name) = file
+            .section_names
+            .get(s.symbol.relative_to_section as usize)
+        {
+            return name.clone();
+        }
+    }
+    s.name.clone()
+}
@@ -0,0 +1,188 @@
+//! What `readelf` calls things, shared by the text and JSON output so the
+//! two can be checked against binutils the same way.
+
+use elf::file::{SectionType, SegmentType};
+
+pub fn file_type(file_type: u16) -> String {
+    match file_type {
+        0 => "NONE (None)".to_string(),
+        1 => "REL (Relocatable file)".to_string(),
+        2 => "EXEC (Executable file)".to_string(),
+        3 => "DYN (Shared object file)".to_string(),
+        4 => "CORE (Core file)".to_string(),
+        other => format!("<unknown>: {other:#x}"),
+    }
+}
+
+pub fn machine(machine: u16) -> String {
+    match machine {
+        0x3e => "Advanced Micro Devices X86-64".to_string(),
+        0xb7 => "AArch64".to_string(),
+        0xf3 => "RISC-V".to_string(),
+        other => format!("<unknown>: {other:#x}"),
+    }
+}
+
+pub fn os_abi(os_abi: u8) -> String {
+    match os_abi {
+        0 => "UNIX - System V".to_string(),
+        3 => "UNIX - GNU".to_string(),
+        other => format!("<unknown: {other:x}>"),
+    }
+}
+
+pub fn section_type(section_type: SectionType) -> &'static str {
+    match section_type {
+        SectionType::None => "NULL",
+        SectionType::ProgramData => "PROGBITS",
+        SectionType::SymbolTable => "SYMTAB",
+        SectionType::StringTable => "STRTAB",
+        SectionType::RelocationWithAddend => "RELA",
+        SectionType::SymbolHashTable => "HASH",
+        SectionType::DynamicLinkingInfo => "DYNAMIC",
+        SectionType::Notes => "NOTE",
+        SectionType::ProgramSpaceWithNoData => "NOBITS",
+        SectionType::RelocationWithoutAddend => "REL",
+        SectionType::Reserved => "SHLIB",
+        SectionType::DynamicLinkerSymbolTable => "DYNSYM",
+        SectionType::ArrayOfConstructors => "INIT_ARRAY",
+        SectionType::ArrayOfDestructors => "FINI_ARRAY",
+        SectionType::ArrayOfPreConstructors => "PREINIT_ARRAY",
+        SectionType::SectionGroup => "GROUP",
+        SectionType::ExtendedSectionIndices => "SYMTAB_SHNDX",
+        SectionType::NumberOfDefinedTypes => "NUM",
+        SectionType::GnuAttributes => "GNU_ATTRIBUTES",
+        SectionType::GnuHash => "GNU_HASH",
+        SectionType::GnuLibraryList => "GNU_LIBLIST",
+        SectionType::GnuVersionDefinitions => "VERDEF",
+        SectionType::GnuVersionNeeds => "VERNEED",
+        SectionType::GnuVersionSymbols => "VERSYM",
+        SectionType::X86_64Unwind => "X86_64_UNWIND",
+    }
+}
+
+// readelf's key: W (write), A (alloc), X (execute), M (merge), S (strings),
+// I (info), L (link order), O (extra OS processing), G (group), T (TLS),
+// C (compressed), E (exclude)
+pub fn section_flags(flags: u64) -> String {
+    [
+        (0x1, 'W'),
+        (0x2, 'A'),
+        (0x4, 'X'),
+        (0x10, 'M'),
+        (0x20, 'S'),
+        (0x40, 'I'),
+        (0x80, 'L'),
+        (0x100, 'O'),
+        (0x200, 'G'),
+        (0x400, 'T'),
+        (0x800, 'C'),
+        (0x80000000, 'E'),
+    ]
+    .into_iter()
+    .filter(|&(flag, _)| flags & flag != 0)
+    .map(|(_, c)| c)
+    .collect()
+}
+
+pub fn segment_type(segment_type: &SegmentType) -> &'static str {
+    match segment_type {
+        SegmentType::None => "NULL",
+        SegmentType::Loadable => "LOAD",
+        SegmentType::DynamicLinkInfo => "DYNAMIC",
+        SegmentType::InterpreterInfo => "INTERP",
+        SegmentType::AuxiliaryInfo => "NOTE",
+        SegmentType::Reserved => "SHLIB",
+        SegmentType::ProgramHeaderTableSegment => "PHDR",
+        SegmentType::ThreadLocalStorageTemplate => "TLS",
+        SegmentType::GnuEHFrame => "GNU_EH_FRAME",
+        SegmentType::GnuStack => "GNU_STACK",
+        SegmentType::GnuRelRO => "GNU_RELRO",
+        SegmentType::GnuProperty => "GNU_PROPERTY",
+    }
+}
+
+pub fn segment_flags(flags: u32) -> String {
+    [(0x4, 'R'), (0x2, 'W'), (0x1, 'E')]
+        .into_iter()
+        .map(|(flag, c)| if flags & flag != 0 { c } else { ' ' })
+        .collect()
+}
+
+pub fn symbol_type(symbol_type: u8) -> String {
+    match symbol_type {
+        0 => "NOTYPE".to_string(),
+        1 => "OBJECT".to_string(),
+        2 => "FUNC".to_string(),
+        3 => "SECTION".to_string(),
+        4 => "FILE".to_string(),
+        5 => "COMMON".to_string(),
+        6 => "TLS".to_string(),
+        10 => "IFUNC".to_string(),
+        other => format!("<unknown>: {other}"),
+    }
+}
+
+pub fn symbol_binding(binding: u8) -> String {
+    match binding {
+        0 => "LOCAL".to_string(),
+        1 => "GLOBAL".to_string(),
+        2 => "WEAK".to_string(),
+        10 => "UNIQUE".to_string(),
+        other => format!("<unknown>: {other}"),
+    }
+}
+
+pub fn symbol_visibility(other: u8) -> &'static str {
+    match other & 0x3 {
+        0 => "DEFAULT",
+        1 => "INTERNAL",
+        2 => "HIDDEN",
+        _ => "PROTECTED",
+    }
+}
+
+pub fn symbol_section(index: u16) -> String {
+    match index {
+        0 => "UND".to_string(),
+        0xfff1 => "ABS".to_string(),
+        0xfff2 => "COM".to_string(),
+        index => index.to_string(),
+    }
+}
+
+pub fn relocation_type(machine: u16, relocation_type: u64) -> String {
+    match elf::file::relocation_type_name(machine, relocation_type) {
+        Some(name) => name.to_string(),
+        None => format!("unrecognized: {relocation_type:x}"),
+    }
+}
+
+pub fn note_type(owner: &str, note_type: u32) -> String {
+    match (owner, note_type) {
+        ("GNU", 1) => "NT_GNU_ABI_TAG (ABI version tag)".to_string(),
+        ("GNU", 3) => "NT_GNU_BUILD_ID (unique build ID bitstring)".to_string(),
+        ("GNU", 4) => "NT_GNU_GOLD_VERSION (gold version)".to_string(),
+        ("GNU", 5) => "NT_GNU_PROPERTY_TYPE_0".to_string(),
+        (_, other) => format!("Unknown note type: ({other:#010x})"),
+    }
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spelled_like_readelf() {
+        assert_eq!(section_flags(0x2 | 0x4), "AX");
+        assert_eq!(section_flags(0x30), "MS");
+        assert_eq!(segment_flags(0x6), "RW ");
+        assert_eq!(symbol_section(0xfff1), "ABS");
+        assert_eq!(relocation_type(0x3e, 4), "R_X86_64_PLT32");
+        assert_eq!(relocation_type(0xb7, 283), "unrecognized: 11b");
+    }
+}
@@ -0,0 +1,234 @@
+//! readelf-style text output. Columns follow `readelf -W`.
+
+use crate::names;
+use crate::Show;
+use elf_parser::{ElfFile, ParseError};
+
+pub fn print(file: &ElfFile, show: &Show) -> Result<(), ParseError> {
+    if show.header {
+        header(file);
+    }
+    if show.sections {
+        sections(file);
+    }
+    if show.segments {
+        segments(file);
+    }
+    if show.relocations {
+        relocations(file);
+    }
+    if show.symbols {
+        symbols(file);
+    }
+    if show.notes {
+        notes(file)?;
+    }
+    Ok(())
+}
+
+fn header(file: &ElfFile) {
+    let h = &file.header;
+    println!("ELF Header:");
+    println!("  Class:                             ELF64");
+    println!("  Data:                              2's complement, little endian");
+    println!(
+        "  OS/ABI:                            {}",
+        names::os_abi(h.identification.os_abi)
+    );
+    println!(
+        "  Type:                              {}",
+        names::file_type(h.object_file_type)
+    );
+    println!(
+        "  Machine:                           {}",
+        names::machine(h.machine_type)
+    );
+    println!("  Entry point address:               {:#x}", h.entrypoint);
+    println!(
+        "  Start of program headers:          {} (bytes into file)",
+        h.program_header_offset
+    );
+    println!(
+        "  Start of section headers:          {} (bytes into file)",
+        h.section_header_offset
+    );
+    println!(
+        "  Number of program headers:         {}",
+        h.program_header_entry_count
+    );
+    println!(
+        "  Number of section headers:         {}",
+        h.section_header_entry_count
+    );
+    println!(
+        "  Section header string table index: {}",
+        h.sh_section_name_stringtab_entry_index
+    );
+}
+
+fn sections(file: &ElfFile) {
+    if file.section_headers.is_empty() {
+        println!("\nThere are no sections in this file.");
+        return;
+    }
+    println!(
+        "There are {} section headers, starting at offset {:#x}:",
+        file.section_headers.len(),
+        file.header.section_header_offset
+    );
+    println!("\nSection Headers:");
+    println!(
+        "  [Nr] Name              Type            Address          Off    Size   ES Flg Lk Inf Al"
+    );
+    for (i, (shdr, name)) in file
+        .section_headers
+        .iter()
+        .zip(&file.section_names)
+        .enumerate()
+    {
+        println!(
+            "  [{i:>2}] {name:<17} {:<15} {:016x} {:06x} {:06x} {:02x} {:>3} {:>2} {:>3} {:>2}",
+            names::section_type(shdr.section_type),
+            shdr.virtual_address,
+            shdr.offset,
+            shdr.size,
+            shdr.entry_size,
+            names::section_flags(shdr.flags),
+            shdr.link_to_other_section,
+            shdr.misc_info,
+            shdr.address_allignment_boundary
+        );
+    }
+    println!("Key to Flags:");
+    println!("  W (write), A (alloc), X (execute), M (merge), S (strings), I (info),");
+    println!("  L (link order), O (extra OS processing required), G (group), T (TLS),");
+    println!("  C (compressed), x (unknown), o (OS specific), E (exclude),");
+    println!("  D (mbind), l (large), p (processor specific)");
+}
+
+fn segments(file: &ElfFile) {
+    if file.program_headers.is_empty() {
+        println!("\nThere are no program headers in this file.");
+        return;
+    }
+    println!(
+        "\nElf file type is {}",
+        names::file_type(file.header.object_file_type)
+    );
+    println!("Entry point {:#x}", file.header.entrypoint);
+    println!(
+        "There are {} program headers, starting at offset {}",
+        file.program_headers.len(),
+        file.header.program_header_offset
+    );
+    println!("\nProgram Headers:");
+    println!("  Type           Offset   VirtAddr           PhysAddr           FileSiz  MemSiz   Flg Align");
+    for phdr in &file.program_headers {
+        println!(
+            "  {:<14} {:#08x} {:#018x} {:#018x} {:#08x} {:#08x} {} {:#x}",
+            names::segment_type(&phdr.segment_type),
+            phdr.offset,
+            phdr.virtual_address,
+            phdr.physical_address,
+            phdr.size_in_file,
+            phdr.size_in_memory,
+            names::segment_flags(phdr.flags),
+            phdr.required_alignment
+        );
+    }
+
+    println!("\n Section to Segment mapping:");
+    println!("  Segment Sections...");
+    for (i, phdr) in file.program_headers.iter().enumerate() {
+        let sections: String = file
+            .section_names
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| crate::in_segment(file, index, phdr))
+            .map(|(_, name)| format!("{name} "))
+            .collect();
+        println!("   {i:02}     {sections}");
+    }
+}
+
+fn relocations(file: &ElfFile) {
+    if file.relocation_tables.is_empty() {
+        println!("\nThere are no relocations in this file.");
+        return;
+    }
+    for (index, entries) in &file.relocation_tables {
+        println!(
+            "\nRelocation section '{}' at offset {:#x} contains {} {}:",
+            file.section_names[*index],
+            file.section_headers[*index].offset,
+            entries.len(),
+            if entries.len() == 1 {
+                "entry"
+            } else {
+                "entries"
+            }
+        );
+        println!("    Offset             Info             Type               Symbol's Value  Symbol's Name + Addend");
+        for r in entries {
+            let kind = names::relocation_type(file.header.machine_type, r.relo_type());
+            let sign = if r.addend < 0 { '-' } else { '+' };
+            match crate::relocation_symbol(file, *index, r.symbol()).filter(|_| r.symbol() != 0) {
+                Some(s) => println!(
+                    "{:016x}  {:016x} {kind:<22} {:016x} {} {sign} {:x}",
+                    r.offset,
+                    r.info,
+                    s.symbol.value,
+                    crate::symbol_name(file, s),
+                    r.addend.unsigned_abs()
+                ),
+                None => println!(
+                    "{:016x}  {:016x} {kind:<22}                    {:x}",
+                    r.offset, r.info, r.addend
+                ),
+            }
+        }
+    }
+}
+
+fn symbols(file: &ElfFile) {
+    for (index, symbols) in &file.symbol_tables {
+        println!(
+            "\nSymbol table '{}' contains {} entries:",
+            file.section_names[*index],
+            symbols.len()
+        );
+        println!("   Num:    Value          Size Type    Bind   Vis      Ndx Name");
+        for (i, s) in symbols.iter().enumerate() {
+            println!(
+                "{i:>6}: {:016x} {:>5} {:<7} {:<6} {:<8} {:>3} {}",
+                s.symbol.value,
+                s.symbol.size,
+                names::symbol_type(s.symbol.symbol_type()),
+                names::symbol_binding(s.symbol.binding()),
+                names::symbol_visibility(s.symbol.other),
+                names::symbol_section(s.symbol.relative_to_section),
+                crate::symbol_name(file, s)
+            );
+        }
+    }
+}
+
+fn notes(file: &ElfFile) -> Result<(), ParseError> {
+    for (source, notes) in crate::notes(file)? {
+        println!("\nDisplaying notes found in: {source}");
+        println!("  Owner                Data size \tDescription");
+        for note in notes {
+            println!(
+                "  {:<20} {:#010x}\t{}",
+                note.name,
+                note.descriptor.len(),
+                names::note_type(&note.name, note.note_type)
+            );
+            match (note.name.as_str(), note.note_type) {
+                ("GNU", 3) => println!("    Build ID: {}", names::hex(note.descriptor)),
+                _ => println!("   description data: {}", names::hex(note.descriptor)),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,14 @@
+int counter;	// .bss
+int step = 3;	// .data
+const int limits[4] = {2, 5, 7, 11};	// .rodata
+
+static int calls;
+
+void bump(void) {
+	counter += step;
+	calls++;
+}
+
+int bumps(void) {
+	return calls;
+}
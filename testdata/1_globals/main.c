@@ -0,0 +1,52 @@
+#include "../runtime/runtime.h"
+
+extern int counter;
+extern int step;
+extern const int limits[4];
+void bump(void);
+int bumps(void);
+
+// Initialized with addresses, which the linker fills in
+int *watched = &counter;
+const int *last_limit = &limits[3];
+
+long squares[8] = {0, 1, 4, 9, 16, 25, 36, 49};
+char scratch[4096];
+
+static int next_id(void) {
+	static int id = 100;
+	return id++;
+}
+
+static void show(const char *name, long value) {
+	print(name);
+	print(" ");
+	print_int(value);
+	print("\n");
+}
+
+int main(void) {
+	for (int i = 0; i < limits[1]; i++)
+		bump();
+	step = 10;
+	bump();
+	show("counter", counter);
+	show("watched", *watched);
+	show("bumps", bumps());
+	show("last limit", *last_limit);
+
+	long sum = 0;
+	for (int i = 0; i < 8; i++)
+		sum += squares[i];
+	squares[7] = -1;
+	show("sum of squares", sum);
+	show("last square", squares[7]);
+
+	scratch[sizeof(scratch) - 1] = 42;
+	show("scratch", scratch[0] + scratch[sizeof(scratch) - 1]);
+
+	next_id();
+	next_id();
+	show("id", next_id());
+	return counter;
+}
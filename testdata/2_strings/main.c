@@ -0,0 +1,47 @@
+#include "../runtime/runtime.h"
+
+extern const char *greetings[];
+extern const int greeting_count;
+extern char banner[];
+int count_vowels(const char *s);
+
+static void reverse(char *s) {
+	unsigned long n = length(s);
+	for (unsigned long i = 0; i < n / 2; i++) {
+		char c = s[i];
+		s[i] = s[n - 1 - i];
+		s[n - 1 - i] = c;
+	}
+}
+
+static void copy(char *to, const char *from) {
+	while ((*to++ = *from++))
+		;
+}
+
+int main(void) {
+	int total = 0;
+	for (int i = 0; i < greeting_count; i++) {
+		print(greetings[i]);
+		print(" has ");
+		print_int(count_vowels(greetings[i]));
+		print(" vowels\n");
+		total += length(greetings[i]);
+	}
+
+	char buffer[64];
+	copy(buffer, banner);
+	reverse(buffer);
+	print(buffer);
+	print("\n");
+
+	// Writable, unlike the literals
+	banner[0] = 'W';
+	print(banner);
+	print("\n");
+
+	const char *tail = "the end" + 4;
+	print(tail);
+	print("\n");
+	return total;
+}
@@ -0,0 +1,15 @@
+// Strings shared with main.c, so their relocations cross files
+const char *greetings[] = {"hello", "bonjour", "hallo", "ciao"};
+const int greeting_count = sizeof(greetings) / sizeof(greetings[0]);
+char banner[] = "weld links strings";
+
+int count_vowels(const char *s) {
+	int n = 0;
+	for (; *s; s++) {
+		switch (*s) {
+		case 'a': case 'e': case 'i': case 'o': case 'u':
+			n++;
+		}
+	}
+	return n;
+}
@@ -0,0 +1,51 @@
+#include "../runtime/runtime.h"
+
+int is_even(unsigned n);
+
+int is_odd(unsigned n) {
+	return n == 0 ? 0 : is_even(n - 1);
+}
+
+long fib(int n) {
+	return n < 2 ? n : fib(n - 1) + fib(n - 2);
+}
+
+long ackermann(long m, long n) {
+	if (m == 0)
+		return n + 1;
+	if (n == 0)
+		return ackermann(m - 1, 1);
+	return ackermann(m - 1, ackermann(m, n - 1));
+}
+
+long gcd(long a, long b) {
+	return b == 0 ? a : gcd(b, a % b);
+}
+
+static long moves;
+
+static void hanoi(int disks, int from, int to, int via) {
+	if (disks == 0)
+		return;
+	hanoi(disks - 1, from, via, to);
+	moves++;
+	hanoi(disks - 1, via, to, from);
+}
+
+static void show(const char *name, long value) {
+	print(name);
+	print(" = ");
+	print_int(value);
+	print("\n");
+}
+
+int main(void) {
+	show("fib(20)", fib(20));
+	show("ackermann(2, 3)", ackermann(2, 3));
+	show("gcd(1071, 462)", gcd(1071, 462));
+	hanoi(10, 1, 3, 2);
+	show("hanoi(10) moves", moves);
+	show("is_even(101)", is_even(101));
+	show("is_odd(101)", is_odd(101));
+	return fib(10);
+}
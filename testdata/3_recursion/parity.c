@@ -0,0 +1,6 @@
+// Mutually recursive across files with main.c's is_odd
+int is_odd(unsigned n);
+
+int is_even(unsigned n) {
+	return n == 0 ? 1 : is_odd(n - 1);
+}
@@ -0,0 +1,62 @@
+#include "../runtime/runtime.h"
+
+extern int (*const operations[])(int, int);
+extern const char *const operation_names[];
+extern const int operation_count;
+int multiply(int a, int b);
+
+typedef int (*compare)(int, int);
+
+static int ascending(int a, int b) {
+	return a - b;
+}
+
+static int descending(int a, int b) {
+	return b - a;
+}
+
+static void sort(int *values, int n, compare cmp) {
+	for (int i = 1; i < n; i++) {
+		int v = values[i];
+		int j = i;
+		for (; j > 0 && cmp(values[j - 1], v) > 0; j--)
+			values[j] = values[j - 1];
+		values[j] = v;
+	}
+}
+
+static void show(const int *values, int n) {
+	for (int i = 0; i < n; i++) {
+		print_int(values[i]);
+		print(i + 1 < n ? " " : "\n");
+	}
+}
+
+static compare order(int reverse) {
+	return reverse ? descending : ascending;
+}
+
+// Writable, so it can be repointed at run time
+int (*accumulate)(int, int) = multiply;
+
+int main(void) {
+	for (int i = 0; i < operation_count; i++) {
+		print(operation_names[i]);
+		print("(7, 3) = ");
+		print_int(operations[i](7, 3));
+		print("\n");
+	}
+
+	int values[] = {5, 3, 9, 1, 7};
+	sort(values, 5, order(0));
+	show(values, 5);
+	sort(values, 5, order(1));
+	show(values, 5);
+
+	int total = 1;
+	for (int i = 1; i <= 4; i++)
+		total = accumulate(total, i);
+	accumulate = operations[0];
+	total = accumulate(total, 1);
+	return total;
+}
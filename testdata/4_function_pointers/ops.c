@@ -0,0 +1,16 @@
+int add(int a, int b) {
+	return a + b;
+}
+
+int subtract(int a, int b) {
+	return a - b;
+}
+
+int multiply(int a, int b) {
+	return a * b;
+}
+
+// A table of addresses, filled in by the linker
+int (*const operations[])(int, int) = {add, subtract, multiply};
+const char *const operation_names[] = {"add", "subtract", "multiply"};
+const int operation_count = 3;
@@ -0,0 +1,3 @@
+// Data on either side of _edata: initialized, then zeroed
+int initialized[4] = {1, 2, 3, 4};
+int zeroed[1024];
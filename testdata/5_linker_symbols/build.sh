@@ -0,0 +1,4 @@
+#!/bin/sh
+
+# The flags driver/tests/testdata.rs compiles with, at -O0
+gcc -O0 -fno-tree-loop-distribute-patterns -fno-stack-protector -c ./*.c ../runtime/runtime.c
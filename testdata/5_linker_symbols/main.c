@@ -0,0 +1,51 @@
+#include "../runtime/runtime.h"
+
+// Defined by the linker, from the layout
+extern char __executable_start[], __ehdr_start[], _etext[], _edata[], __bss_start[], _end[];
+
+// Defined by nothing, so 0
+extern void optional_hook(void) __attribute__((weak));
+extern int optional_setting __attribute__((weak));
+
+extern int initialized[4];
+extern int zeroed[1024];
+
+// Code would reach the weak symbols through the GOT, which weld doesn't
+// build, so their addresses are kept here instead (not const, so gcc can't
+// see through them)
+void (*hook)(void) = optional_hook;
+int *setting = &optional_setting;
+
+// Hides where `p` came from, so gcc can't assume distinct symbols are at
+// distinct addresses
+static unsigned long address(const void *p) {
+	__asm__("" : "+r"(p));
+	return (unsigned long)p;
+}
+
+static int checks;
+
+static void check(const char *what, int ok) {
+	print(what);
+	print(ok ? " yes\n" : " no\n");
+	checks += ok;
+}
+
+int main(void) {
+	check("no hook", !hook);
+	check("no setting", !setting);
+	if (hook)
+		hook();
+
+	unsigned long start = address(__executable_start);
+	check("headers at the start", address(__ehdr_start) == start);
+	check("ELF magic", __ehdr_start[1] == 'E' && __ehdr_start[2] == 'L' && __ehdr_start[3] == 'F');
+	check("code before _etext", start < address(main) && address(main) < address(_etext));
+	check("data before _edata", address(&initialized[3]) < address(_edata));
+	check("_edata at __bss_start", address(_edata) == address(__bss_start));
+	check("bss before _end", address(&zeroed[1023]) < address(_end));
+	check("_end aligned", address(_end) % 8 == 0);
+
+	zeroed[1023] = initialized[3];
+	return checks + zeroed[1023];
+}
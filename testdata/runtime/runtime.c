@@ -0,0 +1,49 @@
+#include <sys/syscall.h>
+#include "runtime.h"
+
+int main(void);
+
+// The kernel starts us with %rsp 16-byte aligned; calling main pushes the
+// return address, which leaves the stack as the ABI expects on entry to a
+// function.
+asm(".globl _start\n"
+    "_start:\n"
+    "  xor %ebp, %ebp\n"
+    "  call main\n"
+    "  mov %eax, %edi\n"
+    "  mov $60, %eax\n" // SYS_exit
+    "  syscall\n");
+
+static long write(int fd, const char *buf, unsigned long count) {
+	long ret;
+	asm volatile("syscall"
+		     : "=a"(ret)
+		     : "a"(SYS_write), "D"(fd), "S"(buf), "d"(count)
+		     : "rcx", "r11", "memory");
+	return ret;
+}
+
+unsigned long length(const char *s) {
+	unsigned long n = 0;
+	while (s[n])
+		n++;
+	return n;
+}
+
+void print(const char *s) {
+	write(1, s, length(s));
+}
+
+void print_int(long n) {
+	char digits[24];
+	int i = sizeof(digits);
+	unsigned long u = n < 0 ? -(unsigned long)n : (unsigned long)n;
+	digits[--i] = 0;
+	do {
+		digits[--i] = '0' + u % 10;
+		u /= 10;
+	} while (u);
+	if (n < 0)
+		digits[--i] = '-';
+	print(&digits[i]);
+}
@@ -0,0 +1,11 @@
+// Just enough of a C library for the test programs: there is no libc to
+// link against, so output goes straight to the write system call.
+
+#ifndef RUNTIME_H
+#define RUNTIME_H
+
+unsigned long length(const char *s);
+void print(const char *s);
+void print_int(long n);
+
+#endif
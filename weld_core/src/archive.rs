@@ -0,0 +1,240 @@
+//! Static libraries: finding them on the search path (-l, -L), and picking
+//! the members a link needs. As in GNU ld, a member is only linked if it
+//! defines a symbol something already linked refers to and nothing defines.
+//! Every archive is searched until nothing more is pulled in, as if they
+//! were all in one --start-group, so their order doesn't matter.
+
+use crate::{is_global_definition, WeldError};
+use elf::logical::Relocatable;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// Where `-l<name>` is: lib<name>.a in the first of `search_paths` that has
+// one, or for `-l:<file>`, that file
+pub fn find_library(name: &str, search_paths: &[PathBuf]) -> Result<PathBuf, WeldError> {
+    let file = match name.strip_prefix(':') {
+        Some(file) => file.to_string(),
+        None => format!("lib{name}.a"),
+    };
+    search_paths
+        .iter()
+        .map(|dir| dir.join(&file))
+        .find(|path| Path::is_file(path))
+        .ok_or_else(|| WeldError::new(format!("cannot find -l{name}")))
+}
+
+// The members of `archives` (path, contents) that `objects` need, in the
+// order they were pulled in, each with the index of its archive. Members
+// are named like ld names them: `lib.a(member.o)`.
+pub fn members<'a>(
+    objects: &[Relocatable],
+    archives: &[(&str, &'a [u8])],
+) -> Result<Vec<(usize, Relocatable<'a>)>, Vec<WeldError>> {
+    let mut candidates = Vec::new();
+    let mut errors = Vec::new();
+    for (archive, &(path, bytes)) in archives.iter().enumerate() {
+        let members = match elf_parser::archive::parse_archive(bytes) {
+            Ok(members) => members,
+            Err(err) => {
+                errors.push(WeldError::new(format!("{path}: {err}")));
+                continue;
+            }
+        };
+        for member in members {
+            let name = format!("{path}({})", member.name);
+            match elf_parser::parse(&name, member.bytes) {
+                Ok(member) => candidates.push(Some((archive, member))),
+                Err(err) => errors.push(WeldError::new(format!("{name}: {err}"))),
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut defined = HashSet::new();
+    let mut undefined = HashSet::new();
+    for f in objects {
+        add_symbols(f, &mut defined, &mut undefined);
+    }
+
+    let mut chosen = Vec::new();
+    loop {
+        let wanted = |f: &Relocatable| {
+            f.symbols.iter().any(|s| {
+                is_global_definition(s) && undefined.contains(&s.name) && !defined.contains(&s.name)
+            })
+        };
+        let Some(next) = candidates
+            .iter()
+            .position(|c| c.as_ref().is_some_and(|(_, member)| wanted(member)))
+        else {
+            break;
+        };
+        let (archive, member) = candidates[next].take().unwrap();
+        log::debug!(target: "parse", "{} pulled in", member.path);
+        add_symbols(&member, &mut defined, &mut undefined);
+        chosen.push((archive, member));
+    }
+    Ok(chosen)
+}
+
+// Records the globals `f` defines, and those it refers to but doesn't
+fn add_symbols(f: &Relocatable, defined: &mut HashSet<String>, undefined: &mut HashSet<String>) {
+    for s in &f.symbols {
+        if is_global_definition(s) {
+            defined.insert(s.name.clone());
+        } else if !s.is_defined() && !s.name.is_empty() && !s.is_weak() {
+            // Weak references don't pull members in
+            undefined.insert(s.name.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use elf::file::{SectionFlags, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_FUNCTION};
+    use elf::logical::RelocationType;
+    use elf::object::ObjectBuilder;
+
+    // A GNU archive of `members` (name, contents)
+    pub(crate) fn archive(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut bytes = elf_parser::archive::MAGIC.to_vec();
+        for (name, data) in members {
+            let header = format!(
+                "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                format!("{name}/"),
+                0,
+                0,
+                0,
+                644,
+                data.len()
+            );
+            bytes.extend_from_slice(header.as_bytes());
+            bytes.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                bytes.push(b'\n');
+            }
+        }
+        bytes
+    }
+
+    // testdata/0_simple as gcc -O0 compiles it, for tests that want a whole
+    // program without needing a C compiler: main.o, multiply.o, square.o and
+    // start.o, in that order, with only their code. It exits with 16.
+    pub(crate) fn simple_program() -> [(&'static str, Vec<u8>); 4] {
+        // `text`'s functions as (name, offset, size), and its calls as (offset
+        // of the call's operand, callee)
+        let object = |text: &[u8], functions: &[(&str, u64, u64)], calls: &[(u64, &str)]| {
+            let mut object = ObjectBuilder::new();
+            let code = SectionFlags::Alloc | SectionFlags::Executable;
+            let section = object.section(".text", code, text);
+            let defined: Vec<(&str, usize)> = functions
+                .iter()
+                .map(|&(name, offset, size)| {
+                    let symbol = object.symbol(
+                        name,
+                        SYMBOL_BINDING_GLOBAL,
+                        SYMBOL_TYPE_FUNCTION,
+                        section,
+                        offset,
+                        size,
+                    );
+                    (name, symbol)
+                })
+                .collect();
+            for &(offset, callee) in calls {
+                let symbol = match defined.iter().find(|(name, _)| *name == callee) {
+                    Some(&(_, symbol)) => symbol,
+                    None => object.undefined(callee),
+                };
+                object.relocation(section, offset, RelocationType::Plt32 as u32, symbol, -4);
+            }
+            object.encode()
+        };
+        [
+            (
+                "main.o",
+                object(
+                    &[
+                        0x55, 0x48, 0x89, 0xe5, 0xbf, 0x04, 0x00, 0x00, 0x00, 0xe8, 0x00, 0x00,
+                        0x00, 0x00, 0x5d, 0xc3,
+                    ],
+                    &[("main", 0, 0x10)],
+                    &[(0xa, "square")],
+                ),
+            ),
+            (
+                "multiply.o",
+                object(
+                    &[
+                        0x55, 0x48, 0x89, 0xe5, 0x89, 0x7d, 0xfc, 0x89, 0x75, 0xf8, 0x8b, 0x45,
+                        0xfc, 0x0f, 0xaf, 0x45, 0xf8, 0x5d, 0xc3,
+                    ],
+                    &[("multiply", 0, 0x13)],
+                    &[],
+                ),
+            ),
+            (
+                "square.o",
+                object(
+                    &[
+                        0x55, 0x48, 0x89, 0xe5, 0x48, 0x83, 0xec, 0x10, 0x89, 0x7d, 0xfc, 0x8b,
+                        0x55, 0xfc, 0x8b, 0x45, 0xfc, 0x89, 0xd6, 0x89, 0xc7, 0xe8, 0x00, 0x00,
+                        0x00, 0x00, 0xc9, 0xc3,
+                    ],
+                    &[("square", 0, 0x1c)],
+                    &[(0x16, "multiply")],
+                ),
+            ),
+            (
+                "start.o",
+                object(
+                    &[
+                        0x55, 0x48, 0x89, 0xe5, 0x89, 0x7d, 0xfc, 0x89, 0x75, 0xf8, 0x8b, 0x45,
+                        0xf8, 0x8b, 0x55, 0xfc, 0x89, 0xc0, 0x89, 0xd7, 0x0f, 0x05, 0x90, 0x5d,
+                        0xc3, 0x55, 0x48, 0x89, 0xe5, 0xe8, 0x00, 0x00, 0x00, 0x00, 0xbe, 0x3c,
+                        0x00, 0x00, 0x00, 0x89, 0xc7, 0xe8, 0x00, 0x00, 0x00, 0x00, 0x90, 0x5d,
+                        0xc3,
+                    ],
+                    &[("call_exit", 0, 0x19), ("_start", 0x19, 0x18)],
+                    &[(0x1e, "main"), (0x2a, "call_exit")],
+                ),
+            ),
+        ]
+    }
+
+    // An object of testdata/0_simple, built in memory
+    pub(crate) fn testdata(name: &str) -> Vec<u8> {
+        let program = simple_program();
+        program.into_iter().find(|(n, _)| *n == name).unwrap().1
+    }
+
+    #[test]
+    fn only_needed_members_are_pulled_in() {
+        let [main, multiply, square, start] =
+            ["main.o", "multiply.o", "square.o", "start.o"].map(testdata);
+        let objects = [
+            elf_parser::parse("main.o", &main).unwrap(),
+            elf_parser::parse("start.o", &start).unwrap(),
+        ];
+        // multiply is only wanted once square is in; main is defined already
+        let lib = archive(&[
+            ("multiply.o", &multiply),
+            ("square.o", &square),
+            ("main.o", &main),
+        ]);
+        let pulled = members(&objects, &[("lib.a", &lib)]).unwrap();
+        let names: Vec<&str> = pulled.iter().map(|(_, m)| m.path.as_str()).collect();
+        assert_eq!(names, ["lib.a(square.o)", "lib.a(multiply.o)"]);
+
+        // Nothing is pulled in for a symbol defined already
+        let everything = [
+            elf_parser::parse("main.o", &main).unwrap(),
+            elf_parser::parse("square.o", &square).unwrap(),
+            elf_parser::parse("multiply.o", &multiply).unwrap(),
+        ];
+        assert!(members(&everything, &[("lib.a", &lib)]).unwrap().is_empty());
+    }
+}
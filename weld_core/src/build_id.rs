@@ -0,0 +1,133 @@
+//! Build ID notes (`--build-id`). The ID lives in a `.note.gnu.build-id`
+//! section covered by a PT_NOTE segment, which is how debuggers, crash
+//! reporters and `file` find it.
+//!
+//! Hash-based styles are computed over the encoded executable with the
+//! descriptor zeroed, so identical links produce identical IDs.
+
+use md5::Md5;
+use sha1::{Digest, Sha1};
+use std::str::FromStr;
+
+const NOTE_HEADER_SIZE: usize = 12;
+const NOTE_NAME: &[u8; 4] = b"GNU\0";
+const NT_GNU_BUILD_ID: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildId {
+    Sha1,
+    Md5,
+    Uuid,
+    Fast, // 64-bit XXH3, like lld
+    Hex(Vec<u8>),
+}
+
+impl BuildId {
+    pub fn descriptor_size(&self) -> usize {
+        match self {
+            BuildId::Sha1 => 20,
+            BuildId::Md5 | BuildId::Uuid => 16,
+            BuildId::Fast => 8,
+            BuildId::Hex(bytes) => bytes.len(),
+        }
+    }
+
+    // The complete note, with a zeroed descriptor
+    pub fn empty_note(&self) -> Vec<u8> {
+        let descriptor_size = self.descriptor_size();
+        let mut note = Vec::with_capacity(Self::descriptor_offset() + descriptor_size);
+        note.extend_from_slice(&(NOTE_NAME.len() as u32).to_le_bytes());
+        note.extend_from_slice(&(descriptor_size as u32).to_le_bytes());
+        note.extend_from_slice(&NT_GNU_BUILD_ID.to_le_bytes());
+        note.extend_from_slice(NOTE_NAME);
+        note.resize(note.len() + descriptor_size.next_multiple_of(4), 0);
+        note
+    }
+
+    pub fn descriptor_offset() -> usize {
+        NOTE_HEADER_SIZE + NOTE_NAME.len()
+    }
+
+    // `output` is the encoded executable, with the descriptor still zeroed
+    pub fn compute(&self, output: &[u8]) -> Vec<u8> {
+        match self {
+            BuildId::Sha1 => Sha1::digest(output).to_vec(),
+            BuildId::Md5 => Md5::digest(output).to_vec(),
+            BuildId::Uuid => uuid::Uuid::new_v4().as_bytes().to_vec(),
+            BuildId::Fast => xxhash_rust::xxh3::xxh3_64(output).to_le_bytes().to_vec(),
+            BuildId::Hex(bytes) => bytes.clone(),
+        }
+    }
+}
+
+impl FromStr for BuildId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha1" | "tree" => Ok(BuildId::Sha1),
+            "md5" => Ok(BuildId::Md5),
+            "uuid" => Ok(BuildId::Uuid),
+            "fast" => Ok(BuildId::Fast),
+            _ => {
+                let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) else {
+                    return Err(format!("unknown build ID style '{s}'"));
+                };
+                // Which also makes every byte a character, so `hex` can be
+                // sliced anywhere
+                if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                    return Err(format!("invalid hex in build ID '{s}'"));
+                }
+                if hex.is_empty() || hex.len() % 2 != 0 {
+                    return Err(format!(
+                        "build ID '{s}' must have an even number of hex digits"
+                    ));
+                }
+                (0..hex.len())
+                    .step_by(2)
+                    .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map(BuildId::Hex)
+                    .map_err(|_| format!("invalid hex in build ID '{s}'"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BuildId;
+
+    #[test]
+    fn parse_styles() {
+        assert_eq!("sha1".parse(), Ok(BuildId::Sha1));
+        assert_eq!("fast".parse(), Ok(BuildId::Fast));
+        assert_eq!(
+            "0xdeadBEEF".parse(),
+            Ok(BuildId::Hex(vec![0xde, 0xad, 0xbe, 0xef]))
+        );
+        assert!("0xabc".parse::<BuildId>().is_err());
+        assert_eq!(
+            "0x1é1".parse::<BuildId>(),
+            Err("invalid hex in build ID '0x1é1'".to_string())
+        );
+        assert!("0x+f".parse::<BuildId>().is_err());
+        assert!("sha256".parse::<BuildId>().is_err());
+    }
+
+    #[test]
+    fn note_layout() {
+        let note = BuildId::Hex(vec![1, 2, 3]).empty_note();
+        assert_eq!(&note[..12], &[4, 0, 0, 0, 3, 0, 0, 0, 3, 0, 0, 0]);
+        assert_eq!(&note[12..16], b"GNU\0");
+        assert_eq!(note.len(), 20); // Descriptor padded to 4 bytes
+    }
+
+    #[test]
+    fn hash_is_deterministic() {
+        let a = BuildId::Sha1.compute(b"weld");
+        assert_eq!(a.len(), BuildId::Sha1.descriptor_size());
+        assert_eq!(a, BuildId::Sha1.compute(b"weld"));
+        assert_ne!(a, BuildId::Sha1.compute(b"weld!"));
+    }
+}
@@ -0,0 +1,109 @@
+//! Symbol cross references (`--cref`) and tracing (`--trace-symbol`).
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Entry {
+    pub definition: Option<String>,
+    pub references: Vec<String>,
+}
+
+// Global symbols only, sorted by name
+#[derive(Debug, Default)]
+pub struct CrossReference {
+    pub symbols: BTreeMap<String, Entry>,
+}
+
+impl CrossReference {
+    pub fn new(inputs: &[elf::logical::Relocatable]) -> CrossReference {
+        let mut cref = CrossReference::default();
+        for f in inputs {
+            for s in &f.symbols {
+                if s.name.is_empty() || !s.is_global() {
+                    continue;
+                }
+                let entry = cref.symbols.entry(s.name.clone()).or_default();
+                if s.is_defined() {
+                    entry.definition.get_or_insert(f.path.clone());
+                } else if !entry.references.contains(&f.path) {
+                    entry.references.push(f.path.clone());
+                }
+            }
+        }
+        cref
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "Cross Reference Table\n")?;
+        writeln!(out, "{:<40} File", "Symbol")?;
+        for (name, entry) in &self.symbols {
+            let definition = entry.definition.as_deref().unwrap_or("(undefined)");
+            writeln!(out, "{name:<40} {definition}")?;
+            for reference in &entry.references {
+                writeln!(out, "{:<40} {reference}", "")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Reports the symbols in `f` named by `--trace-symbol`, in the format GNU ld uses
+pub fn trace(f: &elf::logical::Relocatable, traced: &[String]) {
+    if traced.is_empty() {
+        return;
+    }
+    for s in &f.symbols {
+        if s.is_section() || s.is_file() || !traced.contains(&s.name) {
+            continue;
+        }
+        if s.is_defined() {
+            println!("{}: definition of {}", f.path, s.name);
+        } else {
+            println!("{}: reference to {}", f.path, s.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elf::logical::{Relocatable, SymbolInfo};
+
+    fn symbol(name: &str, defined: bool, global: bool) -> SymbolInfo {
+        let mut s = SymbolInfo {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        s.symbol.relative_to_section = defined as u16;
+        s.symbol.info = (global as u8) << 4;
+        s
+    }
+
+    #[test]
+    fn definitions_and_references() {
+        let inputs = vec![
+            Relocatable {
+                path: "main.o".to_string(),
+                symbols: vec![symbol("main", true, true), symbol("square", false, true)],
+                ..Default::default()
+            },
+            Relocatable {
+                path: "square.o".to_string(),
+                symbols: vec![symbol("square", true, true), symbol("helper", true, false)],
+                ..Default::default()
+            },
+        ];
+
+        let cref = CrossReference::new(&inputs);
+        assert_eq!(cref.symbols.len(), 2);
+        assert_eq!(
+            cref.symbols["square"],
+            Entry {
+                definition: Some("square.o".to_string()),
+                references: vec!["main.o".to_string()],
+            }
+        );
+        assert!(cref.symbols["main"].references.is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+//! Section garbage collection (--gc-sections). The section the entry point
+//! is in is live, as are those the program reaches without a reference
+//! (initializer arrays, .init and .fini, anything marked SHF_GNU_RETAIN),
+//! and so is every section a live one has a relocation against. Loaded
+//! sections that aren't live are left out. Debug sections are always kept,
+//! but don't keep anything else alive: references from them to what was
+//! left out go unresolved, as they do for code the compiler dropped.
+
+use crate::{definition_site, Definition};
+use elf::file::SectionType;
+use elf::logical::{Relocatable, Section};
+use std::collections::{BTreeMap, HashSet};
+
+// Kept whether or not anything refers to it
+const SHF_GNU_RETAIN: u64 = 0x200000;
+
+fn is_root(section: &Section) -> bool {
+    matches!(
+        section.section_type,
+        SectionType::ArrayOfConstructors
+            | SectionType::ArrayOfDestructors
+            | SectionType::ArrayOfPreConstructors
+    ) || section.flags & SHF_GNU_RETAIN != 0
+        || [".init", ".fini"].contains(&section.name.as_str())
+}
+
+// The live sections, as (file, section index), or None if there's no entry
+// point to start from, in which case everything is kept
+pub(crate) fn live_sections(
+    inputs: &[Relocatable],
+    globals: &BTreeMap<&str, Definition>,
+    entry: &str,
+) -> Option<HashSet<(usize, usize)>> {
+    let &(file, s) = globals.get(entry)?;
+    let start = definition_site(inputs, globals, file, s)?;
+
+    // Each file's relocations, by the section they apply to
+    let by_section: Vec<Vec<Vec<usize>>> = inputs
+        .iter()
+        .map(|f| {
+            let mut by_section = vec![Vec::new(); f.sections.len()];
+            for (i, r) in f.relocations.iter().enumerate() {
+                if let Some(relocations) = by_section.get_mut(r.section) {
+                    relocations.push(i);
+                }
+            }
+            by_section
+        })
+        .collect();
+
+    let mut live = HashSet::new();
+    let mut work = vec![(start.0, start.1)];
+    for (file, f) in inputs.iter().enumerate() {
+        for (index, section) in f.sections.iter().enumerate() {
+            if is_root(section) {
+                work.push((file, index));
+            }
+        }
+    }
+    while let Some((file, index)) = work.pop() {
+        if !live.insert((file, index)) {
+            continue;
+        }
+        let f = &inputs[file];
+        for &i in &by_section[file][index] {
+            let symbol = &f.symbols[f.relocations[i].symbol];
+            if let Some((file, index, _)) = definition_site(inputs, globals, file, symbol) {
+                work.push((file, index));
+            }
+        }
+    }
+    Some(live)
+}
@@ -0,0 +1,99 @@
+//! Identical code folding (--icf=all). Read-only code sections with the
+//! same contents and alignment, going to the same output section, whose
+//! relocations are alike and refer to the same places, are laid out once:
+//! the first of them stands for the rest, whose symbols all take its
+//! addresses. As with --icf=all in gold and lld, two functions may then
+//! compare equal.
+//!
+//! Sections are compared in input order in a single pass, so a reference to
+//! a section folded earlier counts as one to what it was folded into, but
+//! sections that only differ by referring to each other (mutual recursion)
+//! aren't folded.
+
+use crate::{definition_site, output_section_name, Definition, Site};
+use elf::file::SectionFlags;
+use elf::logical::Relocatable;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// What two sections must share to be folded
+#[derive(PartialEq, Eq, Hash)]
+struct Key<'a> {
+    output_section: &'a str,
+    alignment: u64,
+    bytes: &'a [u8],
+    // (offset, type, addend, target)
+    relocations: Vec<(usize, u32, i64, Site)>,
+}
+
+// Each folded section, as (file, section index), mapped to the one it's
+// folded into. Only sections in `live` are considered, if given.
+pub(crate) fn fold(
+    inputs: &[Relocatable],
+    globals: &BTreeMap<&str, Definition>,
+    live: Option<&HashSet<(usize, usize)>>,
+) -> HashMap<(usize, usize), (usize, usize)> {
+    let mut relocations: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (file, f) in inputs.iter().enumerate() {
+        for (i, r) in f.relocations.iter().enumerate() {
+            relocations.entry((file, r.section)).or_default().push(i);
+        }
+    }
+
+    let mut folded = HashMap::new();
+    let mut first = HashMap::new();
+    for (file, f) in inputs.iter().enumerate() {
+        for (index, section) in f.sections.iter().enumerate() {
+            let code = section.flags & SectionFlags::Executable as u64 != 0
+                && section.flags & SectionFlags::Write as u64 == 0;
+            let Some(output_section) = output_section_name(section) else {
+                continue;
+            };
+            if !code
+                || section.is_nobits()
+                || live.is_some_and(|live| !live.contains(&(file, index)))
+            {
+                continue;
+            }
+            let alike: Option<Vec<_>> = relocations
+                .get(&(file, index))
+                .into_iter()
+                .flatten()
+                .map(|&i| {
+                    let r = &f.relocations[i];
+                    let (to_file, to_index, offset) =
+                        definition_site(inputs, globals, file, &f.symbols[r.symbol])?;
+                    let (to_file, to_index) = folded
+                        .get(&(to_file, to_index))
+                        .copied()
+                        .unwrap_or((to_file, to_index));
+                    Some((
+                        r.offset,
+                        r.info as u32,
+                        r.addend,
+                        (to_file, to_index, offset),
+                    ))
+                })
+                .collect();
+            // Anything unresolved is reported later; leave it be
+            let Some(mut alike) = alike else {
+                continue;
+            };
+            alike.sort();
+            let key = Key {
+                output_section,
+                alignment: section.alignment,
+                bytes: &section.bytes,
+                relocations: alike,
+            };
+            match first.get(&key) {
+                Some(&into) => {
+                    folded.insert((file, index), into);
+                }
+                None => {
+                    first.insert(key, (file, index));
+                }
+            }
+        }
+    }
+    folded
+}
@@ -0,0 +1,675 @@
+//! Incremental relinking (`--incremental`). A full link leaves room after
+//! each input section and saves a `State` next to the output: where every
+//! input section went, how much room it has, and the address of every
+//! global. When only some inputs changed since, `relink` writes their
+//! sections over the old ones in the existing executable and re-applies
+//! just the relocations that could have changed: all of those in the
+//! changed files, and those elsewhere that refer to a global that moved.
+//!
+//! Nothing else moves, so anything that would need it (a section outgrowing
+//! its room, a section appearing or disappearing, different options) is
+//! reported as a reason to link from scratch instead.
+
+use crate::{
+    build_id, entry_point, map, output_section_name, provided, relocate_one, resolve_globals,
+    target, LinkOptions, Outcome, Symbols, WeldError, SHF_PERMISSIONS,
+};
+use elf::logical::{Executable, Relocatable};
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Write};
+
+const VERSION: &str = "weld incremental 1";
+
+// Where e_machine and e_entry are in the ELF64 file header
+const MACHINE_OFFSET: usize = 18;
+const ENTRY_OFFSET: usize = 24;
+
+// Room reserved after an input section of `size` bytes, so it can grow a
+// little (a few instructions, a new string) without moving anything
+pub fn headroom(size: u64) -> u64 {
+    (size / 4).max(64)
+}
+
+// Identifies file contents, so changed inputs can be told apart
+pub fn hash(bytes: &[u8]) -> u64 {
+    xxhash_rust::xxh3::xxh3_64(bytes)
+}
+
+// The options that decide the layout. Anything else (e.g. symbol tracing)
+// can change without forcing a full link.
+fn fingerprint(options: &LinkOptions) -> u64 {
+    let layout = format!(
+        "{:?} {:?} {} {} {}",
+        options.build_id, options.layout, options.entry, options.gc_sections, options.icf
+    );
+    hash(layout.as_bytes())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct OutputSection {
+    name: String,
+    address: u64,
+    offset: u64, // In the file
+    size: u64,
+    nobits: bool,
+    flags: u64,
+    fill: u8,
+}
+
+// Where one input section went
+#[derive(Debug, Clone, PartialEq)]
+struct Slot {
+    file: usize,
+    index: usize,
+    output: usize, // Into `State::sections`
+    offset: u64,   // Relative to the start of the output section
+    size: u64,
+    capacity: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct State {
+    options: u64,
+    output: u64,               // Hash of the executable this describes
+    files: Vec<(u64, String)>, // Content hash and path, in command line order
+    sections: Vec<OutputSection>,
+    slots: Vec<Slot>,
+    globals: BTreeMap<String, u64>,
+}
+
+pub enum Relink {
+    // The executable was patched in place; this describes it now
+    Patched(State),
+    // Why it couldn't be
+    FullLink(String),
+}
+
+impl State {
+    // Describes `output`, the encoding of `exec` as just linked from `inputs`
+    // (whose contents hash to `hashes`) with `options.incremental` set
+    pub fn new(
+        inputs: &[Relocatable],
+        hashes: &[u64],
+        exec: &Executable,
+        output: &[u8],
+        link_map: &map::LinkMap,
+        options: &LinkOptions,
+    ) -> State {
+        let sections = exec
+            .sections
+            .iter()
+            .zip(&exec.section_headers)
+            .map(|(section, shdr)| OutputSection {
+                name: section.name.clone(),
+                address: shdr.virtual_address,
+                offset: shdr.offset,
+                size: shdr.size,
+                nobits: section.is_nobits(),
+                flags: shdr.flags,
+                fill: crate::fill(section),
+            })
+            .collect();
+        let slots = link_map
+            .input_sections
+            .iter()
+            .map(|input| Slot {
+                file: input.file_index,
+                index: input.index,
+                output: exec.find_section(&input.output_section).unwrap(),
+                offset: input.offset,
+                size: input.size,
+                capacity: input.capacity,
+            })
+            .collect();
+        State {
+            options: fingerprint(options),
+            output: hash(output),
+            files: hashes
+                .iter()
+                .zip(inputs)
+                .map(|(&hash, f)| (hash, f.path.clone()))
+                .collect(),
+            sections,
+            slots,
+            globals: link_map
+                .symbols
+                .iter()
+                .filter(|s| s.global)
+                .map(|s| (s.name.clone(), s.address))
+                .collect(),
+        }
+    }
+
+    // One record per line. Names and paths come last, so may contain spaces.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{VERSION}")?;
+        writeln!(out, "options {:x}", self.options)?;
+        writeln!(out, "output {:x}", self.output)?;
+        for (hash, path) in &self.files {
+            writeln!(out, "file {hash:x} {path}")?;
+        }
+        for s in &self.sections {
+            writeln!(
+                out,
+                "section {:x} {:x} {:x} {} {:x} {:x} {}",
+                s.address, s.offset, s.size, s.nobits as u8, s.flags, s.fill, s.name
+            )?;
+        }
+        for s in &self.slots {
+            writeln!(
+                out,
+                "slot {} {} {} {:x} {:x} {:x}",
+                s.file, s.index, s.output, s.offset, s.size, s.capacity
+            )?;
+        }
+        for (name, address) in &self.globals {
+            writeln!(out, "global {address:x} {name}")?;
+        }
+        Ok(())
+    }
+
+    pub fn read(text: &str) -> Result<State, String> {
+        let mut lines = text.lines();
+        if lines.next() != Some(VERSION) {
+            return Err("incremental state is from another version of weld".to_string());
+        }
+        let mut state = State {
+            options: 0,
+            output: 0,
+            files: Vec::new(),
+            sections: Vec::new(),
+            slots: Vec::new(),
+            globals: BTreeMap::new(),
+        };
+        for (number, line) in lines.enumerate() {
+            let bad = || format!("incremental state is corrupt at line {}", number + 2);
+            let (kind, rest) = line.split_once(' ').ok_or_else(bad)?;
+            let fields = match kind {
+                "options" | "output" => 1,
+                "file" | "global" => 2,
+                "section" => 7,
+                "slot" => 6,
+                _ => return Err(bad()),
+            };
+            let fields: Vec<&str> = rest.splitn(fields, ' ').collect();
+            let hex = |i: usize| {
+                fields
+                    .get(i)
+                    .and_then(|field| u64::from_str_radix(field, 16).ok())
+                    .ok_or_else(bad)
+            };
+            let decimal = |i: usize| {
+                fields
+                    .get(i)
+                    .and_then(|field| field.parse().ok())
+                    .ok_or_else(bad)
+            };
+            let text = |i: usize| fields.get(i).map(|field| field.to_string()).ok_or_else(bad);
+            match kind {
+                "options" => state.options = hex(0)?,
+                "output" => state.output = hex(0)?,
+                "file" => state.files.push((hex(0)?, text(1)?)),
+                "global" => {
+                    state.globals.insert(text(1)?, hex(0)?);
+                }
+                "section" => state.sections.push(OutputSection {
+                    address: hex(0)?,
+                    offset: hex(1)?,
+                    size: hex(2)?,
+                    nobits: hex(3)? != 0,
+                    flags: hex(4)?,
+                    fill: u8::try_from(hex(5)?).map_err(|_| bad())?,
+                    name: text(6)?,
+                }),
+                _ => {
+                    let slot = Slot {
+                        file: decimal(0)?,
+                        index: decimal(1)?,
+                        output: decimal(2)?,
+                        offset: hex(3)?,
+                        size: hex(4)?,
+                        capacity: hex(5)?,
+                    };
+                    if slot.file >= state.files.len() || slot.output >= state.sections.len() {
+                        return Err(bad());
+                    }
+                    state.slots.push(slot);
+                }
+            }
+        }
+        Ok(state)
+    }
+}
+
+// Whether everything `state` places in `output` (the header's e_entry, each
+// output section and the room for each input section in it, and the build
+// ID) is inside it. A state file edited by hand or written for some other
+// output may not be.
+fn fits(state: &State, output: &[u8], options: &LinkOptions) -> bool {
+    let within = |offset: u64, size: u64| {
+        offset
+            .checked_add(size)
+            .is_some_and(|end| end <= output.len() as u64)
+    };
+    let sections = state
+        .sections
+        .iter()
+        .all(|s| s.nobits || within(s.offset, s.size));
+    let slots = state.slots.iter().all(|slot| {
+        let out = &state.sections[slot.output];
+        out.nobits
+            || out
+                .offset
+                .checked_add(slot.offset)
+                .is_some_and(|start| within(start, slot.capacity))
+    });
+    let build_id = options.build_id.as_ref().is_none_or(|style| {
+        let note = state
+            .sections
+            .iter()
+            .find(|s| s.name == ".note.gnu.build-id");
+        note.is_some_and(|note| {
+            let descriptor = build_id::BuildId::descriptor_offset() + style.descriptor_size();
+            within(note.offset, descriptor as u64)
+        })
+    });
+    within(ENTRY_OFFSET as u64, 8) && sections && slots && build_id
+}
+
+// Patches `output`, the executable `state` describes, to be the link of
+// `inputs` (whose contents hash to `hashes`). Errors are those a full link
+// would report; `output` is then only partly patched.
+pub fn relink(
+    state: &State,
+    output: &mut [u8],
+    inputs: &[Relocatable],
+    hashes: &[u64],
+    options: &LinkOptions,
+) -> Result<Relink, Vec<WeldError>> {
+    let full_link = |reason: String| Ok(Relink::FullLink(reason));
+    if fingerprint(options) != state.options {
+        return full_link("link options changed".to_string());
+    }
+    // What's left out or folded can change with any input
+    if options.gc_sections || options.icf {
+        return full_link("--gc-sections and --icf links can't be patched".to_string());
+    }
+    if hash(output) != state.output {
+        return full_link("output was modified after the last link".to_string());
+    }
+    if !fits(state, output, options) {
+        return full_link("incremental state doesn't match the output".to_string());
+    }
+    if inputs.len() != state.files.len()
+        || inputs
+            .iter()
+            .zip(&state.files)
+            .any(|(f, (_, path))| &f.path != path)
+    {
+        return full_link("input files changed".to_string());
+    }
+    let target = target::select(inputs)?;
+    if output.get(MACHINE_OFFSET..MACHINE_OFFSET + 2) != Some(&target.machine().to_le_bytes()) {
+        return full_link(format!("inputs are now {}", target.name()));
+    }
+    let changed: Vec<bool> = hashes
+        .iter()
+        .zip(&state.files)
+        .map(|(hash, (old, _))| hash != old)
+        .collect();
+    for (f, _) in inputs.iter().zip(&changed).filter(|&(_, &changed)| changed) {
+        log::info!(target: "layout", "{} changed; patching it in place", f.path);
+    }
+
+    // Every changed input section must fit where the old one was
+    let mut slots = state.slots.clone();
+    for (file, f) in inputs.iter().enumerate().filter(|&(file, _)| changed[file]) {
+        let old: Vec<&Slot> = slots.iter().filter(|s| s.file == file).collect();
+        let new: Vec<usize> = (0..f.sections.len())
+            .filter(|&index| output_section_name(&f.sections[index]).is_some())
+            .collect();
+        let same = old.len() == new.len()
+            && old.iter().zip(&new).all(|(slot, &index)| {
+                slot.index == index
+                    && output_section_name(&f.sections[index])
+                        == Some(state.sections[slot.output].name.as_str())
+            });
+        if !same {
+            return full_link(format!("{}: sections were added or removed", f.path));
+        }
+        for slot in &old {
+            let section = &f.sections[slot.index];
+            let out = &state.sections[slot.output];
+            let problem = if section.size > slot.capacity {
+                "outgrew the room left for it"
+            } else if !slot.offset.is_multiple_of(section.alignment.max(1)) {
+                "needs more alignment"
+            } else if out.nobits && !section.is_nobits() {
+                "now has contents"
+            } else if section.flags & SHF_PERMISSIONS & !out.flags != 0 {
+                "needs other permissions"
+            } else {
+                continue;
+            };
+            return full_link(format!("{}: {} {problem}", f.path, section.name));
+        }
+    }
+
+    let (globals, conflicts) = resolve_globals(inputs);
+    if !conflicts.is_empty() {
+        return Err(conflicts);
+    }
+    // What the linker defines stays where it was, but can't be added
+    let mut linker_defined = BTreeMap::new();
+    for name in provided::referenced(inputs, &globals) {
+        let Some(&address) = state.globals.get(name) else {
+            return full_link(format!("{name} is referred to, but wasn't defined"));
+        };
+        linker_defined.insert(name.to_string(), address);
+    }
+    let symbols = Symbols {
+        placements: slots
+            .iter()
+            .map(|s| ((s.file, s.index), (s.output, s.offset)))
+            .collect(),
+        globals,
+        addresses: state.sections.iter().map(|s| s.address).collect(),
+        provided: linker_defined,
+    };
+
+    for slot in slots.iter_mut().filter(|s| changed[s.file]) {
+        let section = &inputs[slot.file].sections[slot.index];
+        let out = &state.sections[slot.output];
+        if !out.nobits {
+            let start = (out.offset + slot.offset) as usize;
+            let room = &mut output[start..start + slot.capacity as usize];
+            room.fill(out.fill);
+            if section.is_nobits() {
+                room[..section.size as usize].fill(0);
+            } else {
+                room[..section.bytes.len()].copy_from_slice(&section.bytes);
+            }
+        }
+        slot.size = section.size;
+    }
+
+    // A global that moved, appeared or disappeared changes every reference to it
+    let mut addresses = symbols.provided.clone();
+    for (&name, &(file, s)) in &symbols.globals {
+        let placed = symbols
+            .placements
+            .contains_key(&(file, s.symbol.relative_to_section as usize));
+        if let (true, Some(address)) = (placed, symbols.address(file, s)) {
+            addresses.insert(name.to_string(), address);
+        }
+    }
+    let moved: HashSet<&str> = addresses
+        .iter()
+        .filter(|&(name, address)| state.globals.get(name) != Some(address))
+        .chain(
+            state
+                .globals
+                .iter()
+                .filter(|&(name, _)| !addresses.contains_key(name)),
+        )
+        .map(|(name, _)| name.as_str())
+        .collect();
+    for name in &moved {
+        log::debug!(target: "resolve", "{name} moved");
+    }
+
+    let mut errors = Vec::new();
+    for (file, f) in inputs.iter().enumerate() {
+        for (i, r) in f.relocations.iter().enumerate() {
+            let symbol = &f.symbols[r.symbol];
+            // Globals are resolved by name, even those the file defines itself: its
+            // weak definition may have lost to a strong one elsewhere that moved
+            let by_name = !symbol.is_defined() || symbol.is_global();
+            let refers_to_moved = by_name && moved.contains(symbol.name.as_str());
+            if !changed[file] && !refers_to_moved {
+                continue;
+            }
+            let Some(&(out, base)) = symbols.placements.get(&(file, r.section)) else {
+                continue;
+            };
+            let section = &state.sections[out];
+            let bytes: &mut [u8] = if section.nobits {
+                &mut []
+            } else {
+                let start = section.offset as usize;
+                &mut output[start..start + section.size as usize]
+            };
+            let debug = section.name.starts_with(".debug_")
+                && section.flags & elf::file::SectionFlags::Alloc as u64 == 0;
+            match relocate_one(
+                inputs,
+                &symbols,
+                (file, i),
+                base,
+                bytes,
+                section.address,
+                debug,
+            ) {
+                Outcome::Applied(_) => {}
+                Outcome::Note(note) => log::debug!(target: "relocate", "{note}"),
+                Outcome::Error(err) => errors.push(err),
+            }
+        }
+    }
+
+    let text = state
+        .sections
+        .iter()
+        .find(|s| s.name == ".text")
+        .map(|s| s.address)
+        .unwrap_or_default();
+    let entry = entry_point(&symbols, options, text, &mut errors);
+    output[ENTRY_OFFSET..ENTRY_OFFSET + 8].copy_from_slice(&entry.to_le_bytes());
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // As in a full link, the build ID covers everything else
+    if let Some(style) = &options.build_id {
+        let note = state
+            .sections
+            .iter()
+            .find(|s| s.name == ".note.gnu.build-id")
+            .unwrap();
+        let start = note.offset as usize + build_id::BuildId::descriptor_offset();
+        let descriptor = start..start + style.descriptor_size();
+        output[descriptor.clone()].fill(0);
+        let id = style.compute(output);
+        output[descriptor].copy_from_slice(&id);
+    }
+
+    Ok(Relink::Patched(State {
+        output: hash(output),
+        files: hashes
+            .iter()
+            .zip(inputs)
+            .map(|(&hash, f)| (hash, f.path.clone()))
+            .collect(),
+        slots,
+        globals: addresses,
+        ..state.clone()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(name: &str) -> (String, Vec<u8>) {
+        (name.to_string(), crate::archive::tests::testdata(name))
+    }
+
+    fn parse(files: &[(String, Vec<u8>)]) -> (Vec<Relocatable<'_>>, Vec<u64>) {
+        let inputs = files
+            .iter()
+            .map(|(path, bytes)| elf_parser::parse(path, bytes).unwrap())
+            .collect();
+        (inputs, files.iter().map(|(_, bytes)| hash(bytes)).collect())
+    }
+
+    fn options() -> LinkOptions {
+        LinkOptions {
+            build_id: Some(build_id::BuildId::Sha1),
+            incremental: true,
+            ..Default::default()
+        }
+    }
+
+    fn full_link(files: &[(String, Vec<u8>)]) -> (Vec<u8>, State) {
+        let (inputs, hashes) = parse(files);
+        let linked = crate::link(&inputs, &options()).unwrap();
+        let output = linked.executable.encode();
+        let state = State::new(
+            &inputs,
+            &hashes,
+            &linked.executable,
+            &output,
+            &linked.map,
+            &options(),
+        );
+        (output, state)
+    }
+
+    // Flips the first byte of .text in an object file
+    fn edit_text(file: &mut (String, Vec<u8>)) {
+        let offset = {
+            let f = elf_parser::parse(&file.0, &file.1).unwrap();
+            f.sections[f.find_section(".text").unwrap()].offset as usize
+        };
+        file.1[offset] ^= 0xff;
+    }
+
+    fn files() -> Vec<(String, Vec<u8>)> {
+        ["main.o", "multiply.o", "square.o", "start.o"]
+            .map(testdata)
+            .to_vec()
+    }
+
+    #[test]
+    fn patching_matches_a_full_link() {
+        let mut files = files();
+        let (mut output, state) = full_link(&files);
+
+        let mut text = Vec::new();
+        state.write(&mut text).unwrap();
+        assert_eq!(
+            State::read(std::str::from_utf8(&text).unwrap()),
+            Ok(state.clone())
+        );
+
+        edit_text(&mut files[1]);
+        let (inputs, hashes) = parse(&files);
+        let Ok(Relink::Patched(patched)) =
+            relink(&state, &mut output, &inputs, &hashes, &options())
+        else {
+            panic!("expected the output to be patched");
+        };
+        let (expected, expected_state) = full_link(&files);
+        assert!(output == expected);
+        assert_eq!(patched, expected_state);
+    }
+
+    // a.o's _start calls its own weak f, which b.o's f, `at` bytes into its
+    // .text, overrides
+    fn weak_and_strong(at: u64) -> Vec<(String, Vec<u8>)> {
+        use elf::file::*;
+        use elf::logical::RelocationType;
+        use elf::object::ObjectBuilder;
+
+        let code = SectionFlags::Alloc | SectionFlags::Executable;
+        let mut a = ObjectBuilder::new();
+        let text = a.section(".text", code, &[0xe8, 0, 0, 0, 0, 0xc3, 0xc3, 0xc3]);
+        a.symbol(
+            "_start",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_FUNCTION,
+            text,
+            0,
+            6,
+        );
+        let f = a.symbol("f", SYMBOL_BINDING_WEAK, SYMBOL_TYPE_FUNCTION, text, 6, 1);
+        a.relocation(text, 1, RelocationType::Plt32 as u32, f, -4);
+
+        let mut b = ObjectBuilder::new();
+        let text = b.section(".text", code, &[0xc3; 16]);
+        b.symbol(
+            "f",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_FUNCTION,
+            text,
+            at,
+            1,
+        );
+        vec![
+            ("a.o".to_string(), a.encode()),
+            ("b.o".to_string(), b.encode()),
+        ]
+    }
+
+    #[test]
+    fn references_to_a_weak_definition_follow_the_strong_one() {
+        let (mut output, state) = full_link(&weak_and_strong(0));
+        let files = weak_and_strong(8);
+        let (inputs, hashes) = parse(&files);
+        let Ok(Relink::Patched(patched)) =
+            relink(&state, &mut output, &inputs, &hashes, &options())
+        else {
+            panic!("expected the output to be patched");
+        };
+        let (expected, expected_state) = full_link(&files);
+        assert!(output == expected);
+        assert_eq!(patched, expected_state);
+    }
+
+    #[test]
+    fn falls_back_when_a_section_outgrows_its_room() {
+        let mut files = files();
+        let (mut output, mut state) = full_link(&files);
+        edit_text(&mut files[1]);
+        let (inputs, hashes) = parse(&files);
+
+        let mut other = options();
+        other.layout.image_base = Some(0x800000);
+        assert!(matches!(
+            relink(&state, &mut output, &inputs, &hashes, &other),
+            Ok(Relink::FullLink(_))
+        ));
+
+        for slot in state.slots.iter_mut().filter(|s| s.file == 1) {
+            slot.capacity = 0;
+        }
+        let Ok(Relink::FullLink(reason)) =
+            relink(&state, &mut output, &inputs, &hashes, &options())
+        else {
+            panic!("expected a full link");
+        };
+        assert!(reason.contains("outgrew the room left for it"), "{reason}");
+    }
+
+    #[test]
+    fn falls_back_when_the_state_does_not_fit_the_output() {
+        let mut files = files();
+        let (mut output, state) = full_link(&files);
+        edit_text(&mut files[1]);
+        let (inputs, hashes) = parse(&files);
+
+        let mut past_the_end = state.clone();
+        let slot = past_the_end.slots.iter_mut().find(|s| s.file == 1).unwrap();
+        slot.capacity = output.len() as u64;
+        let mut overflowing = state.clone();
+        overflowing.sections[1].offset = u64::MAX;
+        for state in [past_the_end, overflowing] {
+            let Ok(Relink::FullLink(reason)) =
+                relink(&state, &mut output, &inputs, &hashes, &options())
+            else {
+                panic!("expected a full link");
+            };
+            assert_eq!(reason, "incremental state doesn't match the output");
+        }
+    }
+}
@@ -0,0 +1,588 @@
+//! Output layout. `plan` takes the output sections in any order and decides
+//! where each goes: allocated sections are grouped by permissions into
+//! loadable segments (read-only, then code, then writable data with .bss
+//! last), and every section gets a file offset and virtual address honoring
+//! its alignment. The headers, and any read-only sections, share the first
+//! segment; each later segment starts on a new page (of the target's largest
+//! size), so p_offset and p_vaddr stay congruent modulo the page size as the
+//! loader requires. Non-alloc sections (debug info, .shstrtab) follow at
+//! address 0.
+//!
+//! `LayoutOptions` is the only place addresses come from: everything else
+//! (headers, entry point, relocations) reads them back from the `Plan`.
+
+use crate::target::Target;
+use elf::file::{
+    ProgramHeader, SectionFlags, SectionHeader, SectionType, SegmentFlags, SegmentType,
+    FILE_HEADER_SIZE, PROGRAM_HEADER_SIZE, SECTION_HEADER_SIZE,
+};
+use elf::logical::{Executable, Section};
+use elf::string_table::StrTab;
+use std::collections::BTreeMap;
+
+// Set on .debug_str and friends. Their entries are one byte wide.
+const SHF_STRINGS: u64 = 0x20;
+
+#[derive(Debug, Default)]
+pub struct LayoutOptions {
+    // Where the file header is loaded (--image-base), if not the target's
+    // default
+    pub image_base: Option<u64>,
+    // Output section name -> address (-Ttext, -Tdata, -Tbss, --section-start).
+    // Each such section starts a new segment.
+    pub section_starts: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    // In file order, each with its offset and virtual address assigned.
+    // The first is the null section and the last is .shstrtab.
+    pub sections: Vec<Section<'static>>,
+    pub section_headers: Vec<SectionHeader>,
+    pub program_headers: Vec<ProgramHeader>,
+    pub section_header_offset: u64,
+}
+
+impl Plan {
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.sections.iter().position(|s| s.name == name)
+    }
+
+    // Section contents may still change (e.g. relocation), but not their sizes
+    pub fn into_executable(self, target: &dyn Target, entrypoint: u64) -> Executable {
+        let mut hdr = elf::file::FileHeader::default();
+        hdr.identification.magic = [0x7f, 0x45, 0x4c, 0x46];
+        hdr.identification.format_class = 2; // 64-bit
+        hdr.identification.endianness = 1; // little-endian
+        hdr.identification.format_version = 1; // original ELF
+        hdr.identification.os_abi = 0; // System V
+        hdr.object_file_type = 0x02; // ET_EXEC
+        hdr.machine_type = target.machine();
+        hdr.object_file_version = 1; // original ELF
+        hdr.processor_specific_flags = target.flags();
+        hdr.file_header_size = FILE_HEADER_SIZE as u16;
+        hdr.program_header_offset = FILE_HEADER_SIZE as u64;
+        hdr.program_headers_total_size = PROGRAM_HEADER_SIZE as u16;
+        hdr.section_headers_total_size = SECTION_HEADER_SIZE as u16;
+        hdr.program_header_entry_count = self.program_headers.len() as u16;
+        hdr.section_header_entry_count = self.section_headers.len() as u16;
+        hdr.sh_section_name_stringtab_entry_index = self.section_headers.len() as u16 - 1; // Always last
+        hdr.entrypoint = entrypoint;
+        hdr.section_header_offset = self.section_header_offset;
+
+        Executable {
+            file_header: hdr,
+            program_headers: self.program_headers,
+            sections: self.sections,
+            section_headers: self.section_headers,
+        }
+    }
+}
+
+// Sections are placed in ascending rank; the sort is stable, so sections of
+// the same rank keep the order they were given in.
+fn rank(s: &Section) -> u8 {
+    if !s.is_alloc() {
+        4
+    } else if s.flags & SectionFlags::Write as u64 != 0 {
+        if s.is_nobits() {
+            3
+        } else {
+            2
+        }
+    } else if s.flags & SectionFlags::Executable as u64 != 0 {
+        1
+    } else {
+        0
+    }
+}
+
+fn segment_flags(s: &Section) -> u32 {
+    let mut flags = SegmentFlags::Read as u32;
+    if s.flags & SectionFlags::Write as u64 != 0 {
+        flags |= SegmentFlags::Write as u32;
+    }
+    if s.flags & SectionFlags::Executable as u64 != 0 {
+        flags |= SegmentFlags::Execute as u32;
+    }
+    flags
+}
+
+const TOO_LARGE: &str = "output is too large";
+
+// None if rounding up overflows
+fn align_up(value: u64, alignment: u64) -> Option<u64> {
+    value.checked_next_multiple_of(alignment.max(1))
+}
+
+pub fn plan(
+    mut sections: Vec<Section<'static>>,
+    options: &LayoutOptions,
+    target: &dyn Target,
+) -> Result<Plan, String> {
+    let page_size = target.page_size();
+    let image_base = options.image_base.unwrap_or(target.image_base());
+    if !image_base.is_multiple_of(page_size) {
+        return Err(format!(
+            "image base {image_base:#x} is not a multiple of the page size ({page_size:#x})"
+        ));
+    }
+
+    // Notes go first, so that a single PT_NOTE covers them
+    sections.sort_by_key(|s| (rank(s), s.section_type != SectionType::Notes));
+
+    let mut shstrtab = StrTab::default();
+    let mut names: Vec<u32> = sections
+        .iter()
+        .map(|s| shstrtab.insert(&s.name) as u32)
+        .collect();
+    // Inserted before measuring so the table's size includes its own name
+    names.push(shstrtab.insert(".shstrtab") as u32);
+    sections.push(Section {
+        name: ".shstrtab".to_string(),
+        section_type: SectionType::StringTable,
+        bytes: shstrtab.get_bytes().to_vec().into(),
+        size: shstrtab.len() as u64,
+        alignment: 1,
+        ..Default::default()
+    });
+    names.insert(0, 0);
+    sections.insert(0, Section::default());
+
+    // One loadable segment per run of sections with the same permissions, or
+    // starting at a given address. The headers are read-only, so they share
+    // the first segment if that is too.
+    let start = |s: &Section| options.section_starts.get(&s.name).copied();
+    let alloc: Vec<usize> = (1..sections.len())
+        .filter(|&i| sections[i].is_alloc())
+        .collect();
+    let mut runs: Vec<(u32, Vec<usize>)> = Vec::new();
+    for &i in &alloc {
+        let flags = segment_flags(&sections[i]);
+        match runs.last_mut() {
+            Some((last, members)) if *last == flags && start(&sections[i]).is_none() => {
+                members.push(i)
+            }
+            _ => runs.push((flags, vec![i])),
+        }
+    }
+    let headers_fit = runs.first().is_some_and(|(flags, members)| {
+        *flags == SegmentFlags::Read as u32 && start(&sections[members[0]]).is_none()
+    });
+    if !headers_fit {
+        runs.insert(0, (SegmentFlags::Read as u32, Vec::new()));
+    }
+    let notes: Vec<usize> = alloc
+        .iter()
+        .copied()
+        .filter(|&i| sections[i].section_type == SectionType::Notes)
+        .collect();
+    let program_header_count = runs.len() + !notes.is_empty() as usize + 1; // + PT_GNU_STACK
+
+    let too_high = |s: &Section| format!("{} does not fit in the address space", s.name);
+    let mut program_headers = Vec::new();
+    let mut offset = (FILE_HEADER_SIZE + program_header_count * PROGRAM_HEADER_SIZE) as u64;
+    let mut address = image_base
+        .checked_add(offset)
+        .ok_or("image base is too high")?;
+    for (n, (flags, members)) in runs.iter().enumerate() {
+        let (segment_offset, segment_address) = if n == 0 {
+            (0, image_base)
+        } else if let Some(fixed) = start(&sections[members[0]]) {
+            // Segments must not overlap, so they can't go backwards
+            if fixed < address {
+                return Err(format!(
+                    "{} at {fixed:#x} overlaps the previous segment, which ends at {address:#x}",
+                    sections[members[0]].name
+                ));
+            }
+            offset = align_up(offset, page_size).ok_or(TOO_LARGE)? + fixed % page_size;
+            address = fixed;
+            (offset, address)
+        } else {
+            let first = &sections[members[0]];
+            offset = align_up(offset, page_size).ok_or(TOO_LARGE)?;
+            address = align_up(address, page_size).ok_or_else(|| too_high(first))?;
+            (offset, address)
+        };
+        let mut file_end = offset;
+        for (m, &i) in members.iter().enumerate() {
+            let section = &mut sections[i];
+            // A given address is used as is, like GNU ld does
+            let aligned = if m == 0 && start(section).is_some() {
+                address
+            } else {
+                align_up(address, section.alignment).ok_or_else(|| too_high(section))?
+            };
+            offset = offset.checked_add(aligned - address).ok_or(TOO_LARGE)?;
+            address = aligned;
+            section.offset = offset;
+            section.virtual_address = address;
+            address = address
+                .checked_add(section.size)
+                .ok_or_else(|| too_high(section))?;
+            if !section.is_nobits() {
+                offset += section.size;
+                file_end = offset;
+            }
+        }
+        // The image ends 8-aligned, where _end is: GNU ld's default script
+        // pads .bss to it
+        if let (true, Some(&i)) = (n == runs.len() - 1, members.last()) {
+            let last = &mut sections[i];
+            let end = align_up(address, 8).ok_or_else(|| too_high(last))?;
+            if last.is_nobits() {
+                last.size += end - address;
+            }
+            address = end;
+        }
+        program_headers.push(ProgramHeader {
+            segment_type: SegmentType::Loadable,
+            flags: *flags,
+            offset: segment_offset,
+            virtual_address: segment_address,
+            physical_address: segment_address,
+            size_in_file: file_end - segment_offset,
+            size_in_memory: address - segment_address,
+            required_alignment: page_size,
+        });
+    }
+
+    if let (Some(&first), Some(&last)) = (notes.first(), notes.last()) {
+        let (start, end) = (&sections[first], &sections[last]);
+        program_headers.push(ProgramHeader {
+            segment_type: SegmentType::AuxiliaryInfo,
+            flags: SegmentFlags::Read as u32,
+            offset: start.offset,
+            virtual_address: start.virtual_address,
+            physical_address: start.virtual_address,
+            size_in_file: end.offset + end.size - start.offset,
+            size_in_memory: end.offset + end.size - start.offset,
+            required_alignment: notes
+                .iter()
+                .map(|&i| sections[i].alignment)
+                .max()
+                .unwrap_or(1),
+        });
+    }
+
+    // Without this, the stack is executable
+    program_headers.push(ProgramHeader {
+        segment_type: SegmentType::GnuStack,
+        flags: SegmentFlags::Read | SegmentFlags::Write,
+        required_alignment: 16,
+        ..Default::default()
+    });
+
+    for section in sections.iter_mut().skip(1).filter(|s| !s.is_alloc()) {
+        offset = align_up(offset, section.alignment).ok_or(TOO_LARGE)?;
+        section.offset = offset;
+        section.virtual_address = 0;
+        offset += section.size;
+    }
+    let section_header_offset = align_up(offset, 8).ok_or(TOO_LARGE)?;
+
+    let section_headers = sections
+        .iter()
+        .zip(names)
+        .enumerate()
+        .map(|(i, (s, name))| {
+            if i == 0 {
+                return SectionHeader::default();
+            }
+            SectionHeader {
+                name,
+                section_type: s.section_type,
+                flags: s.flags,
+                virtual_address: s.virtual_address,
+                offset: s.offset,
+                size: s.size,
+                link_to_other_section: 0,
+                misc_info: 0,
+                address_allignment_boundary: s.alignment.max(1),
+                entry_size: if s.flags & SHF_STRINGS != 0 { 1 } else { 0 },
+            }
+        })
+        .collect();
+
+    Ok(Plan {
+        sections,
+        section_headers,
+        program_headers,
+        section_header_offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::X86_64;
+
+    const PAGE_SIZE: u64 = 0x1000;
+
+    fn section(
+        name: &str,
+        section_type: SectionType,
+        flags: u64,
+        size: u64,
+        alignment: u64,
+    ) -> Section<'static> {
+        Section {
+            name: name.to_string(),
+            section_type,
+            bytes: match section_type {
+                SectionType::ProgramSpaceWithNoData => Vec::new().into(),
+                _ => vec![0xaa; size as usize].into(),
+            },
+            size,
+            flags,
+            alignment,
+            ..Default::default()
+        }
+    }
+
+    fn sample_with(options: &LayoutOptions) -> Result<Plan, String> {
+        let alloc = SectionFlags::Alloc as u64;
+        plan(
+            vec![
+                section(".debug_info", SectionType::ProgramData, 0, 0x30, 1),
+                section(
+                    ".bss",
+                    SectionType::ProgramSpaceWithNoData,
+                    alloc | SectionFlags::Write as u64,
+                    0x2000,
+                    32,
+                ),
+                section(
+                    ".data",
+                    SectionType::ProgramData,
+                    alloc | SectionFlags::Write as u64,
+                    0x11,
+                    8,
+                ),
+                section(
+                    ".text",
+                    SectionType::ProgramData,
+                    alloc | SectionFlags::Executable as u64,
+                    0x123,
+                    16,
+                ),
+                section(".rodata", SectionType::ProgramData, alloc, 0x9, 16),
+                section(".note.gnu.build-id", SectionType::Notes, alloc, 0x24, 4),
+            ],
+            options,
+            &X86_64,
+        )
+    }
+
+    fn sample() -> Plan {
+        sample_with(&LayoutOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn orders_sections_by_permissions() {
+        let plan = sample();
+        let names: Vec<&str> = plan.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "",
+                ".note.gnu.build-id",
+                ".rodata",
+                ".text",
+                ".data",
+                ".bss",
+                ".debug_info",
+                ".shstrtab"
+            ]
+        );
+    }
+
+    #[test]
+    fn segments_are_page_congruent_and_cover_their_sections() {
+        let plan = sample();
+        let loads: Vec<&ProgramHeader> = plan
+            .program_headers
+            .iter()
+            .filter(|p| p.segment_type == SegmentType::Loadable)
+            .collect();
+        assert_eq!(loads.len(), 3);
+        assert_eq!((loads[0].offset, loads[0].virtual_address), (0, 0x400000));
+        for (phdr, flags) in loads.iter().zip([4, 5, 6]) {
+            assert_eq!(phdr.flags, flags);
+            assert_eq!(phdr.offset % PAGE_SIZE, phdr.virtual_address % PAGE_SIZE);
+        }
+
+        for s in plan.sections.iter().filter(|s| s.is_alloc()) {
+            assert_eq!(s.virtual_address % s.alignment, 0, "{}", s.name);
+            let phdr = loads
+                .iter()
+                .find(|p| {
+                    (p.virtual_address..p.virtual_address + p.size_in_memory)
+                        .contains(&s.virtual_address)
+                })
+                .unwrap();
+            assert_eq!(
+                s.virtual_address - phdr.virtual_address,
+                s.offset - phdr.offset,
+                "{}",
+                s.name
+            );
+        }
+
+        // .bss takes memory but no file space
+        let data = &plan.sections[plan.find(".data").unwrap()];
+        let bss = &plan.sections[plan.find(".bss").unwrap()];
+        assert_eq!(
+            loads[2].size_in_file,
+            data.offset + data.size - loads[2].offset
+        );
+        assert_eq!(
+            loads[2].size_in_memory,
+            bss.virtual_address + bss.size - loads[2].virtual_address
+        );
+
+        let note = plan
+            .program_headers
+            .iter()
+            .find(|p| p.segment_type == SegmentType::AuxiliaryInfo)
+            .unwrap();
+        assert_eq!(
+            (note.offset, note.size_in_file),
+            (plan.sections[1].offset, 0x24)
+        );
+    }
+
+    #[test]
+    fn the_image_ends_8_aligned() {
+        let writable = SectionFlags::Alloc as u64 | SectionFlags::Write as u64;
+        let data = || section(".data", SectionType::ProgramData, writable, 0x3, 1);
+        let bss = section(
+            ".bss",
+            SectionType::ProgramSpaceWithNoData,
+            writable,
+            0x6,
+            1,
+        );
+        let end = |plan: &Plan| {
+            let load = &plan.program_headers[1];
+            load.virtual_address + load.size_in_memory
+        };
+
+        // .bss is padded to it, as GNU ld's default script does
+        let padded = plan(vec![data(), bss], &LayoutOptions::default(), &X86_64).unwrap();
+        let bss = &padded.sections[padded.find(".bss").unwrap()];
+        assert_eq!(bss.size, 0xd);
+        assert_eq!(bss.virtual_address + bss.size, end(&padded));
+
+        // Without one, the segment takes up the padding
+        let unpadded = plan(vec![data()], &LayoutOptions::default(), &X86_64).unwrap();
+        let data = &unpadded.sections[unpadded.find(".data").unwrap()];
+        assert_eq!(data.size, 0x3);
+        assert_eq!(end(&unpadded), data.virtual_address + 8);
+    }
+
+    #[test]
+    fn headers_describe_the_sections() {
+        let exec = sample().into_executable(&X86_64, 0x401000);
+        assert_eq!(exec.file_header.program_header_entry_count, 5); // 3 LOAD, NOTE, GNU_STACK
+        assert_eq!(exec.file_header.section_header_entry_count, 8);
+        assert_eq!(exec.section_headers.len(), exec.sections.len());
+
+        let shstrtab = elf::string_table::StrTab::new(&exec.sections[7].bytes);
+        for (s, shdr) in exec.sections.iter().zip(&exec.section_headers).skip(1) {
+            assert_eq!(shstrtab.get(shdr.name as usize).unwrap(), s.name);
+            assert_eq!((shdr.offset, shdr.size), (s.offset, s.size));
+        }
+        let debug = &exec.section_headers[6];
+        assert_eq!(debug.virtual_address, 0);
+
+        let bytes = exec.encode();
+        assert_eq!(
+            bytes.len() as u64,
+            exec.file_header.section_header_offset + 8 * SECTION_HEADER_SIZE as u64
+        );
+        let text = &exec.section_headers[3];
+        assert_eq!(bytes[text.offset as usize], 0xaa);
+    }
+
+    #[test]
+    fn headers_get_their_own_segment_when_nothing_else_is_read_only() {
+        let plan = plan(
+            vec![section(
+                ".text",
+                SectionType::ProgramData,
+                SectionFlags::Alloc | SectionFlags::Executable,
+                4,
+                1,
+            )],
+            &LayoutOptions::default(),
+            &X86_64,
+        )
+        .unwrap();
+        let load = &plan.program_headers[0];
+        assert_eq!(
+            load.size_in_file,
+            (FILE_HEADER_SIZE + 3 * PROGRAM_HEADER_SIZE) as u64 // 2 LOAD, GNU_STACK
+        );
+        assert_eq!(
+            (plan.sections[1].offset, plan.sections[1].virtual_address),
+            (0x1000, 0x401000)
+        );
+    }
+
+    #[test]
+    fn sections_start_where_asked() {
+        let options = LayoutOptions {
+            image_base: Some(0x200000),
+            section_starts: [
+                (".text".to_string(), 0x500000),
+                (".bss".to_string(), 0x600010),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let plan = sample_with(&options).unwrap();
+        let text = &plan.sections[plan.find(".text").unwrap()];
+        let bss = &plan.sections[plan.find(".bss").unwrap()];
+        assert_eq!(text.virtual_address, 0x500000);
+        assert_eq!(bss.virtual_address, 0x600010);
+        assert_eq!(plan.program_headers[0].virtual_address, 0x200000);
+
+        // .bss gets its own segment, congruent with its file offset
+        let loads: Vec<&ProgramHeader> = plan
+            .program_headers
+            .iter()
+            .filter(|p| p.segment_type == SegmentType::Loadable)
+            .collect();
+        assert_eq!(loads.len(), 4);
+        assert_eq!(loads[3].virtual_address, 0x600010);
+        assert_eq!(loads[3].offset % PAGE_SIZE, 0x10);
+        assert_eq!(loads[3].size_in_file, 0);
+    }
+
+    #[test]
+    fn bad_addresses_are_errors() {
+        let backwards = LayoutOptions {
+            section_starts: [(".text".to_string(), 0x1000)].into_iter().collect(),
+            ..Default::default()
+        };
+        assert!(sample_with(&backwards).unwrap_err().contains("overlaps"));
+
+        let unaligned = LayoutOptions {
+            image_base: Some(0x400100),
+            ..Default::default()
+        };
+        assert!(sample_with(&unaligned).is_err());
+
+        // The headers fit in the last page, but .text's segment doesn't
+        let top = LayoutOptions {
+            image_base: Some(0xfffffffffffff000),
+            ..Default::default()
+        };
+        assert_eq!(
+            sample_with(&top).unwrap_err(),
+            ".text does not fit in the address space"
+        );
+    }
+}
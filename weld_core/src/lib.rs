@@ -1,222 +1,1443 @@
-use elf::logical::Executable;
-use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Instruction};
-use std::collections::HashMap;
+use elf::logical::{Relocatable, Section, SymbolInfo};
+use rayon::prelude::*;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Instant;
 
 extern crate elf;
 
+pub mod archive;
+pub mod build_id;
+pub mod cref;
+mod gc;
+mod icf;
+pub mod incremental;
+pub mod layout;
+pub mod linker;
+pub mod map;
+pub mod output;
+mod provided;
+pub mod reproducible;
+pub mod target;
+
+pub use linker::Linker;
+pub use output::LinkOutput;
+
 #[derive(Debug, Default)]
-pub struct WeldError;
+pub struct WeldError {
+    pub message: String,
+}
 
-pub fn link(
-    inputs: &[elf::logical::Relocatable],
-) -> Result<elf::logical::Executable, Vec<WeldError>> {
-    let mut exec = elf::logical::Executable::default();
+impl WeldError {
+    pub fn new(message: String) -> WeldError {
+        WeldError { message }
+    }
+}
 
-    let mut start_of_section = HashMap::<String, usize>::new();
-    let mut symbols = HashMap::<String, usize>::new();
+impl fmt::Display for WeldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
 
-    for f in inputs {
-        let text_section_offset = f.find_section(".text").expect("Cannot find .text");
-        let section_start_in_exec = exec.text_section.len();
-        start_of_section.insert(f.path.clone(), section_start_in_exec);
+#[derive(Debug)]
+pub struct LinkOptions {
+    pub build_id: Option<build_id::BuildId>,
+    pub trace_symbols: Vec<String>,
+    pub layout: layout::LayoutOptions,
+    // Leave room after each input section so it can be patched in place (--incremental)
+    pub incremental: bool,
+    // Where execution starts (-e, --entry)
+    pub entry: String,
+    pub output_type: OutputType,
+    // Directories -l libraries are looked for in, in order (-L)
+    pub search_paths: Vec<PathBuf>,
+    // Leave out sections nothing reachable from the entry point refers to (--gc-sections)
+    pub gc_sections: bool,
+    // Lay out identical read-only code sections once (--icf=all)
+    pub icf: bool,
+    pub warnings: Warnings,
+}
 
-        exec.text_section
-            .extend_from_slice(f.sections[text_section_offset].bytes.as_slice());
+impl Default for LinkOptions {
+    fn default() -> LinkOptions {
+        LinkOptions {
+            build_id: None,
+            trace_symbols: Vec::new(),
+            layout: layout::LayoutOptions::default(),
+            incremental: false,
+            entry: "_start".to_string(),
+            output_type: OutputType::default(),
+            search_paths: Vec::new(),
+            gc_sections: false,
+            icf: false,
+            warnings: Warnings::default(),
+        }
+    }
+}
 
-        for s in &f.symbols {
-            if s.is_defined() {
-                // Assume defined relative to .text for now. st_shndx identifies which section we're *actually* relative to.
-                symbols.insert(s.name.clone(), section_start_in_exec + s.symbol.value as usize);
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputType {
+    // A static ET_EXEC at the addresses layout picks
+    #[default]
+    Executable,
+}
+
+// What to do about something suspect that doesn't stop the link
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Warnings {
+    // Log it, at warn level
+    #[default]
+    Report,
+    // Say nothing (-w, --no-warnings)
+    Ignore,
+    // Fail the link, as for an error (--fatal-warnings)
+    Fatal,
+}
+
+// Reports `message`, found in `phase`, as `options.warnings` says to
+fn warn(options: &LinkOptions, errors: &mut Vec<WeldError>, phase: &str, message: String) {
+    match options.warnings {
+        Warnings::Report => log::warn!(target: phase, "{message}"),
+        Warnings::Ignore => {}
+        Warnings::Fatal => errors.push(WeldError::new(message)),
+    }
+}
+
+// Input sections named like these are merged into one output section, as
+// GNU ld's default linker script does: .text.foo goes in .text, and so on.
+const MERGED_SECTION_PREFIXES: [&str; 4] = [".text", ".rodata", ".data", ".bss"];
+
+// Set on .rodata.str1.1 and friends. These only describe the input sections,
+// so aren't carried over to merged output sections.
+const SHF_MERGE_STRINGS: u64 = 0x30;
+
+// Flags that decide an output section's segment
+const SHF_PERMISSIONS: u64 = 0x7;
+
+// Bigger than anything the small code model can address
+const MAX_SECTION_SIZE: u64 = 1 << 32;
+
+// Which output section `section` goes in, if it is linked at all. Notes,
+// unwind tables, groups etc. are dropped, as are empty sections, which would
+// otherwise leave empty segments behind.
+fn output_section_name<'a>(section: &'a Section) -> Option<&'a str> {
+    if section.size == 0 {
+        return None;
+    }
+    if section.is_debug() {
+        return Some(&section.name);
+    }
+    if !section.is_alloc()
+        || section.name == ".eh_frame"
+        || !matches!(
+            section.section_type,
+            elf::file::SectionType::ProgramData | elf::file::SectionType::ProgramSpaceWithNoData
+        )
+    {
+        return None;
+    }
+    let merged = MERGED_SECTION_PREFIXES.into_iter().find(|prefix| {
+        section
+            .name
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    });
+    Some(merged.unwrap_or(&section.name))
+}
+
+// Padding between functions is never executed, but disassemblers (and
+// people) read it
+const CODE_FILL: u8 = 0x90; // NOP
+
+fn fill(section: &Section) -> u8 {
+    if section.flags & elf::file::SectionFlags::Executable as u64 != 0 {
+        CODE_FILL
+    } else {
+        0
+    }
+}
+
+// Appends `input` to `output` at its alignment, returning its offset there.
+// The gap is filled with NOPs in code and zeros elsewhere. .bss stays NOBITS
+// unless something with contents joins it.
+fn append(output: &mut Section<'static>, input: &Section) -> Result<u64, String> {
+    let alignment = input.alignment.max(1);
+    let size = if input.is_nobits() {
+        input.size
+    } else {
+        input.bytes.len() as u64
+    };
+    let offset = output
+        .size
+        .checked_next_multiple_of(alignment)
+        .filter(|offset| offset + size <= MAX_SECTION_SIZE)
+        .ok_or_else(|| format!("output section {} is too large", output.name))?;
+    output.alignment = output.alignment.max(alignment);
+    if !output.is_nobits() {
+        let fill = fill(output);
+        output.bytes.to_mut().resize(offset as usize, fill);
+    }
+    if output.is_nobits() && !input.is_nobits() {
+        output.section_type = input.section_type;
+        output.bytes.to_mut().resize(offset as usize, 0);
+    }
+    if input.is_nobits() && !output.is_nobits() {
+        output.bytes.to_mut().resize((offset + size) as usize, 0);
+    } else {
+        output.bytes.to_mut().extend_from_slice(&input.bytes);
+    }
+    output.size = offset + size;
+    Ok(offset)
+}
+
+// Grows `output` by `extra` bytes of fill, for the last input in it to grow into
+fn reserve(output: &mut Section<'static>, extra: u64) -> Result<(), String> {
+    let size = output
+        .size
+        .checked_add(extra)
+        .filter(|&size| size <= MAX_SECTION_SIZE)
+        .ok_or_else(|| format!("output section {} is too large", output.name))?;
+    if !output.is_nobits() {
+        let fill = fill(output);
+        output.bytes.to_mut().resize(size as usize, fill);
+    }
+    output.size = size;
+    Ok(())
+}
+
+// (file, definition)
+type Definition<'a> = (usize, &'a SymbolInfo);
+
+// Records `definition` of global `name`, given that it comes after every
+// definition already in `globals`. A strong definition overrides a weak one,
+// otherwise the first one wins, so the result doesn't depend on how files
+// are grouped when merging.
+fn define<'a>(
+    globals: &mut BTreeMap<&'a str, Definition<'a>>,
+    name: &'a str,
+    definition: Definition<'a>,
+) {
+    match globals.entry(name) {
+        Entry::Vacant(entry) => {
+            entry.insert(definition);
+        }
+        Entry::Occupied(mut entry) => {
+            if entry.get().1.is_weak() && !definition.1.is_weak() {
+                entry.insert(definition);
             }
         }
     }
+}
 
-    println!("\nSymbols defined: {symbols:?}");
-    println!("\nsection starts: {start_of_section:?}");
-
-    for f in inputs {
-        for r in &f.relocations {
-            if matches!(r.relo_type(), elf::logical::RelocationType::Plt32) {
-                let symbol_addr = *symbols
-                    .get(&r.symbol.name)
-                    .expect("Couldn't find symbol");
-                let base_addr = start_of_section
-                    .get(&f.path)
-                    .expect("Unknown start of section in exectuable");
-                println!("Relocating symbol {:?}, defined_at:{:?} insert_at.base:{:?} insert_at.offset:{:?}", r.symbol.name, symbol_addr, base_addr, r.offset);
-                let new_addr = ((symbol_addr as isize) - ((r.offset + base_addr) as isize)
-                    + (r.addend as isize)) as i32;
-                exec.text_section[base_addr + r.offset] = (new_addr & 0xff) as u8;
-                exec.text_section[base_addr + r.offset + 1] = (new_addr >> 8 & 0xff) as u8;
-                exec.text_section[base_addr + r.offset + 2] = (new_addr >> 16 & 0xff) as u8;
-                exec.text_section[base_addr + r.offset + 3] = (new_addr >> 24 & 0xff) as u8;
-            } else {
-                println!(
-                    "Unhandled relo_type {:#x}  in {} ; full relo: [{:?}]",
-                    r.relo_type() as usize,
-                    f.path,
-                    r
-                );
+fn is_global_definition(s: &SymbolInfo) -> bool {
+    !s.name.is_empty() && s.is_defined() && s.is_global()
+}
+
+// (file, input section index, offset within it)
+type Site = (usize, usize, u64);
+
+// Where what symbol `s` of `inputs[file]` refers to is defined. None for absolute and
+// unresolved symbols.
+fn definition_site(
+    inputs: &[Relocatable],
+    globals: &BTreeMap<&str, Definition>,
+    file: usize,
+    s: &SymbolInfo,
+) -> Option<Site> {
+    // A global or weak name refers to what it resolved to, even in a file
+    // with a weak definition of its own
+    let (file, s) = match globals.get(s.name.as_str()) {
+        Some(&definition) if s.is_global() && !s.name.is_empty() => definition,
+        _ if s.is_defined() => (file, s),
+        _ => return None,
+    };
+    match s.symbol.relative_to_section {
+        elf::file::SECTION_INDEX_UNDEFINED => None,
+        // SHN_LORESERVE and up: absolute, common etc.
+        section if section >= 0xff00 => None,
+        section => Some((file, section as usize, s.symbol.value)),
+    }
+    .filter(|&(file, section, _)| section < inputs[file].sections.len())
+}
+
+// Where execution starts: the entry symbol, or like GNU ld (with a
+// warning), the start of .text at `text` if it isn't defined
+fn entry_point(
+    symbols: &Symbols,
+    options: &LinkOptions,
+    text: u64,
+    errors: &mut Vec<WeldError>,
+) -> u64 {
+    let entry = symbols
+        .globals
+        .get(options.entry.as_str())
+        .and_then(|&(file, s)| symbols.address(file, s));
+    entry.unwrap_or_else(|| {
+        let message = format!(
+            "cannot find entry symbol {}; defaulting to {text:#x}",
+            options.entry
+        );
+        warn(options, errors, "resolve", message);
+        text
+    })
+}
+
+// What became of one relocation
+enum Outcome {
+    Applied(output::AppliedRelocation),
+    Note(String),
+    Error(WeldError),
+}
+
+// Each file's globals are collected in parallel, then merged pairwise in
+// file order. Any strong definition but the chosen one is an error.
+fn resolve_globals<'a>(
+    inputs: &'a [Relocatable],
+) -> (BTreeMap<&'a str, Definition<'a>>, Vec<WeldError>) {
+    let globals = inputs
+        .par_iter()
+        .enumerate()
+        .map(|(file, f)| {
+            let mut globals = BTreeMap::new();
+            for s in f.symbols.iter().filter(|s| is_global_definition(s)) {
+                define(&mut globals, &s.name, (file, s));
+            }
+            globals
+        })
+        .reduce(BTreeMap::new, |mut globals, later| {
+            for (name, definition) in later {
+                define(&mut globals, name, definition);
             }
+            globals
+        });
+
+    let errors = inputs
+        .par_iter()
+        .flat_map_iter(|f| {
+            let globals = &globals;
+            f.symbols.iter().filter_map(move |s| {
+                if !is_global_definition(s) || s.is_weak() {
+                    return None;
+                }
+                let &(first, chosen) = globals.get(s.name.as_str())?;
+                (!std::ptr::eq(chosen, s) && !chosen.is_weak()).then(|| {
+                    WeldError::new(format!(
+                        "{}: multiple definition of `{}`; first defined in {}",
+                        f.path, s.name, inputs[first].path
+                    ))
+                })
+            })
+        })
+        .collect();
+    (globals, errors)
+}
+
+struct Symbols<'a> {
+    // (file, input section index) -> (output section, offset within it)
+    // Only ever looked up, so its order can't reach the output
+    placements: HashMap<(usize, usize), (usize, u64)>,
+    // Ordered by name, so anything walking it (symbol tables, say) is
+    // reproducible whatever the hash seed
+    globals: BTreeMap<&'a str, Definition<'a>>,
+    // Of each output section, once laid out
+    addresses: Vec<u64>,
+    // What the linker defines, by name, once laid out
+    provided: BTreeMap<String, u64>,
+}
+
+impl Symbols<'_> {
+    // The final address `s` refers to, as seen from the file it's in. A
+    // global or weak name refers to the definition it resolved to, even in
+    // the file with a (weak) definition of its own.
+    fn address(&self, file: usize, s: &SymbolInfo) -> Option<u64> {
+        if s.name.is_empty() || !s.is_global() {
+            return self.definition_address(file, s);
+        }
+        match self.globals.get(s.name.as_str()) {
+            Some(&(file, definition)) => self.definition_address(file, definition),
+            None if !s.is_defined() => self.provided.get(&s.name).copied(),
+            None => self.definition_address(file, s),
         }
     }
 
-    let entry_point = *symbols
-        .get("_start")
-        .expect("Entrypoint symbol _start not found") as u64;
-
-    let mut decoder = Decoder::with_ip(64, &exec.text_section, 0, DecoderOptions::NONE);
-    let mut formatter = GasFormatter::new();
-    let mut output = String::new();
-    let mut instruction = Instruction::default();
-    while decoder.can_decode() {
-        decoder.decode_out(&mut instruction);
-        output.clear();
-        formatter.format(&instruction, &mut output);
-        println!("{:4X}  {}", instruction.ip(), output)
-    }
-
-    // We know exactly how many segments and sections we have.
-    // This is useful for calculating padding, building headers
-    // in a single pass, etc.
-    // At the moment, we have these segments:
-    //    1) NULL header to load PHT
-    //    2) Program data
-    // and these sections:
-    //    1) NULL section
-    //    2) .text
-    //    3) .shstrtab
-    // If a segment or section is unneeded, we can keep the header
-    // and give it a 'null' value, keeping our layout logic simpler.
-    const NUM_PROGRAM_HEADERS: u16 = 2;
-    exec.pre_text_pad = get_pre_text_pad(NUM_PROGRAM_HEADERS as usize);
-
-    const NUM_SECTION_HEADERS: u16 = 3;
-
-    // Build the executable
-    exec.file_header = build_header(&exec, NUM_PROGRAM_HEADERS, NUM_SECTION_HEADERS, entry_point);
-    exec.program_headers = build_pht(&exec);
-    exec.section_headers = build_sht(&mut exec);
-
-    Ok(exec)
-}
-
-fn get_pre_text_pad(num_program_headers: usize) -> usize {
-    // Align the text section with the page size
-    // The man page says 'loadable process segments must have congruent values
-    // for p_vaddr and p_offset, modulo the page size.' Not quite sure what this
-    // means - but aligning right at a page boundary makes a lot of sense for mmap-ing.
-    // I played with other alignments - some of which led to segfaults on execve ¯\_(ツ)_/¯
-    let page_size: usize = 4096;
-    let unpadded_text_offset =
-        elf::file::FILE_HEADER_SIZE + num_program_headers * elf::file::PROGRAM_HEADER_SIZE;
-    if unpadded_text_offset < page_size {
-        page_size - unpadded_text_offset
-    } else {
-        unpadded_text_offset % page_size
-    }
-}
-
-// Precondition: The following fields in `e` must be correctly populated:
-//    - text_section
-//    - pre_text_pad
-pub fn build_header(
-    e: &Executable,
-    program_header_entry_count: u16,
-    section_header_entry_count: u16,
-    entrypoint_offset_from_text_start: u64,
-) -> elf::file::FileHeader {
-    let mut hdr = elf::file::FileHeader::default();
-    hdr.identification.magic = [0x7f, 0x45, 0x4c, 0x46];
-    hdr.identification.format_class = 2; // 64-bit
-    hdr.identification.endianness = 1; // little-endian
-    hdr.identification.format_version = 1; // original ELF
-    hdr.identification.os_abi = 0; // System V
-    hdr.object_file_type = 0x02; // ET_EXEC
-    hdr.machine_type = 0x3e; // AMD x86-64
-    hdr.object_file_version = 1; // original ELF
-    hdr.processor_specific_flags = 0x00000102;
-    hdr.file_header_size = elf::file::FILE_HEADER_SIZE as u16;
-    hdr.program_header_offset = elf::file::FILE_HEADER_SIZE as u64;
-    hdr.program_headers_total_size = elf::file::PROGRAM_HEADER_SIZE as u16;
-    hdr.section_headers_total_size = elf::file::SECTION_HEADER_SIZE as u16;
-    hdr.program_header_entry_count = program_header_entry_count;
-    hdr.section_header_entry_count = section_header_entry_count;
-    hdr.sh_section_name_stringtab_entry_index = section_header_entry_count - 1; // Always last
-    hdr.entrypoint = entrypoint_offset_from_text_start + 0x401000;
-    hdr.section_header_offset = (elf::file::FILE_HEADER_SIZE
-        + (program_header_entry_count as usize) * elf::file::PROGRAM_HEADER_SIZE
-        + e.pre_text_pad
-        + e.text_section.len()
-        + e.shstrtab.len()) as u64;
-    hdr
-}
-
-// Precondition - executable's text_section and pre_text_pad must be populated
-pub fn build_pht(e: &elf::logical::Executable) -> Vec<elf::file::ProgramHeader> {
-    assert!(!e.text_section.is_empty());
-    assert!(e.file_header.program_header_entry_count == 2);
-
-    // The ELF header and program headers comprise a segment
-    let mut phdr0 = elf::file::ProgramHeader::default();
-    phdr0.segment_type = elf::file::SegmentType::Loadable;
-    phdr0.offset = 0;
-    phdr0.virtual_address = 0x400000; // Let's try this
-    phdr0.physical_address = 0;
-    phdr0.size_in_file = (elf::file::FILE_HEADER_SIZE + elf::file::PROGRAM_HEADER_SIZE * 2) as u64;
-    phdr0.size_in_memory = phdr0.size_in_file;
-    phdr0.required_alignment = 0x1000;
-    phdr0.flags = elf::file::SegmentFlags::Read as u32;
-
-    let mut phdr = elf::file::ProgramHeader::default();
-    phdr.segment_type = elf::file::SegmentType::Loadable;
-    phdr.offset = (elf::file::FILE_HEADER_SIZE
-        + elf::file::PROGRAM_HEADER_SIZE * (e.file_header.program_header_entry_count as usize)
-        + e.pre_text_pad) as u64;
-    phdr.virtual_address = 0x401000;
-    phdr.physical_address = phdr.virtual_address;
-    phdr.size_in_file = e.text_section.len() as u64;
-    phdr.size_in_memory = phdr.size_in_file;
-    phdr.required_alignment = 0x1000;
-    phdr.flags = elf::file::SegmentFlags::Read | elf::file::SegmentFlags::Execute;
-
-    vec![phdr0, phdr]
-}
-
-pub fn build_sht(e: &mut elf::logical::Executable) -> Vec<elf::file::SectionHeader> {
-    assert!(e.file_header.section_header_entry_count == 3);
-
-    let sh_text = elf::file::SectionHeader {
-        name: e.shstrtab.insert(".text") as u32,
-        section_type: elf::file::SectionType::ProgramData,
-        flags: elf::file::SectionFlags::Alloc | elf::file::SectionFlags::Executable,
-        virtual_address: 0x400100,
-        offset: (elf::file::FILE_HEADER_SIZE
-            + (e.file_header.program_header_entry_count as usize) * elf::file::PROGRAM_HEADER_SIZE
-            + e.pre_text_pad) as u64,
-        size: e.text_section.len() as u64,
-        link_to_other_section: 0,
-        misc_info: 0,
-        address_allignment_boundary: 1,
-        entry_size: 0,
+    // Where `s` itself is, whether or not its name resolved to it
+    fn definition_address(&self, file: usize, s: &SymbolInfo) -> Option<u64> {
+        match s.symbol.relative_to_section {
+            elf::file::SECTION_INDEX_UNDEFINED => None,
+            elf::file::SECTION_INDEX_ABSOLUTE => Some(s.symbol.value),
+            section => {
+                let &(out, offset) = self.placements.get(&(file, section as usize))?;
+                Some(
+                    self.addresses[out]
+                        .wrapping_add(offset)
+                        .wrapping_add(s.symbol.value),
+                )
+            }
+        }
+    }
+}
+
+// Applies relocation `i` of `inputs[file]` to `bytes`, the contents of the
+// output section at `address` its input section was placed `base` into.
+fn relocate_one(
+    inputs: &[Relocatable],
+    symbols: &Symbols,
+    (file, i): (usize, usize),
+    base: u64,
+    bytes: &mut [u8],
+    address: u64,
+    debug: bool,
+) -> Outcome {
+    let f = &inputs[file];
+    let r = &f.relocations[i];
+    let symbol = &f.symbols[r.symbol];
+    // Inputs' machines were checked by target::select
+    let target = target::for_machine(f.machine).unwrap();
+    let offset = base.wrapping_add(r.offset as u64);
+    let place = address.wrapping_add(offset);
+    let symbol_addr = match symbols.address(file, symbol) {
+        Some(address) => address,
+        // Nothing defines it, and nothing has to
+        None if !symbol.is_defined() && symbol.is_weak() => {
+            target.undefined_weak(r.relo_type(), place, r.addend)
+        }
+        // Debug info may describe code that wasn't linked
+        None if debug => {
+            return Outcome::Note(format!(
+                "Unresolved debug relocation in {} ; full relo: [{:?}]",
+                f.path, r
+            ))
+        }
+        None => {
+            return Outcome::Error(WeldError::new(format!(
+                "{}: undefined reference to `{}`",
+                f.path, symbol.name
+            )))
+        }
     };
+    log::trace!(
+        target: "relocate",
+        "{}: {:?} at {place:#x} to {} = {symbol_addr:#x}{:+}",
+        f.path,
+        r.relo_type(),
+        symbol.name,
+        r.addend
+    );
+    match target.apply(r.relo_type(), bytes, offset, symbol_addr, r.addend, place) {
+        Ok(value) => Outcome::Applied(output::AppliedRelocation {
+            file: f.path.clone(),
+            section: f.sections[r.section].name.clone(),
+            site: place,
+            relocation_type: r.relo_type(),
+            // A section symbol in no section of the file is absolute, which
+            // GNU ld calls *ABS*
+            target: match symbol.is_section() {
+                true => f
+                    .sections
+                    .get(symbol.symbol.relative_to_section as usize)
+                    .map_or("*ABS*", |s| &s.name)
+                    .to_string(),
+                false => symbol.name.clone(),
+            },
+            target_address: symbol_addr,
+            addend: r.addend,
+            value,
+        }),
+        Err(err) => Outcome::Error(WeldError::new(format!("{}: {err}", f.path))),
+    }
+}
+
+// Symbol resolution and relocation run on the current rayon thread pool.
+// The output doesn't depend on how many threads it has, nor on anything
+// else but the inputs and options.
+pub fn link(
+    inputs: &[Relocatable],
+    options: &LinkOptions,
+) -> Result<output::LinkOutput, Vec<WeldError>> {
+    link_with(inputs, options, None)
+}
 
-    let sh_shstrtab = elf::file::SectionHeader {
-        name: e.shstrtab.insert(".shstrtab") as u32,
-        section_type: elf::file::SectionType::StringTable,
-        flags: elf::file::SectionFlags::Alloc | elf::file::SectionFlags::Executable,
-        virtual_address: 0,
-        offset: sh_text.offset + sh_text.size,
-        size: e.shstrtab.len() as u64,
-        link_to_other_section: 0,
-        misc_info: 0,
-        address_allignment_boundary: 1,
-        entry_size: 0,
+// `link`, with the order of work that shouldn't matter shuffled by `shuffle`
+// if given. The determinism checker links both ways and compares.
+fn link_with(
+    inputs: &[Relocatable],
+    options: &LinkOptions,
+    shuffle: Option<u64>,
+) -> Result<output::LinkOutput, Vec<WeldError>> {
+    let target = target::select(inputs)?;
+    let mut link_map = map::LinkMap::default();
+    let mut errors = Vec::new();
+    let mut outputs: Vec<Section<'static>> = Vec::new();
+    let mut symbols = Symbols {
+        placements: HashMap::new(),
+        globals: BTreeMap::new(),
+        addresses: Vec::new(),
+        provided: BTreeMap::new(),
     };
 
-    let sh0 = elf::file::SectionHeader {
-    flags : elf::file::SectionFlags::Alloc | elf::file::SectionFlags::Executable,
-    ..Default::default()
+    // Globals are resolved first, as deciding which sections to leave out
+    // or fold needs to know what refers to what
+    let resolving = Instant::now();
+    let (globals, conflicts) = resolve_globals(inputs);
+    for (name, &(file, _)) in &globals {
+        log::debug!(target: "resolve", "{name} defined in {}", inputs[file].path);
+    }
+    log::info!(target: "resolve", "{} globals in {:.2?}", globals.len(), resolving.elapsed());
+    errors.extend(conflicts);
+    let provided = provided::referenced(inputs, &globals);
+
+    let started = Instant::now();
+    let live = match options.gc_sections {
+        true => gc::live_sections(inputs, &globals, &options.entry),
+        false => None,
     };
-    vec![sh0, sh_text, sh_shstrtab]
+    let folded = match options.icf {
+        true => icf::fold(inputs, &globals, live.as_ref()),
+        false => HashMap::new(),
+    };
+
+    for (file, f) in inputs.iter().enumerate() {
+        cref::trace(f, &options.trace_symbols);
+
+        // Output sections are created in the order their names are first seen
+        for (index, section) in f.sections.iter().enumerate() {
+            let Some(name) = output_section_name(section) else {
+                continue;
+            };
+            if section.is_alloc()
+                && live
+                    .as_ref()
+                    .is_some_and(|live| !live.contains(&(file, index)))
+            {
+                log::debug!(target: "layout", "{}: removing unused section {}", f.path, section.name);
+                continue;
+            }
+            // What it's folded into comes first, so has been placed already
+            if let Some(&(into, into_index)) = folded.get(&(file, index)) {
+                if let Some(&placement) = symbols.placements.get(&(into, into_index)) {
+                    log::debug!(
+                        target: "layout",
+                        "{}: folding {} into {}'s",
+                        f.path,
+                        section.name,
+                        inputs[into].path
+                    );
+                    symbols.placements.insert((file, index), placement);
+                }
+                continue;
+            }
+            let out = match outputs.iter().position(|s| s.name == name) {
+                Some(out) => out,
+                None => {
+                    outputs.push(Section {
+                        name: name.to_string(),
+                        section_type: section.section_type,
+                        flags: if section.is_debug() {
+                            section.flags
+                        } else {
+                            section.flags & !SHF_MERGE_STRINGS
+                        },
+                        alignment: 1,
+                        ..Default::default()
+                    });
+                    outputs.len() - 1
+                }
+            };
+            outputs[out].flags |= section.flags & SHF_PERMISSIONS;
+            let offset = match append(&mut outputs[out], section) {
+                Ok(offset) => offset,
+                Err(err) => {
+                    errors.push(WeldError::new(format!("{}: {err}", f.path)));
+                    continue;
+                }
+            };
+            let size = outputs[out].size - offset;
+            // Debug sections can't hold padding: their contents are a
+            // sequence of units that consumers walk to the end
+            if options.incremental && !section.is_debug() {
+                if let Err(err) = reserve(&mut outputs[out], incremental::headroom(size)) {
+                    errors.push(WeldError::new(format!("{}: {err}", f.path)));
+                    continue;
+                }
+            }
+            symbols.placements.insert((file, index), (out, offset));
+            log::trace!(target: "layout", "{}: {} -> {name}+{offset:#x}", f.path, section.name);
+            link_map.input_sections.push(map::InputSection {
+                file: f.path.clone(),
+                file_index: file,
+                name: section.name.clone(),
+                index,
+                output_section: name.to_string(),
+                offset,
+                size,
+                capacity: outputs[out].size - offset,
+                alignment: section.alignment,
+            });
+        }
+    }
+
+    symbols.globals = globals;
+
+    if !outputs.iter().any(|s| s.name == ".text" && s.size > 0) {
+        errors.push(WeldError::new("No code to link".to_string()));
+    }
+    if outputs.len() > (u16::MAX - 4) as usize {
+        errors.push(WeldError::new(format!(
+            "Too many output sections ({})",
+            outputs.len()
+        )));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    if let Some(style) = &options.build_id {
+        let note = style.empty_note();
+        outputs.push(Section {
+            name: ".note.gnu.build-id".to_string(),
+            section_type: elf::file::SectionType::Notes,
+            size: note.len() as u64,
+            bytes: note.into(),
+            flags: elf::file::SectionFlags::Alloc as u64,
+            alignment: 4,
+            ..Default::default()
+        });
+    }
+
+    outputs.extend(provided::got(&provided));
+
+    // Layout reorders the sections, so find them again by name
+    let names: Vec<String> = outputs.iter().map(|s| s.name.clone()).collect();
+    let mut plan =
+        layout::plan(outputs, &options.layout, target).map_err(|err| vec![WeldError::new(err)])?;
+    let plan_index: Vec<usize> = names.iter().map(|n| plan.find(n).unwrap()).collect();
+    symbols.addresses = plan_index
+        .iter()
+        .map(|&i| plan.sections[i].virtual_address)
+        .collect();
+    let provided = provided::define(&plan, &provided);
+    for s in &provided {
+        log::debug!(target: "layout", "{} provided at {:#x}", s.name, s.address);
+        symbols.provided.insert(s.name.clone(), s.address);
+    }
+    for section in plan.sections.iter().filter(|s| !s.name.is_empty()) {
+        log::debug!(
+            target: "layout",
+            "{} at {:#x}, {:#x} bytes",
+            section.name,
+            section.virtual_address,
+            section.size
+        );
+    }
+    log::info!(
+        target: "layout",
+        "{} output sections in {:.2?}",
+        plan.sections.len(),
+        started.elapsed()
+    );
+
+    // Relocations are applied one output section per task. Anything to
+    // report is keyed by (file, relocation) and reported in that order.
+    let started = Instant::now();
+    let count: usize = inputs.iter().map(|f| f.relocations.len()).sum();
+    let mut outcomes = Vec::new();
+    let mut work = vec![Vec::new(); plan.sections.len()];
+    for (file, f) in inputs.iter().enumerate() {
+        for (i, r) in f.relocations.iter().enumerate() {
+            // Applied once, to the section it's folded into
+            if folded.contains_key(&(file, r.section)) {
+                continue;
+            }
+            match symbols.placements.get(&(file, r.section)) {
+                Some(&(out, base)) => work[plan_index[out]].push((file, i, base)),
+                None => outcomes.push((
+                    (file, i),
+                    Outcome::Note(format!(
+                        "Skipping relocation in unlinked section in {} ; full relo: [{:?}]",
+                        f.path, r
+                    )),
+                )),
+            }
+        }
+    }
+    if let Some(seed) = shuffle {
+        let mut shuffler = reproducible::Shuffler::new(seed);
+        for work in &mut work {
+            shuffler.shuffle(work);
+        }
+        shuffler.shuffle(&mut outcomes);
+    }
+    let symbols = &symbols;
+    outcomes.par_extend(
+        plan.sections
+            .par_iter_mut()
+            .zip(work)
+            .flat_map_iter(|(section, work)| {
+                let (address, debug) = (section.virtual_address, section.is_debug());
+                let bytes = section.bytes.to_mut();
+                work.into_iter().map(move |(file, i, base)| {
+                    let outcome =
+                        relocate_one(inputs, symbols, (file, i), base, bytes, address, debug);
+                    ((file, i), outcome)
+                })
+            }),
+    );
+    outcomes.sort_by_key(|&(key, _)| key);
+    let mut relocations = Vec::new();
+    for (_, outcome) in outcomes {
+        match outcome {
+            Outcome::Applied(applied) => relocations.push(applied),
+            Outcome::Note(note) => log::debug!(target: "relocate", "{note}"),
+            Outcome::Error(err) => errors.push(err),
+        }
+    }
+    log::info!(target: "relocate", "{count} relocations in {:.2?}", started.elapsed());
+
+    let text = plan.sections[plan.find(".text").unwrap()].virtual_address;
+    let entry_point = entry_point(symbols, options, text, &mut errors);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut exec = plan.into_executable(target, entry_point);
+
+    // The build ID is the last thing written, as it covers everything else
+    if let Some(style) = &options.build_id {
+        let id = style.compute(&exec.encode());
+        let start = build_id::BuildId::descriptor_offset();
+        let note = exec.find_section(".note.gnu.build-id").unwrap();
+        exec.sections[note].bytes.to_mut()[start..start + id.len()].copy_from_slice(&id);
+    }
+
+    for (file, f) in inputs.iter().enumerate() {
+        for s in &f.symbols {
+            if s.name.is_empty() || s.is_section() || s.is_file() {
+                continue;
+            }
+            let section = s.symbol.relative_to_section as usize;
+            let (Some(&(out, _)), Some(address)) = (
+                symbols.placements.get(&(file, section)),
+                symbols.definition_address(file, s),
+            ) else {
+                continue;
+            };
+            if exec.sections[plan_index[out]].is_debug() {
+                continue;
+            }
+            let global = symbols
+                .globals
+                .get(s.name.as_str())
+                .is_some_and(|&(_, definition)| std::ptr::eq(definition, s));
+            link_map.symbols.push(map::MappedSymbol {
+                name: s.name.clone(),
+                file: f.path.clone(),
+                output_section: names[out].clone(),
+                address,
+                size: s.symbol.size,
+                global,
+            });
+        }
+    }
+
+    link_map.symbols.extend(provided);
+
+    Ok(output::LinkOutput {
+        executable: exec,
+        map: link_map,
+        relocations,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use elf::file::*;
+    use elf::logical::RelocationType;
+    use elf::object::ObjectBuilder;
+
+    fn section(
+        section_type: SectionType,
+        flags: u64,
+        size: usize,
+        alignment: u64,
+    ) -> Section<'static> {
+        Section {
+            section_type,
+            bytes: match section_type {
+                SectionType::ProgramSpaceWithNoData => Vec::new().into(),
+                _ => vec![0xaa; size].into(),
+            },
+            size: size as u64,
+            flags,
+            alignment,
+            ..Default::default()
+        }
+    }
+
+    fn testdata(name: &str) -> Relocatable<'static> {
+        let bytes = archive::tests::testdata(name).leak();
+        elf_parser::parse(name, bytes).unwrap()
+    }
+
+    // An object built in memory, leaked like `testdata`'s
+    fn built(path: &str, build: impl FnOnce(&mut ObjectBuilder)) -> Relocatable<'static> {
+        let mut object = ObjectBuilder::new();
+        build(&mut object);
+        elf_parser::parse(path, object.encode().leak()).unwrap()
+    }
+
+    // A global function at the start of `section`
+    fn function(object: &mut ObjectBuilder, name: &str, section: u16) -> usize {
+        object.symbol(
+            name,
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_FUNCTION,
+            section,
+            0,
+            0,
+        )
+    }
+
+    // Where the input section `name` of `file` ended up
+    fn input_address(output: &LinkOutput, file: &str, name: &str) -> u64 {
+        let section = output.section(name).unwrap();
+        let input = section.inputs.iter().find(|i| i.file == file).unwrap();
+        section.address + input.offset
+    }
+
+    fn link_on(threads: usize, inputs: &[Relocatable]) -> Result<Vec<u8>, Vec<String>> {
+        let options = LinkOptions {
+            build_id: Some(build_id::BuildId::Sha1),
+            ..Default::default()
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| link(inputs, &options))
+            .map(|output| output.executable.encode())
+            .map_err(|errs| errs.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[test]
+    fn output_does_not_depend_on_thread_count() {
+        let inputs = ["main.o", "multiply.o", "square.o", "start.o"].map(testdata);
+        let serial = link_on(1, &inputs).unwrap();
+        for threads in [2, 4, 8] {
+            assert_eq!(link_on(threads, &inputs), Ok(serial.clone()));
+        }
+
+        // Errors come out in the same order too
+        let inputs = ["multiply.o", "square.o", "multiply.o", "multiply.o"].map(testdata);
+        let serial = link_on(1, &inputs).unwrap_err();
+        assert_eq!(serial.len(), 2);
+        assert!(serial[0].contains("multiple definition of `multiply`"));
+        for threads in [2, 4, 8] {
+            assert_eq!(link_on(threads, &inputs), Err(serial.clone()));
+        }
+    }
+
+    #[test]
+    fn gc_leaves_out_what_the_entry_point_cant_reach() {
+        let inputs = ["main.o", "multiply.o", "square.o", "start.o"].map(testdata);
+        let options = LinkOptions {
+            entry: "main".to_string(),
+            gc_sections: true,
+            ..Default::default()
+        };
+        let output = link(&inputs, &options).unwrap();
+        let text = output.section(".text").unwrap();
+        let linked: Vec<&str> = text
+            .inputs
+            .iter()
+            .map(|s| s.file.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(linked, ["main.o", "multiply.o", "square.o"]);
+        let main = output.symbol("main").unwrap();
+        assert_eq!(output.executable.file_header.entrypoint, main.address);
+        assert!(output.symbol("_start").is_none());
+    }
+
+    #[test]
+    fn icf_folds_identical_code() {
+        // multiply.o again, defining `times` instead
+        let mut times = testdata("multiply.o");
+        times.path = "times.o".to_string();
+        for s in times.symbols.iter_mut().filter(|s| s.name == "multiply") {
+            s.name = "times".to_string();
+        }
+        let mut inputs = Vec::from(["main.o", "multiply.o", "square.o", "start.o"].map(testdata));
+        inputs.push(times);
+
+        let address = |options: &LinkOptions, name: &str| {
+            let output = link(&inputs, options).unwrap();
+            output.symbol(name).unwrap().address
+        };
+        let apart = LinkOptions::default();
+        assert_ne!(address(&apart, "times"), address(&apart, "multiply"));
+        let folded = LinkOptions {
+            icf: true,
+            ..Default::default()
+        };
+        assert_eq!(address(&folded, "times"), address(&folded, "multiply"));
+        // Nothing else is folded: the other functions differ
+        assert_ne!(address(&folded, "square"), address(&folded, "multiply"));
+    }
+
+    #[test]
+    fn missing_entry_symbol_is_a_warning() {
+        let inputs = ["main.o", "multiply.o", "square.o", "start.o"].map(testdata);
+        let options = LinkOptions {
+            entry: "nowhere".to_string(),
+            ..Default::default()
+        };
+        let exec = link(&inputs, &options).unwrap().executable;
+        let text = exec.find_section(".text").unwrap();
+        assert_eq!(
+            exec.file_header.entrypoint,
+            exec.sections[text].virtual_address
+        );
+
+        let fatal = LinkOptions {
+            warnings: Warnings::Fatal,
+            ..options
+        };
+        let errors = link(&inputs, &fatal).unwrap_err();
+        assert_eq!(
+            errors[0].message,
+            "cannot find entry symbol nowhere; defaulting to 0x401000"
+        );
+    }
+
+    #[test]
+    fn every_relocation_type() {
+        use RelocationType::*;
+        let code = SectionFlags::Alloc | SectionFlags::Executable;
+        let start = built("start.o", |object| {
+            let text = object.section(".text", code, &[0; 32]);
+            function(object, "_start", text);
+            let target = object.undefined("target");
+            let relocations = [
+                (0, Abs64, 8),
+                (8, Pc64, 0),
+                (16, Pc32, -4),
+                (20, Plt32, -4),
+                (24, Abs32, 2),
+                (28, Abs32Signed, -2),
+            ];
+            for (offset, relocation_type, addend) in relocations {
+                object.relocation(text, offset, relocation_type as u32, target, addend);
+            }
+        });
+        let target = built("target.o", |object| {
+            let data = object.section(".data", SectionFlags::Alloc | SectionFlags::Write, &[0; 8]);
+            object.symbol(
+                "target",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_OBJECT,
+                data,
+                4,
+                4,
+            );
+        });
+        let output = link(&[start, target], &LinkOptions::default()).unwrap();
+
+        let s = output.symbol("target").unwrap().address;
+        let text = output.section(".text").unwrap();
+        let exec = &output.executable;
+        let bytes = &exec.sections[exec.find_section(".text").unwrap()].bytes;
+        let applied: Vec<_> = output.relocations_in("start.o", ".text").collect();
+        let expected = [
+            (Abs64, (s + 8) as i64),
+            (Pc64, s as i64 - text.address as i64 - 8),
+            (Pc32, s as i64 - 4 - text.address as i64 - 16),
+            (Plt32, s as i64 - 4 - text.address as i64 - 20),
+            (Abs32, (s + 2) as i64),
+            (Abs32Signed, (s - 2) as i64),
+        ];
+        assert_eq!(applied.len(), expected.len());
+        for (r, (relocation_type, value)) in applied.iter().zip(expected) {
+            assert_eq!((r.relocation_type, r.value), (relocation_type, value));
+            let at = (r.site - text.address) as usize;
+            let written = match relocation_type {
+                Abs64 | Pc64 => bytes[at..at + 8].to_vec(),
+                _ => bytes[at..at + 4].to_vec(),
+            };
+            assert_eq!(written, value.to_le_bytes()[..written.len()], "{r:?}");
+        }
+    }
+
+    #[test]
+    fn rel_links_like_rela() {
+        let executable = |implicit: bool| {
+            let start = built("start.o", |object| {
+                object.implicit_addends(implicit);
+                let code = SectionFlags::Alloc | SectionFlags::Executable;
+                let text = object.section(".text", code, &[0; 16]);
+                function(object, "_start", text);
+                let strings = object.section(".rodata", SectionFlags::Alloc as u64, b"ab\0");
+                let strings = object.section_symbol(strings);
+                object.relocation(text, 0, RelocationType::Pc32 as u32, strings, -3);
+                object.relocation(text, 8, RelocationType::Abs64 as u32, strings, 1);
+            });
+            link(&[start], &LinkOptions::default())
+                .unwrap()
+                .executable
+                .encode()
+        };
+        assert_eq!(executable(true), executable(false));
+    }
+
+    #[test]
+    fn relocation_errors() {
+        let code = SectionFlags::Alloc | SectionFlags::Executable;
+        let start = built("start.o", |object| {
+            let text = object.section(".text", code, &[0; 16]);
+            function(object, "_start", text);
+            let far = object.symbol(
+                "far",
+                SYMBOL_BINDING_LOCAL,
+                SYMBOL_TYPE_NONE,
+                SECTION_INDEX_ABSOLUTE,
+                1 << 32,
+                0,
+            );
+            let nowhere = object.undefined("nowhere");
+            object.relocation(text, 0, RelocationType::Abs32 as u32, far, 0);
+            object.relocation(text, 4, RelocationType::Pc32 as u32, nowhere, -4);
+            object.relocation(text, 8, 9, far, 0); // R_X86_64_GOTPCREL
+        });
+        let errors: Vec<String> = link(&[start], &LinkOptions::default())
+            .unwrap_err()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "start.o: Abs32 relocation at offset 0x0 overflows",
+                "start.o: undefined reference to `nowhere`",
+                "start.o: unsupported relocation type Unknown at offset 0x8",
+            ]
+        );
+    }
+
+    #[test]
+    fn absolute_section_symbols() {
+        let start = built("start.o", |object| {
+            let code = SectionFlags::Alloc | SectionFlags::Executable;
+            let text = object.section(".text", code, &[0; 8]);
+            function(object, "_start", text);
+            let absolute = object.symbol(
+                "",
+                SYMBOL_BINDING_LOCAL,
+                SYMBOL_TYPE_SECTION,
+                SECTION_INDEX_ABSOLUTE,
+                0x1000,
+                0,
+            );
+            object.relocation(text, 0, RelocationType::Abs64 as u32, absolute, 8);
+        });
+        let output = link(&[start], &LinkOptions::default()).unwrap();
+        let applied = &output.relocations[0];
+        assert_eq!(applied.target, "*ABS*");
+        assert_eq!((applied.target_address, applied.value), (0x1000, 0x1008));
+    }
+
+    #[test]
+    fn symbol_resolution() {
+        let code = SectionFlags::Alloc | SectionFlags::Executable;
+        let rodata = SectionFlags::Alloc as u64;
+        let abs64 = RelocationType::Abs64 as u32;
+        // References weak_strong, weak_weak, helper, limit and its own
+        // .rodata through its section symbol
+        let a = built("a.o", |object| {
+            let text = object.section(".text", code, &[0; 40]);
+            function(object, "_start", text);
+            object.symbol(
+                "weak_strong",
+                SYMBOL_BINDING_WEAK,
+                SYMBOL_TYPE_FUNCTION,
+                text,
+                0,
+                0,
+            );
+            object.symbol(
+                "weak_weak",
+                SYMBOL_BINDING_WEAK,
+                SYMBOL_TYPE_FUNCTION,
+                text,
+                0,
+                0,
+            );
+            let strings = object.section(".rodata", rodata, b"weld\0");
+            let strings = object.section_symbol(strings);
+            for (i, name) in ["weak_strong", "weak_weak", "helper", "limit"]
+                .into_iter()
+                .enumerate()
+            {
+                let symbol = object.undefined(name);
+                object.relocation(text, 8 * i as u64, abs64, symbol, 0);
+            }
+            object.relocation(text, 32, abs64, strings, 2);
+        });
+        let b = built("b.o", |object| {
+            let text = object.section(".text", code, &[0; 8]);
+            function(object, "weak_strong", text);
+            function(object, "helper", text);
+            object.symbol(
+                "weak_weak",
+                SYMBOL_BINDING_WEAK,
+                SYMBOL_TYPE_FUNCTION,
+                text,
+                0,
+                0,
+            );
+            object.symbol(
+                "limit",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_NONE,
+                SECTION_INDEX_ABSOLUTE,
+                0x1234,
+                0,
+            );
+        });
+        // A local helper doesn't clash with b.o's global one
+        let c = built("c.o", |object| {
+            let text = object.section(".text", code, &[0; 8]);
+            let helper = object.symbol(
+                "helper",
+                SYMBOL_BINDING_LOCAL,
+                SYMBOL_TYPE_FUNCTION,
+                text,
+                0,
+                0,
+            );
+            object.relocation(text, 0, abs64, helper, 0);
+        });
+        let output = link(&[a, b, c], &LinkOptions::default()).unwrap();
+
+        let a_text = input_address(&output, "a.o", ".text");
+        let b_text = input_address(&output, "b.o", ".text");
+        let c_text = input_address(&output, "c.o", ".text");
+        let targets: Vec<(&str, u64)> = output
+            .relocations
+            .iter()
+            .map(|r| (r.target.as_str(), r.target_address))
+            .collect();
+        assert_eq!(
+            targets,
+            [
+                ("weak_strong", b_text), // The strong definition wins
+                ("weak_weak", a_text),   // Otherwise the first
+                ("helper", b_text),
+                ("limit", 0x1234),
+                (".rodata", input_address(&output, "a.o", ".rodata")),
+                ("helper", c_text),
+            ]
+        );
+        assert_eq!(output.relocations[4].value, targets[4].1 as i64 + 2);
+    }
+
+    #[test]
+    fn references_to_a_weak_definition_go_to_the_strong_one() {
+        let code = SectionFlags::Alloc | SectionFlags::Executable;
+        // Calls its own weak f through the symbol that defines it
+        let a = built("a.o", |object| {
+            let text = object.section(".text", code, &[0; 16]);
+            function(object, "_start", text);
+            let f = object.symbol("f", SYMBOL_BINDING_WEAK, SYMBOL_TYPE_FUNCTION, text, 8, 8);
+            object.relocation(text, 0, RelocationType::Abs64 as u32, f, 0);
+        });
+        let b = built("b.o", |object| {
+            let text = object.section(".text", code, &[0; 8]);
+            function(object, "f", text);
+        });
+        let output = link(&[a, b], &LinkOptions::default()).unwrap();
+
+        let f = input_address(&output, "b.o", ".text");
+        assert_eq!(output.symbol("f").unwrap().address, f);
+        assert_eq!(output.relocations[0].target_address, f);
+    }
+
+    #[test]
+    fn undefined_weak_symbols_are_zero() {
+        let start = built("start.o", |object| {
+            let code = SectionFlags::Alloc | SectionFlags::Executable;
+            let text = object.section(".text", code, &[0; 16]);
+            function(object, "_start", text);
+            let maybe = object.symbol(
+                "maybe",
+                SYMBOL_BINDING_WEAK,
+                SYMBOL_TYPE_NONE,
+                SECTION_INDEX_UNDEFINED,
+                0,
+                0,
+            );
+            object.relocation(text, 0, RelocationType::Abs64 as u32, maybe, 0);
+            object.relocation(text, 8, RelocationType::Plt32 as u32, maybe, -4);
+        });
+        let output = link(&[start], &LinkOptions::default()).unwrap();
+        let text = output.section(".text").unwrap().address;
+        let values: Vec<(u64, i64)> = output
+            .relocations
+            .iter()
+            .map(|r| (r.target_address, r.value))
+            .collect();
+        // A call to it goes to 0, relative to where it's made from
+        assert_eq!(values, [(0, 0), (0, -4 - (text as i64 + 8))]);
+        assert!(output.symbol("maybe").is_none());
+    }
+
+    #[test]
+    fn linker_defined_symbols() {
+        let names = [
+            "__executable_start",
+            "__ehdr_start",
+            "_etext",
+            "_edata",
+            "__bss_start",
+            "_end",
+            "_GLOBAL_OFFSET_TABLE_",
+        ];
+        let abs64 = RelocationType::Abs64 as u32;
+        let start = built("start.o", |object| {
+            let code = SectionFlags::Alloc | SectionFlags::Executable;
+            let text = object.section(".text", code, &[0; 5]);
+            function(object, "_start", text);
+            let writable = SectionFlags::Alloc | SectionFlags::Write;
+            let data = object.section(".data", writable, &[0; 8 * 8]);
+            object.nobits(".bss", writable, 0x13);
+            for (i, name) in names.into_iter().enumerate() {
+                let symbol = object.undefined(name);
+                object.relocation(data, 8 * i as u64, abs64, symbol, 0);
+            }
+            // Defined here, so not by the linker
+            let end = object.undefined("end");
+            object.relocation(data, 8 * 7, abs64, end, 0);
+            object.symbol("end", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_NONE, data, 8, 0);
+        });
+        let output = link(&[start], &LinkOptions::default()).unwrap();
+
+        let section = |name: &str| output.section(name).unwrap();
+        let (text, data, bss) = (section(".text"), section(".data"), section(".bss"));
+        let got = section(".got.plt");
+        assert_eq!(got.size, 24);
+        let expected = [
+            0x400000,
+            0x400000,
+            text.address + 5,
+            got.address + 24, // After .data
+            got.address + 24,
+            bss.address + bss.size,
+            got.address,
+        ];
+        // .bss is padded to 8 bytes, so _end is at its end, as in GNU ld
+        assert_eq!(
+            bss.address + bss.size,
+            (bss.address + 0x13).next_multiple_of(8)
+        );
+        for (name, address) in names.into_iter().zip(expected) {
+            assert_eq!(output.symbol(name).unwrap().address, address, "{name}");
+        }
+        let targets: Vec<u64> = output
+            .relocations_in("start.o", ".data")
+            .map(|r| r.target_address)
+            .collect();
+        assert_eq!(targets[..7], expected);
+        assert_eq!(targets[7], data.address + 8);
+        assert_eq!(output.symbol("end").unwrap().file, "start.o");
+
+        // Nothing refers to them, so they aren't there
+        let bare = built("bare.o", |object| {
+            let code = SectionFlags::Alloc | SectionFlags::Executable;
+            let text = object.section(".text", code, &[0; 5]);
+            function(object, "_start", text);
+        });
+        let output = link(&[bare], &LinkOptions::default()).unwrap();
+        assert!(names.iter().all(|name| output.symbol(name).is_none()));
+        assert!(output.section(".got.plt").is_none());
+    }
+
+    #[test]
+    fn aarch64() {
+        use RelocationType::*;
+        // adrp x0, 0; add x0, x0, #0; ldr x1, [x0]; ldrh w2, [x0]; bl 0; b 0
+        let code: Vec<u8> = [
+            0x90000000u32,
+            0x91000000,
+            0xf9400001,
+            0x79400002,
+            0x94000000,
+            0x14000000,
+        ]
+        .iter()
+        .flat_map(|i| i.to_le_bytes())
+        .collect();
+        let start = built("start.o", |object| {
+            object.machine(MACHINE_AARCH64);
+            let text = object.section(
+                ".text",
+                SectionFlags::Alloc | SectionFlags::Executable,
+                &code,
+            );
+            function(object, "_start", text);
+            let value = object.undefined("value");
+            let helper = object.undefined("helper");
+            let relocations = [
+                (0, AdrPrelPgHi21, value),
+                (4, AddAbsLo12Nc, value),
+                (8, Ldst64AbsLo12Nc, value),
+                (12, Ldst16AbsLo12Nc, value),
+                (16, Call26, helper),
+                (20, Jump26, helper),
+            ];
+            for (offset, relocation_type, symbol) in relocations {
+                object.relocation(text, offset, relocation_type as u32, symbol, 0);
+            }
+        });
+        let data = built("data.o", |object| {
+            object.machine(MACHINE_AARCH64);
+            let ret = 0xd65f03c0u32.to_le_bytes();
+            let text = object.section(
+                ".text",
+                SectionFlags::Alloc | SectionFlags::Executable,
+                &ret,
+            );
+            function(object, "helper", text);
+            let data = object.section(".data", SectionFlags::Alloc | SectionFlags::Write, &[0; 24]);
+            object.symbol(
+                "value",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_OBJECT,
+                data,
+                16,
+                8,
+            );
+        });
+        let output = link(&[start, data], &LinkOptions::default()).unwrap();
+        let exec = &output.executable;
+        assert_eq!(exec.file_header.machine_type, MACHINE_AARCH64);
+        let loads = exec
+            .program_headers
+            .iter()
+            .filter(|p| p.segment_type == SegmentType::Loadable);
+        for load in loads {
+            assert_eq!(load.required_alignment, 0x10000);
+            assert_eq!(load.offset % 0x10000, load.virtual_address % 0x10000);
+        }
+
+        let text = output.section(".text").unwrap().address;
+        let value = output.symbol("value").unwrap().address;
+        let helper = output.symbol("helper").unwrap().address;
+        let pages = ((value >> 12) - (text >> 12)) as u32;
+        let low = (value & 0xfff) as u32;
+        let words: Vec<u32> = exec.sections[exec.find_section(".text").unwrap()].bytes[..24]
+            .chunks(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect();
+        assert_eq!(
+            words,
+            [
+                0x90000000 | (pages & 3) << 29 | (pages >> 2) << 5,
+                0x91000000 | low << 10,
+                0xf9400001 | (low >> 3) << 10,
+                0x79400002 | (low >> 1) << 10,
+                0x94000000 | ((helper - text - 16) >> 2) as u32,
+                0x14000000 | ((helper - text - 20) >> 2) as u32,
+            ]
+        );
+    }
+
+    #[test]
+    fn machines_cant_be_mixed() {
+        let object = |path: &str, machine: u16| {
+            built(path, |object| {
+                object.machine(machine);
+                let text = object.section(
+                    ".text",
+                    SectionFlags::Alloc | SectionFlags::Executable,
+                    &[0; 4],
+                );
+                function(object, path, text);
+            })
+        };
+        let errors: Vec<String> = link(
+            &[
+                object("a.o", MACHINE_X86_64),
+                object("b.o", MACHINE_AARCH64),
+            ],
+            &LinkOptions::default(),
+        )
+        .unwrap_err()
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+        assert_eq!(
+            errors,
+            ["b.o: AArch64 object can't be linked into x86-64 output"]
+        );
+    }
+
+    #[test]
+    fn append_pads_to_input_alignment() {
+        let code = SectionFlags::Alloc | SectionFlags::Executable;
+        let mut text = section(SectionType::ProgramData, code, 0, 1);
+        assert_eq!(
+            append(&mut text, &section(SectionType::ProgramData, code, 3, 4)),
+            Ok(0)
+        );
+        assert_eq!(
+            append(&mut text, &section(SectionType::ProgramData, code, 2, 16)),
+            Ok(16)
+        );
+        assert_eq!(text.bytes[3..16], [CODE_FILL; 13]);
+        assert_eq!((text.size, text.alignment), (18, 16));
+
+        let data = SectionFlags::Alloc | SectionFlags::Write;
+        let mut rodata = section(SectionType::ProgramData, data, 0, 1);
+        append(&mut rodata, &section(SectionType::ProgramData, data, 1, 1)).unwrap();
+        assert_eq!(
+            append(&mut rodata, &section(SectionType::ProgramData, data, 8, 8)),
+            Ok(8)
+        );
+        assert_eq!(rodata.bytes[1..8], [0; 7]);
+    }
+
+    #[test]
+    fn bss_stays_nobits() {
+        let data = SectionFlags::Alloc | SectionFlags::Write;
+        let mut bss = section(SectionType::ProgramSpaceWithNoData, data, 0, 1);
+        append(
+            &mut bss,
+            &section(SectionType::ProgramSpaceWithNoData, data, 5, 1),
+        )
+        .unwrap();
+        assert_eq!(
+            append(
+                &mut bss,
+                &section(SectionType::ProgramSpaceWithNoData, data, 64, 32)
+            ),
+            Ok(32)
+        );
+        assert_eq!((bss.size, bss.alignment, bss.bytes.len()), (96, 32, 0));
+
+        // Joining contents to it makes it PROGBITS
+        assert_eq!(
+            append(&mut bss, &section(SectionType::ProgramData, data, 4, 4)),
+            Ok(96)
+        );
+        assert!(!bss.is_nobits());
+        assert_eq!(bss.bytes.len(), 100);
+    }
+
+    #[test]
+    fn append_rejects_absurd_alignment() {
+        let mut text = section(SectionType::ProgramData, 0, 1, 1);
+        text.size = 1;
+        assert!(append(&mut text, &section(SectionType::ProgramData, 0, 1, 1 << 63)).is_err());
+    }
 }
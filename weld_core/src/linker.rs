@@ -0,0 +1,210 @@
+//! `Linker` links files on disk the way the driver does, for build tools
+//! that would rather call weld than run it:
+//!
+//! ```text
+//! let output = weld_core::Linker::new()
+//!     .input("main.o")
+//!     .search_path("target/lib")
+//!     .library("util")
+//!     .entry("main")
+//!     .gc_sections(true)
+//!     .link()?;
+//! std::fs::write("main", output.executable.encode())?;
+//! ```
+//!
+//! Everything it can be told is kept in its `LinkOptions`, which can also
+//! be filled in directly and passed to `Linker::with_options`.
+
+use crate::build_id::BuildId;
+use crate::{archive, link, LinkOptions, LinkOutput, OutputType, Warnings, WeldError};
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Debug, Default)]
+pub struct Linker {
+    options: LinkOptions,
+    inputs: Vec<PathBuf>,   // Objects and archives
+    libraries: Vec<String>, // -l names
+}
+
+impl Linker {
+    pub fn new() -> Linker {
+        Linker::default()
+    }
+
+    pub fn with_options(options: LinkOptions) -> Linker {
+        Linker {
+            options,
+            ..Default::default()
+        }
+    }
+
+    pub fn options(&self) -> &LinkOptions {
+        &self.options
+    }
+
+    // An object file or archive
+    pub fn input(mut self, path: impl Into<PathBuf>) -> Linker {
+        self.inputs.push(path.into());
+        self
+    }
+
+    // A library to find on the search path, as for -l<name>
+    pub fn library(mut self, name: &str) -> Linker {
+        self.libraries.push(name.to_string());
+        self
+    }
+
+    pub fn search_path(mut self, dir: impl Into<PathBuf>) -> Linker {
+        self.options.search_paths.push(dir.into());
+        self
+    }
+
+    pub fn entry(mut self, symbol: &str) -> Linker {
+        self.options.entry = symbol.to_string();
+        self
+    }
+
+    pub fn output_type(mut self, output_type: OutputType) -> Linker {
+        self.options.output_type = output_type;
+        self
+    }
+
+    pub fn image_base(mut self, address: u64) -> Linker {
+        self.options.layout.image_base = Some(address);
+        self
+    }
+
+    pub fn section_start(mut self, section: &str, address: u64) -> Linker {
+        self.options
+            .layout
+            .section_starts
+            .insert(section.to_string(), address);
+        self
+    }
+
+    pub fn build_id(mut self, style: BuildId) -> Linker {
+        self.options.build_id = Some(style);
+        self
+    }
+
+    pub fn gc_sections(mut self, enabled: bool) -> Linker {
+        self.options.gc_sections = enabled;
+        self
+    }
+
+    pub fn icf(mut self, enabled: bool) -> Linker {
+        self.options.icf = enabled;
+        self
+    }
+
+    pub fn warnings(mut self, warnings: Warnings) -> Linker {
+        self.options.warnings = warnings;
+        self
+    }
+
+    // Reads, parses and links the inputs, then whichever members of the
+    // archives and libraries they need
+    pub fn link(&self) -> Result<LinkOutput, Vec<WeldError>> {
+        let mut paths = self.inputs.clone();
+        for name in &self.libraries {
+            paths.push(
+                archive::find_library(name, &self.options.search_paths).map_err(|err| vec![err])?,
+            );
+        }
+        let names: Vec<String> = paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        let mut contents = Vec::new();
+        for (name, path) in names.iter().zip(&paths) {
+            let bytes =
+                fs::read(path).map_err(|err| vec![WeldError::new(format!("{name}: {err}"))])?;
+            contents.push(bytes);
+        }
+
+        let mut objects = Vec::new();
+        let mut archives = Vec::new();
+        let mut errors = Vec::new();
+        for (name, bytes) in names.iter().zip(&contents) {
+            if elf_parser::archive::is_archive(bytes) {
+                archives.push((name.as_str(), &bytes[..]));
+                continue;
+            }
+            match elf_parser::parse(name, bytes) {
+                Ok(object) => objects.push(object),
+                Err(err) => errors.push(WeldError::new(format!("{name}: {err}"))),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let members = archive::members(&objects, &archives)?;
+        objects.extend(members.into_iter().map(|(_, member)| member));
+        link(&objects, &self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::tests::{archive, simple_program, testdata};
+
+    // A new directory for `test` holding testdata/0_simple's objects
+    fn inputs(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("weld-{test}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, bytes) in simple_program() {
+            fs::write(dir.join(name), bytes).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn links_like_link() {
+        let dir = inputs("links-like-link");
+        let output = Linker::new()
+            .input(dir.join("main.o"))
+            .input(dir.join("multiply.o"))
+            .input(dir.join("square.o"))
+            .input(dir.join("start.o"))
+            .build_id(BuildId::Sha1)
+            .link()
+            .unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let contents = ["main.o", "multiply.o", "square.o", "start.o"].map(testdata);
+        let inputs: Vec<_> = contents
+            .iter()
+            .map(|bytes| elf_parser::parse("input.o", bytes).unwrap())
+            .collect();
+        let options = LinkOptions {
+            build_id: Some(BuildId::Sha1),
+            ..Default::default()
+        };
+        let expected = link(&inputs, &options).unwrap();
+        assert_eq!(output.executable.encode(), expected.executable.encode());
+    }
+
+    #[test]
+    fn libraries_come_from_the_search_path() {
+        let dir = inputs("linker");
+        let [multiply, square] = ["multiply.o", "square.o"].map(testdata);
+        let lib = archive(&[("multiply.o", &multiply), ("square.o", &square)]);
+        fs::write(dir.join("libsquare.a"), lib).unwrap();
+
+        let linker = || {
+            Linker::new()
+                .input(dir.join("main.o"))
+                .input(dir.join("start.o"))
+        };
+        let linked = linker().search_path(&dir).library("square").link();
+        let unfound = linker().library("square").link();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let multiply = linked.unwrap().symbol("multiply").unwrap().file.clone();
+        assert!(multiply.ends_with("libsquare.a(multiply.o)"), "{multiply}");
+        let errors = unfound.unwrap_err();
+        assert_eq!(errors[0].message, "cannot find -lsquare");
+    }
+}
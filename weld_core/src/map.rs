@@ -0,0 +1,184 @@
+//! Link maps (`-Map`). `link` records where each input section and symbol
+//! ended up; `write` combines that with the executable's headers to explain
+//! any address in the output.
+
+use elf::logical::Executable;
+use std::io::{self, Write};
+
+#[derive(Debug, Default)]
+pub struct InputSection {
+    pub file: String,
+    pub file_index: usize, // Position on the command line
+    pub name: String,
+    pub index: usize, // Section header index in its file
+    pub output_section: String,
+    pub offset: u64, // Relative to the start of the output section
+    pub size: u64,
+    pub capacity: u64, // Size plus the room reserved after it by --incremental
+    pub alignment: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct MappedSymbol {
+    pub name: String,
+    pub file: String,
+    pub output_section: String,
+    pub address: u64,
+    pub size: u64,
+    pub global: bool, // The definition its name resolves to
+}
+
+#[derive(Debug, Default)]
+pub struct LinkMap {
+    pub input_sections: Vec<InputSection>,
+    pub symbols: Vec<MappedSymbol>,
+}
+
+pub fn write(exec: &Executable, map: &LinkMap, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "Segments\n")?;
+    writeln!(
+        out,
+        "{:<10} {:>10} {:>18} {:>10} {:>10} {:>5} {:>8}",
+        "Type", "Offset", "VirtAddr", "FileSiz", "MemSiz", "Flags", "Align"
+    )?;
+    for phdr in &exec.program_headers {
+        writeln!(
+            out,
+            "{:<10} {:>#10x} {:>#18x} {:>#10x} {:>#10x} {:>5} {:>#8x}",
+            segment_type(&phdr.segment_type),
+            phdr.offset,
+            phdr.virtual_address,
+            phdr.size_in_file,
+            phdr.size_in_memory,
+            segment_flags(phdr.flags),
+            phdr.required_alignment
+        )?;
+    }
+
+    writeln!(out, "\nOutput sections\n")?;
+    for (section, shdr) in exec.sections.iter().zip(&exec.section_headers).skip(1) {
+        let name = &section.name;
+        writeln!(
+            out,
+            "{:<24} {:#018x} {:>#10x} (file offset {:#x})",
+            name, shdr.virtual_address, shdr.size, shdr.offset
+        )?;
+
+        for input in map
+            .input_sections
+            .iter()
+            .filter(|i| &i.output_section == name)
+        {
+            writeln!(
+                out,
+                " {:<23} {:#018x} {:>#10x} {} (align {})",
+                input.name,
+                shdr.virtual_address + input.offset,
+                input.size,
+                input.file,
+                input.alignment
+            )?;
+
+            let mut symbols: Vec<&MappedSymbol> = map
+                .symbols
+                .iter()
+                .filter(|s| s.file == input.file && &s.output_section == name)
+                .filter(|s| {
+                    let start = shdr.virtual_address + input.offset;
+                    s.address >= start && s.address < start + input.size.max(1)
+                })
+                .collect();
+            symbols.sort_by_key(|s| s.address);
+            for symbol in symbols {
+                writeln!(
+                    out,
+                    " {:<23} {:#018x} {:>10} {}",
+                    "", symbol.address, "", symbol.name
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn segment_type(segment_type: &elf::file::SegmentType) -> &'static str {
+    use elf::file::SegmentType;
+    match segment_type {
+        SegmentType::None => "NULL",
+        SegmentType::Loadable => "LOAD",
+        SegmentType::DynamicLinkInfo => "DYNAMIC",
+        SegmentType::InterpreterInfo => "INTERP",
+        SegmentType::AuxiliaryInfo => "NOTE",
+        SegmentType::Reserved => "SHLIB",
+        SegmentType::ProgramHeaderTableSegment => "PHDR",
+        SegmentType::ThreadLocalStorageTemplate => "TLS",
+        SegmentType::GnuEHFrame => "GNU_EH_FRAME",
+        SegmentType::GnuStack => "GNU_STACK",
+        SegmentType::GnuRelRO => "GNU_RELRO",
+        SegmentType::GnuProperty => "GNU_PROPERTY",
+    }
+}
+
+fn segment_flags(flags: u32) -> String {
+    use elf::file::SegmentFlags;
+    [
+        (SegmentFlags::Read, 'R'),
+        (SegmentFlags::Write, 'W'),
+        (SegmentFlags::Execute, 'E'),
+    ]
+    .into_iter()
+    .map(|(flag, c)| if flags & flag as u32 != 0 { c } else { ' ' })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_inputs_and_symbols_under_their_section() {
+        let mut exec = Executable::default();
+        exec.sections.push(elf::logical::Section::default());
+        exec.section_headers
+            .push(elf::file::SectionHeader::default());
+        exec.sections.push(elf::logical::Section {
+            name: ".text".to_string(),
+            ..Default::default()
+        });
+        exec.section_headers.push(elf::file::SectionHeader {
+            virtual_address: 0x401000,
+            size: 0x20,
+            ..Default::default()
+        });
+        let map = LinkMap {
+            input_sections: vec![InputSection {
+                file: "a.o".to_string(),
+                name: ".text".to_string(),
+                output_section: ".text".to_string(),
+                offset: 0x10,
+                size: 0x10,
+                alignment: 16,
+                ..Default::default()
+            }],
+            symbols: vec![MappedSymbol {
+                name: "foo".to_string(),
+                file: "a.o".to_string(),
+                output_section: ".text".to_string(),
+                address: 0x401014,
+                size: 4,
+                global: true,
+            }],
+        };
+
+        let mut out = Vec::new();
+        write(&exec, &map, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out
+            .lines()
+            .skip_while(|l| !l.starts_with(".text"))
+            .collect();
+        assert!(lines[1].contains("0x0000000000401010") && lines[1].contains("a.o (align 16)"));
+        assert!(lines[2].contains("0x0000000000401014") && lines[2].ends_with("foo"));
+    }
+}
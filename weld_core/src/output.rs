@@ -0,0 +1,128 @@
+//! What `link` returns: the executable, and a typed account of how it was
+//! put together (where every input section and symbol went, and every
+//! relocation applied), for tools and tests to ask about directly rather
+//! than reading a link map.
+
+use crate::map::{InputSection, LinkMap, MappedSymbol};
+use elf::logical::{Executable, RelocationType};
+
+#[derive(Debug)]
+pub struct LinkOutput {
+    pub executable: Executable,
+    pub map: LinkMap,
+    pub relocations: Vec<AppliedRelocation>, // In input order
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppliedRelocation {
+    pub file: String,
+    pub section: String, // The input section patched
+    pub site: u64,       // Address patched (P)
+    pub relocation_type: RelocationType,
+    pub target: String, // Symbol name; section symbols by their section's name
+    pub target_address: u64, // S
+    pub addend: i64,    // A
+    pub value: i64,     // Written at the site, sign-extended
+}
+
+// An output section, with the input sections laid out in it
+#[derive(Debug)]
+pub struct OutputSection<'a> {
+    pub name: &'a str,
+    pub address: u64,
+    pub offset: u64, // In the file
+    pub size: u64,
+    pub inputs: Vec<&'a InputSection>,
+}
+
+impl LinkOutput {
+    // The symbol `name` resolves to: its global definition, or failing
+    // that the first local by that name
+    pub fn symbol(&self, name: &str) -> Option<&MappedSymbol> {
+        let mut named = self.map.symbols.iter().filter(|s| s.name == name);
+        let first = named.clone().next();
+        named.find(|s| s.global).or(first)
+    }
+
+    // In section header order, leaving out the null section
+    pub fn sections(&self) -> Vec<OutputSection<'_>> {
+        let exec = &self.executable;
+        exec.sections
+            .iter()
+            .zip(&exec.section_headers)
+            .skip(1)
+            .map(|(section, shdr)| OutputSection {
+                name: &section.name,
+                address: shdr.virtual_address,
+                offset: shdr.offset,
+                size: shdr.size,
+                inputs: self
+                    .map
+                    .input_sections
+                    .iter()
+                    .filter(|input| input.output_section == section.name)
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn section(&self, name: &str) -> Option<OutputSection<'_>> {
+        self.sections().into_iter().find(|s| s.name == name)
+    }
+
+    // Those applied to the input section `section` of `file`
+    pub fn relocations_in<'a>(
+        &'a self,
+        file: &'a str,
+        section: &'a str,
+    ) -> impl Iterator<Item = &'a AppliedRelocation> {
+        self.relocations
+            .iter()
+            .filter(move |r| r.file == file && r.section == section)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{link, LinkOptions};
+
+    fn testdata(name: &str) -> elf::logical::Relocatable<'static> {
+        let bytes = crate::archive::tests::testdata(name).leak();
+        elf_parser::parse(name, bytes).unwrap()
+    }
+
+    #[test]
+    fn symbols_sections_and_relocations() {
+        let inputs = ["main.o", "multiply.o", "square.o", "start.o"].map(testdata);
+        let output = link(&inputs, &LinkOptions::default()).unwrap();
+
+        let text = output.section(".text").unwrap();
+        assert_eq!(text.address, 0x401000);
+        assert_eq!(text.size, 0x70);
+        let files: Vec<&str> = text.inputs.iter().map(|i| i.file.as_str()).collect();
+        assert_eq!(files, ["main.o", "multiply.o", "square.o", "start.o"]);
+
+        let main = output.symbol("main").unwrap();
+        assert_eq!((main.address, main.size), (0x401000, 16));
+        let square = output.symbol("square").unwrap();
+        assert_eq!(square.file, "square.o");
+
+        // main's call to square
+        let call: Vec<_> = output.relocations_in("main.o", ".text").collect();
+        assert_eq!(call.len(), 1);
+        assert_eq!(call[0].target, "square");
+        assert_eq!(call[0].target_address, square.address);
+        assert_eq!(
+            call[0].value,
+            square.address as i64 + call[0].addend - call[0].site as i64
+        );
+        let exec = &output.executable;
+        let bytes = &exec.sections[exec.find_section(".text").unwrap()].bytes;
+        let at = (call[0].site - text.address) as usize;
+        assert_eq!(bytes[at..at + 4], (call[0].value as i32).to_le_bytes());
+        assert_eq!(
+            output.executable.file_header.entrypoint,
+            output.symbol("_start").unwrap().address
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Symbols the linker defines, as GNU ld's default linker script PROVIDEs
+//! them: the bounds of the image, its code and its data, and the GOT. Each
+//! is defined only if an input refers to it and none defines it, so a
+//! program's own `_end` wins. Their values come from the final layout.
+
+use crate::layout::Plan;
+use crate::{map, Definition};
+use elf::file::SectionFlags;
+use elf::logical::{Relocatable, Section};
+use std::collections::{BTreeMap, BTreeSet};
+
+const EXECUTABLE_START: &str = "__executable_start";
+const EHDR_START: &str = "__ehdr_start";
+const ETEXT: &str = "_etext";
+const EDATA: &str = "_edata";
+const BSS_START: &str = "__bss_start";
+const END: &str = "_end";
+const GLOBAL_OFFSET_TABLE: &str = "_GLOBAL_OFFSET_TABLE_";
+
+const NAMES: [&str; 7] = [
+    EXECUTABLE_START,
+    EHDR_START,
+    ETEXT,
+    EDATA,
+    BSS_START,
+    END,
+    GLOBAL_OFFSET_TABLE,
+];
+
+// Where _GLOBAL_OFFSET_TABLE_ points. Its first three entries are reserved
+// for the dynamic linker, which a static executable doesn't have, so zero.
+pub(crate) const GOT_SECTION: &str = ".got.plt";
+const GOT_RESERVED_SIZE: usize = 24;
+
+// The ones `inputs` refer to (weakly or not) but don't define
+pub(crate) fn referenced(
+    inputs: &[Relocatable],
+    globals: &BTreeMap<&str, Definition>,
+) -> BTreeSet<&'static str> {
+    NAMES
+        .into_iter()
+        .filter(|name| !globals.contains_key(name))
+        .filter(|&name| {
+            inputs
+                .iter()
+                .flat_map(|f| &f.symbols)
+                .any(|s| s.name == name && !s.is_defined())
+        })
+        .collect()
+}
+
+// The section _GLOBAL_OFFSET_TABLE_ needs, if `names` includes it
+pub(crate) fn got(names: &BTreeSet<&str>) -> Option<Section<'static>> {
+    names.contains(GLOBAL_OFFSET_TABLE).then(|| Section {
+        name: GOT_SECTION.to_string(),
+        section_type: elf::file::SectionType::ProgramData,
+        bytes: vec![0; GOT_RESERVED_SIZE].into(),
+        size: GOT_RESERVED_SIZE as u64,
+        flags: SectionFlags::Alloc | SectionFlags::Write,
+        alignment: 8,
+        ..Default::default()
+    })
+}
+
+// The symbols `names` as laid out in `plan`. Those outside any section
+// have an empty output section; none has a file.
+pub(crate) fn define(plan: &Plan, names: &BTreeSet<&str>) -> Vec<map::MappedSymbol> {
+    let alloc: Vec<&Section> = plan.sections.iter().filter(|s| s.is_alloc()).collect();
+    // The end of the last section that `filter` accepts
+    let end_of = |filter: &dyn Fn(&Section) -> bool| {
+        alloc
+            .iter()
+            .filter(|s| filter(s))
+            .max_by_key(|s| s.virtual_address + s.size)
+            .map(|s| (s.virtual_address + s.size, s.name.as_str()))
+    };
+    let image_base = plan
+        .program_headers
+        .first()
+        .map_or(0, |load| load.virtual_address);
+    let text = end_of(&|s| s.flags & SectionFlags::Executable as u64 != 0);
+    let data = end_of(&|s| !s.is_nobits());
+    // Rounded up to 8 bytes, as ld does. Layout extends the image to there.
+    let end = end_of(&|_| true).map(|(end, name)| (end.next_multiple_of(8), name));
+    let got = plan
+        .find(GOT_SECTION)
+        .map(|i| (plan.sections[i].virtual_address, GOT_SECTION));
+
+    names
+        .iter()
+        .filter_map(|&name| {
+            let (address, section) = match name {
+                EXECUTABLE_START | EHDR_START => (image_base, ""),
+                ETEXT => text?,
+                EDATA | BSS_START => data?,
+                END => end?,
+                GLOBAL_OFFSET_TABLE => got?,
+                _ => return None,
+            };
+            Some(map::MappedSymbol {
+                name: name.to_string(),
+                file: String::new(),
+                output_section: section.to_string(),
+                address,
+                size: 0,
+                global: true,
+            })
+        })
+        .collect()
+}
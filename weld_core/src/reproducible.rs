@@ -0,0 +1,174 @@
+//! Reproducible output. Identical inputs and options give a byte-identical
+//! executable and link map, whatever the thread count or hash seeds. Input
+//! paths only reach the map, cross reference table and diagnostics; with
+//! `--reproducible` the driver records them by `strip_path`, so neither
+//! does where the build tree is.
+//!
+//! `check` (`--check-determinism`) tests the guarantee: it links once as
+//! usual and once on a different number of threads with the order of
+//! internal work shuffled, then diffs the results.
+
+use crate::build_id::BuildId;
+use crate::{link_with, map, LinkOptions, LinkOutput};
+use elf::logical::Relocatable;
+use std::path::Path;
+
+// How `path` is recorded under --reproducible: relative to `dir` (the
+// current directory) if it's inside it, otherwise just its file name.
+// Relative paths are already independent of where the build tree is.
+pub fn strip_path(path: &str, dir: &Path) -> String {
+    let path = Path::new(path);
+    if path.is_relative() {
+        return path.display().to_string();
+    }
+    match path.strip_prefix(dir) {
+        Ok(relative) => relative.display().to_string(),
+        Err(_) => path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+    }
+}
+
+// xorshift64*: not random enough for anything but reordering work
+pub(crate) struct Shuffler(u64);
+
+impl Shuffler {
+    pub(crate) fn new(seed: u64) -> Shuffler {
+        Shuffler(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    // Fisher-Yates
+    pub(crate) fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, (self.next() % (i as u64 + 1)) as usize);
+        }
+    }
+}
+
+// Everything a link produces, as compared by `check`
+fn encode(linked: &LinkOutput) -> (Vec<u8>, Vec<u8>) {
+    let mut text = Vec::new();
+    map::write(&linked.executable, &linked.map, &mut text).expect("Write to memory failed");
+    (linked.executable.encode(), text)
+}
+
+// Links `inputs` twice, the second time on 2 to 8 threads (picked by `seed`)
+// with internal work shuffled by `seed`, and describes the first difference
+pub fn check(inputs: &[Relocatable], options: &LinkOptions, seed: u64) -> Result<(), String> {
+    if options.build_id == Some(BuildId::Uuid) {
+        return Err("--build-id=uuid is random, so the output can't be reproducible".to_string());
+    }
+    let on = |threads: usize, shuffle: Option<u64>| {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|err| err.to_string())?;
+        Ok::<_, String>(pool.install(|| link_with(inputs, options, shuffle)))
+    };
+    let first = on(1, None)?;
+    let second = on(2 + (seed % 7) as usize, Some(seed))?;
+
+    match (first, second) {
+        (Ok(first), Ok(second)) => {
+            let (exec, map) = encode(&first);
+            let (other_exec, other_map) = encode(&second);
+            if let Some(offset) = first_difference(&exec, &other_exec) {
+                let section = first
+                    .executable
+                    .sections
+                    .iter()
+                    .zip(&first.executable.section_headers)
+                    .find(|(s, shdr)| {
+                        !s.is_nobits() && (shdr.offset..shdr.offset + shdr.size).contains(&offset)
+                    })
+                    .map(|(s, _)| format!(" (in {})", s.name))
+                    .unwrap_or_default();
+                return Err(format!(
+                    "executables differ at offset {offset:#x}{section} (seed {seed})"
+                ));
+            }
+            if let Some(offset) = first_difference(&map, &other_map) {
+                return Err(format!(
+                    "link maps differ at offset {offset:#x} (seed {seed})"
+                ));
+            }
+            let (applied, other_applied) = (&first.relocations, &second.relocations);
+            let differing = applied.iter().zip(other_applied).position(|(a, b)| a != b);
+            let missing = applied.len().min(other_applied.len());
+            match differing.or((applied.len() != other_applied.len()).then_some(missing)) {
+                Some(i) => Err(format!("relocation logs differ at entry {i} (seed {seed})")),
+                None => Ok(()),
+            }
+        }
+        (Err(first), Err(second)) => {
+            let first: Vec<String> = first.iter().map(|e| e.to_string()).collect();
+            let second: Vec<String> = second.iter().map(|e| e.to_string()).collect();
+            match first == second {
+                true => Ok(()),
+                false => Err(format!("links report different errors (seed {seed})")),
+            }
+        }
+        _ => Err(format!("only one of the links failed (seed {seed})")),
+    }
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> Option<u64> {
+    match a.iter().zip(b).position(|(a, b)| a != b) {
+        Some(offset) => Some(offset as u64),
+        None if a.len() != b.len() => Some(a.len().min(b.len()) as u64),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(name: &str) -> Relocatable<'static> {
+        let bytes = crate::archive::tests::testdata(name).leak();
+        elf_parser::parse(name, bytes).unwrap()
+    }
+
+    #[test]
+    fn shuffled_links_match() {
+        let inputs = ["main.o", "multiply.o", "square.o", "start.o"].map(testdata);
+        let options = LinkOptions {
+            build_id: Some(BuildId::Sha1),
+            ..Default::default()
+        };
+        for seed in 0..8 {
+            assert_eq!(check(&inputs, &options, seed), Ok(()));
+        }
+
+        let uuid = LinkOptions {
+            build_id: Some(BuildId::Uuid),
+            ..Default::default()
+        };
+        assert!(check(&inputs, &uuid, 0).is_err());
+    }
+
+    #[test]
+    fn shuffling_permutes() {
+        let mut items: Vec<u32> = (0..100).collect();
+        Shuffler::new(7).shuffle(&mut items);
+        assert_ne!(items, (0..100).collect::<Vec<_>>());
+        items.sort();
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stripped_paths() {
+        let dir = Path::new("/home/someone/build");
+        assert_eq!(strip_path("/home/someone/build/obj/a.o", dir), "obj/a.o");
+        assert_eq!(strip_path("/usr/lib/crt1.o", dir), "crt1.o");
+        assert_eq!(strip_path("obj/a.o", dir), "obj/a.o");
+    }
+}
@@ -0,0 +1,157 @@
+//! What weld needs to know about the machine it links for: the output's
+//! e_machine and e_flags, the page size segments are aligned to, where the
+//! image is loaded by default, how each relocation type patches code and
+//! data, and what a PLT entry looks like. The inputs' e_machine picks the
+//! `Target`, and they must all agree on it.
+//!
+//! Relocations are described in the psABIs' terms: S is the address of the
+//! symbol, A the addend and P the address of the place being patched.
+
+use crate::WeldError;
+use elf::logical::{Relocatable, RelocationType};
+
+mod aarch64;
+mod x86_64;
+
+pub use aarch64::AArch64;
+pub use x86_64::X86_64;
+
+pub trait Target: Sync {
+    // As readelf names it
+    fn name(&self) -> &'static str;
+
+    // e_machine
+    fn machine(&self) -> u16;
+
+    // e_flags
+    fn flags(&self) -> u32 {
+        0
+    }
+
+    // The largest page the loader may map with. Each loadable segment
+    // starts on a new one.
+    fn page_size(&self) -> u64;
+
+    // Where the file header is loaded, unless --image-base says otherwise
+    fn image_base(&self) -> u64;
+
+    // Patches `bytes` at `offset` for relocation `kind`, returning the value
+    // written there: the whole field, or the immediate for an instruction,
+    // sign-extended if narrower than 64 bits
+    fn apply(
+        &self,
+        kind: RelocationType,
+        bytes: &mut [u8],
+        offset: u64,
+        s: u64,
+        a: i64,
+        p: u64,
+    ) -> Result<i64, String>;
+
+    // What S is for relocation `kind` at `p` against an undefined weak
+    // symbol. Its address is 0, which code compares against before using it.
+    fn undefined_weak(&self, _kind: RelocationType, _p: u64, _a: i64) -> u64 {
+        0
+    }
+
+    // A PLT entry placed at `address`, which jumps to wherever the 8-byte
+    // GOT entry at `got_entry` points
+    fn plt_entry(&self, address: u64, got_entry: u64) -> Result<Vec<u8>, String>;
+}
+
+pub fn for_machine(machine: u16) -> Option<&'static dyn Target> {
+    match machine {
+        elf::file::MACHINE_X86_64 => Some(&X86_64),
+        elf::file::MACHINE_AARCH64 => Some(&AArch64),
+        _ => None,
+    }
+}
+
+// The target of the first input, which every other input must share. With
+// no inputs, it's x86-64.
+pub fn select(inputs: &[Relocatable]) -> Result<&'static dyn Target, Vec<WeldError>> {
+    let Some(first) = inputs.first() else {
+        return Ok(&X86_64);
+    };
+    let target = for_machine(first.machine).ok_or_else(|| {
+        vec![WeldError::new(format!(
+            "{}: unsupported machine {:#x}",
+            first.path, first.machine
+        ))]
+    })?;
+    let errors: Vec<WeldError> = inputs
+        .iter()
+        .filter(|f| f.machine != first.machine)
+        .map(|f| {
+            let name = for_machine(f.machine).map_or("unknown", |t| t.name());
+            WeldError::new(format!(
+                "{}: {name} object can't be linked into {} output",
+                f.path,
+                target.name()
+            ))
+        })
+        .collect();
+    match errors.is_empty() {
+        true => Ok(target),
+        false => Err(errors),
+    }
+}
+
+// Writes `value` at `offset` in `bytes`, failing if any of it would land outside
+fn patch(bytes: &mut [u8], offset: u64, value: &[u8]) -> Result<(), String> {
+    let site = site(bytes, offset, value.len())?;
+    bytes[site..site + value.len()].copy_from_slice(value);
+    Ok(())
+}
+
+// `offset` as an index into `bytes`, if `size` bytes fit there
+fn site(bytes: &[u8], offset: u64, size: usize) -> Result<usize, String> {
+    usize::try_from(offset)
+        .ok()
+        .filter(|&start| {
+            start
+                .checked_add(size)
+                .is_some_and(|end| end <= bytes.len())
+        })
+        .ok_or_else(|| format!("relocation at offset {offset:#x} is outside its section"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(path: &str, machine: u16) -> Relocatable<'static> {
+        Relocatable {
+            path: path.to_string(),
+            machine,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn inputs_pick_the_target() {
+        let arm = [object("a.o", 0xb7), object("b.o", 0xb7)];
+        assert_eq!(select(&arm).unwrap().machine(), 0xb7);
+        assert_eq!(select(&[]).unwrap().machine(), 0x3e);
+
+        let mixed = [
+            object("a.o", 0x3e),
+            object("b.o", 0xb7),
+            object("c.o", 0x28),
+        ];
+        let errors: Vec<String> = select(&mixed)
+            .err()
+            .unwrap()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "b.o: AArch64 object can't be linked into x86-64 output",
+                "c.o: unknown object can't be linked into x86-64 output",
+            ]
+        );
+        assert!(select(&[object("a.o", 0x28)]).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! AArch64, as the ELF for the Arm 64-bit Architecture psABI describes it.
+//! Code refers to data with an ADRP, which gets the 4KiB page it's in, then
+//! an ADD or load/store carrying the low 12 bits; branches and calls reach
+//! 128MiB either way. Instructions are 4 bytes, little-endian.
+
+use super::{patch, site, Target};
+use elf::logical::RelocationType;
+
+// adrp x16, 0; ldr x17, [x16]; add x16, x16, #0; br x17
+const PLT_ENTRY: [u32; 4] = [0x90000010, 0xf9400211, 0x91000210, 0xd61f0220];
+
+#[derive(Debug)]
+pub struct AArch64;
+
+fn page(address: u64) -> u64 {
+    address & !0xfff
+}
+
+// Whether `value` fits in a signed field of `bits` bits
+fn fits(value: i64, bits: u32) -> bool {
+    (-(1 << (bits - 1))..1 << (bits - 1)).contains(&value)
+}
+
+// Replaces the bits of the instruction at `offset` that `mask` selects
+fn encode(bytes: &mut [u8], offset: u64, mask: u32, bits: u32) -> Result<(), String> {
+    let at = site(bytes, offset, 4)?;
+    let instruction = u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    patch(
+        bytes,
+        offset,
+        &(instruction & !mask | bits & mask).to_le_bytes(),
+    )
+}
+
+impl Target for AArch64 {
+    fn name(&self) -> &'static str {
+        "AArch64"
+    }
+
+    fn machine(&self) -> u16 {
+        elf::file::MACHINE_AARCH64
+    }
+
+    // Linux may use 64KiB pages
+    fn page_size(&self) -> u64 {
+        0x10000
+    }
+
+    fn image_base(&self) -> u64 {
+        0x400000
+    }
+
+    fn apply(
+        &self,
+        kind: RelocationType,
+        bytes: &mut [u8],
+        offset: u64,
+        s: u64,
+        a: i64,
+        p: u64,
+    ) -> Result<i64, String> {
+        let value = s.wrapping_add(a as u64);
+        let overflow = || format!("{kind:?} relocation at offset {offset:#x} overflows");
+        match kind {
+            RelocationType::Aarch64Abs64 => {
+                patch(bytes, offset, &value.to_le_bytes())?;
+                Ok(value as i64)
+            }
+            // Either signed or unsigned
+            RelocationType::Aarch64Abs32 | RelocationType::Aarch64Prel32 => {
+                let value = match kind {
+                    RelocationType::Aarch64Prel32 => value.wrapping_sub(p),
+                    _ => value,
+                } as i64;
+                if !(-(1 << 31)..1 << 32).contains(&value) {
+                    return Err(overflow());
+                }
+                patch(bytes, offset, &(value as u32).to_le_bytes())?;
+                Ok(value as i32 as i64)
+            }
+            RelocationType::AdrPrelPgHi21 => {
+                let pages = (page(value).wrapping_sub(page(p)) as i64) >> 12;
+                if !fits(pages, 21) {
+                    return Err(overflow());
+                }
+                // immlo is bits 29-30, immhi bits 5-23
+                let (lo, hi) = (pages as u32 & 0x3, (pages >> 2) as u32 & 0x7ffff);
+                encode(bytes, offset, 0x3 << 29 | 0x7ffff << 5, lo << 29 | hi << 5)?;
+                Ok(pages)
+            }
+            // The low 12 bits of S + A, which loads and stores scale by
+            // their access size
+            RelocationType::AddAbsLo12Nc
+            | RelocationType::Ldst8AbsLo12Nc
+            | RelocationType::Ldst16AbsLo12Nc
+            | RelocationType::Ldst32AbsLo12Nc
+            | RelocationType::Ldst64AbsLo12Nc
+            | RelocationType::Ldst128AbsLo12Nc => {
+                let shift = match kind {
+                    RelocationType::Ldst16AbsLo12Nc => 1,
+                    RelocationType::Ldst32AbsLo12Nc => 2,
+                    RelocationType::Ldst64AbsLo12Nc => 3,
+                    RelocationType::Ldst128AbsLo12Nc => 4,
+                    _ => 0,
+                };
+                if value & ((1 << shift) - 1) != 0 {
+                    return Err(format!(
+                        "{kind:?} relocation at offset {offset:#x} refers to {value:#x}, \
+                         which isn't {}-byte aligned",
+                        1 << shift
+                    ));
+                }
+                let imm = (value & 0xfff) >> shift;
+                encode(bytes, offset, 0xfff << 10, (imm as u32) << 10)?;
+                Ok(imm as i64)
+            }
+            RelocationType::Jump26 | RelocationType::Call26 => {
+                let relative = value.wrapping_sub(p) as i64;
+                if relative % 4 != 0 || !fits(relative, 28) {
+                    return Err(overflow());
+                }
+                let words = relative >> 2;
+                encode(bytes, offset, 0x3ffffff, words as u32)?;
+                Ok(words)
+            }
+            _ => Err(format!(
+                "unsupported relocation type {kind:?} at offset {offset:#x}"
+            )),
+        }
+    }
+
+    // 0 may be out of a branch's reach, so as the psABI asks, a branch
+    // there goes to the next instruction instead
+    fn undefined_weak(&self, kind: RelocationType, p: u64, a: i64) -> u64 {
+        match kind {
+            RelocationType::Jump26 | RelocationType::Call26 => {
+                p.wrapping_add(4).wrapping_sub(a as u64)
+            }
+            _ => 0,
+        }
+    }
+
+    fn plt_entry(&self, address: u64, got_entry: u64) -> Result<Vec<u8>, String> {
+        let mut entry: Vec<u8> = PLT_ENTRY.iter().flat_map(|i| i.to_le_bytes()).collect();
+        let adrp = RelocationType::AdrPrelPgHi21;
+        self.apply(adrp, &mut entry, 0, got_entry, 0, address)?;
+        self.apply(
+            RelocationType::Ldst64AbsLo12Nc,
+            &mut entry,
+            4,
+            got_entry,
+            0,
+            0,
+        )?;
+        self.apply(RelocationType::AddAbsLo12Nc, &mut entry, 8, got_entry, 0, 0)?;
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use RelocationType::*;
+
+    // Applies `kind` to the one instruction `instruction`, returning the
+    // result's encoding
+    fn relocated(kind: RelocationType, instruction: u32, s: u64, a: i64, p: u64) -> u32 {
+        let mut bytes = instruction.to_le_bytes();
+        AArch64.apply(kind, &mut bytes, 0, s, a, p).unwrap();
+        u32::from_le_bytes(bytes)
+    }
+
+    // Encodings are as llvm-mc -triple=aarch64 -show-encoding gives them
+
+    #[test]
+    fn adrp_gets_the_page() {
+        // adrp x0, 0 at 0x400ffc, for 0x412345: 0x12 pages on
+        assert_eq!(
+            relocated(AdrPrelPgHi21, 0x90000000, 0x412340, 5, 0x400ffc),
+            0xd0000080
+        );
+        // adrp x1, 0 at 0x800000, 3 pages back: immlo 1, immhi all ones
+        assert_eq!(
+            relocated(AdrPrelPgHi21, 0x90000001, 0x7fd010, 0, 0x800000),
+            0xb0ffffe1
+        );
+        // 4GiB away doesn't fit
+        let mut bytes = 0x90000000u32.to_le_bytes();
+        let far = AArch64.apply(AdrPrelPgHi21, &mut bytes, 0, 1 << 32, 0, 0);
+        assert_eq!(
+            far.unwrap_err(),
+            "AdrPrelPgHi21 relocation at offset 0x0 overflows"
+        );
+    }
+
+    #[test]
+    fn low_12_bits_are_scaled_by_access_size() {
+        // add x0, x0, #0x345
+        assert_eq!(
+            relocated(AddAbsLo12Nc, 0x91000000, 0x412345, 0, 0),
+            0x910d1400
+        );
+        // ldrb w1, [x0, #0x345]
+        assert_eq!(
+            relocated(Ldst8AbsLo12Nc, 0x39400001, 0x412345, 0, 0),
+            0x394d1401
+        );
+        // ldrh w1, [x0, #0x346]
+        assert_eq!(
+            relocated(Ldst16AbsLo12Nc, 0x79400001, 0x412346, 0, 0),
+            0x79468c01
+        );
+        // ldr w1, [x0, #0x344]
+        assert_eq!(
+            relocated(Ldst32AbsLo12Nc, 0xb9400001, 0x412340, 4, 0),
+            0xb9434401
+        );
+        // ldr x1, [x0, #0x348]
+        assert_eq!(
+            relocated(Ldst64AbsLo12Nc, 0xf9400001, 0x412348, 0, 0),
+            0xf941a401
+        );
+        // ldr q0, [x0, #0x350]
+        assert_eq!(
+            relocated(Ldst128AbsLo12Nc, 0x3dc00000, 0x412350, 0, 0),
+            0x3dc0d400
+        );
+
+        let mut bytes = 0xf9400001u32.to_le_bytes();
+        let misaligned = AArch64.apply(Ldst64AbsLo12Nc, &mut bytes, 0, 0x412344, 0, 0);
+        assert_eq!(
+            misaligned.unwrap_err(),
+            "Ldst64AbsLo12Nc relocation at offset 0x0 refers to 0x412344, \
+             which isn't 8-byte aligned"
+        );
+    }
+
+    #[test]
+    fn branches_reach_128mib() {
+        // bl .+0x100 and b .-8
+        assert_eq!(
+            relocated(Call26, 0x94000000, 0x401100, 0, 0x401000),
+            0x94000040
+        );
+        assert_eq!(
+            relocated(Jump26, 0x14000000, 0x401000, 0, 0x401008),
+            0x17fffffe
+        );
+        // The furthest forward, and just past it
+        assert_eq!(
+            relocated(Call26, 0x94000000, (1 << 27) - 4, 0, 0),
+            0x95ffffff
+        );
+        let mut bytes = 0x94000000u32.to_le_bytes();
+        assert!(AArch64.apply(Call26, &mut bytes, 0, 1 << 27, 0, 0).is_err());
+        assert!(AArch64
+            .apply(Call26, &mut bytes, 0, 0x401002, 0, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn data() {
+        let mut bytes = [0; 12];
+        AArch64
+            .apply(Aarch64Abs64, &mut bytes, 0, 0x412345, 3, 0)
+            .unwrap();
+        assert_eq!(bytes[..8], 0x412348u64.to_le_bytes());
+        let value = AArch64.apply(Aarch64Prel32, &mut bytes, 8, 0x400000, 0, 0x400010);
+        assert_eq!(value, Ok(-0x10));
+        assert_eq!(bytes[8..], (-0x10i32).to_le_bytes());
+        assert!(AArch64
+            .apply(Aarch64Abs32, &mut bytes, 8, 1 << 32, 0, 0)
+            .is_err());
+        assert!(AArch64
+            .apply(Aarch64Abs32, &mut bytes, 10, 0, 0, 0)
+            .is_err());
+        assert!(AArch64.apply(Pc32, &mut bytes, 0, 0, 0, 0).is_err());
+    }
+
+    #[test]
+    fn branches_to_undefined_weak_symbols_fall_through() {
+        let s = AArch64.undefined_weak(Call26, 0x40000000, 8);
+        assert_eq!(relocated(Call26, 0x94000000, s, 8, 0x40000000), 0x94000001);
+        assert_eq!(AArch64.undefined_weak(AdrPrelPgHi21, 0x40000000, 8), 0);
+    }
+
+    #[test]
+    fn plt_entry_loads_the_got_entry() {
+        // adrp x16, 0x4 pages; ldr x17, [x16, #0x18]; add x16, x16, #0x18; br x17
+        let entry = AArch64.plt_entry(0x400ff0, 0x404018).unwrap();
+        let words: Vec<u32> = entry
+            .chunks(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect();
+        assert_eq!(words, [0x90000030, 0xf9400e11, 0x91006210, 0xd61f0220]);
+        assert!(AArch64.plt_entry(0x400ff0, 0x40401c).is_err());
+    }
+}
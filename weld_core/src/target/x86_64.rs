@@ -0,0 +1,144 @@
+//! x86-64, as the System V psABI describes it.
+
+use super::{patch, Target};
+use elf::logical::RelocationType;
+
+// `jmp *disp32(%rip)`, before its displacement
+const JMP_INDIRECT: [u8; 2] = [0xff, 0x25];
+const PLT_ENTRY_SIZE: usize = 16;
+const INT3: u8 = 0xcc;
+
+#[derive(Debug)]
+pub struct X86_64;
+
+impl Target for X86_64 {
+    fn name(&self) -> &'static str {
+        "x86-64"
+    }
+
+    fn machine(&self) -> u16 {
+        elf::file::MACHINE_X86_64
+    }
+
+    fn flags(&self) -> u32 {
+        0x00000102
+    }
+
+    fn page_size(&self) -> u64 {
+        0x1000
+    }
+
+    fn image_base(&self) -> u64 {
+        0x400000
+    }
+
+    fn apply(
+        &self,
+        kind: RelocationType,
+        bytes: &mut [u8],
+        offset: u64,
+        s: u64,
+        a: i64,
+        p: u64,
+    ) -> Result<i64, String> {
+        let value = s.wrapping_add(a as u64);
+        match kind {
+            RelocationType::Abs64 => {
+                patch(bytes, offset, &value.to_le_bytes())?;
+                Ok(value as i64)
+            }
+            RelocationType::Pc64 => {
+                let relative = value.wrapping_sub(p);
+                patch(bytes, offset, &relative.to_le_bytes())?;
+                Ok(relative as i64)
+            }
+            RelocationType::Pc32 | RelocationType::Plt32 => {
+                let relative = i32::try_from(value.wrapping_sub(p) as i64)
+                    .map_err(|_| format!("{kind:?} relocation at offset {offset:#x} overflows"))?;
+                patch(bytes, offset, &relative.to_le_bytes())?;
+                Ok(relative.into())
+            }
+            RelocationType::Abs32 => {
+                let value = u32::try_from(value)
+                    .map_err(|_| format!("{kind:?} relocation at offset {offset:#x} overflows"))?;
+                patch(bytes, offset, &value.to_le_bytes())?;
+                Ok(value.into())
+            }
+            RelocationType::Abs32Signed => {
+                let value = i32::try_from(value as i64)
+                    .map_err(|_| format!("{kind:?} relocation at offset {offset:#x} overflows"))?;
+                patch(bytes, offset, &value.to_le_bytes())?;
+                Ok(value.into())
+            }
+            _ => Err(format!(
+                "unsupported relocation type {kind:?} at offset {offset:#x}"
+            )),
+        }
+    }
+
+    // The rest of the entry is never reached, so it traps
+    fn plt_entry(&self, address: u64, got_entry: u64) -> Result<Vec<u8>, String> {
+        let mut entry = JMP_INDIRECT.to_vec();
+        entry.extend_from_slice(&[0; 4]);
+        let next = address.wrapping_add(entry.len() as u64);
+        self.apply(RelocationType::Pc32, &mut entry, 2, got_entry, 0, next)?;
+        entry.resize(PLT_ENTRY_SIZE, INT3);
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pc_relative_call() {
+        // call rel32 at 0x401010; the displacement is at 0x401011
+        let mut bytes = [0xe8, 0, 0, 0, 0];
+        let value = X86_64.apply(RelocationType::Plt32, &mut bytes, 1, 0x401000, -4, 0x401011);
+        assert_eq!(value, Ok(-0x15));
+        assert_eq!(bytes[1..], (-0x15i32).to_le_bytes());
+    }
+
+    #[test]
+    fn absolute() {
+        let mut bytes = [0; 8];
+        X86_64
+            .apply(RelocationType::Abs64, &mut bytes, 0, 0x402000, 8, 0)
+            .unwrap();
+        assert_eq!(bytes, 0x402008u64.to_le_bytes());
+        X86_64
+            .apply(RelocationType::Abs32Signed, &mut bytes, 4, 0, -1, 0)
+            .unwrap();
+        assert_eq!(bytes[4..], [0xff; 4]);
+    }
+
+    #[test]
+    fn overflow_and_bounds_are_errors() {
+        let mut bytes = [0; 4];
+        assert!(X86_64
+            .apply(RelocationType::Abs32, &mut bytes, 0, 1 << 32, 0, 0)
+            .is_err());
+        assert!(X86_64
+            .apply(RelocationType::Pc32, &mut bytes, 0, 1 << 32, 0, 0)
+            .is_err());
+        assert!(X86_64
+            .apply(RelocationType::Abs32, &mut bytes, 1, 0, 0, 0)
+            .is_err());
+        assert!(X86_64
+            .apply(RelocationType::Abs32, &mut bytes, u64::MAX, 0, 0, 0)
+            .is_err());
+        assert!(X86_64
+            .apply(RelocationType::Copy, &mut bytes, 0, 0, 0, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn plt_entry_jumps_through_the_got() {
+        // jmp *0x2ffa(%rip), then int3 padding
+        let entry = X86_64.plt_entry(0x401000, 0x404000).unwrap();
+        assert_eq!(entry[..6], [0xff, 0x25, 0xfa, 0x2f, 0, 0]);
+        assert_eq!(entry[6..], [INT3; 10]);
+        assert!(X86_64.plt_entry(0x401000, 1 << 40).is_err());
+    }
+}